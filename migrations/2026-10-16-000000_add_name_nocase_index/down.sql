@@ -0,0 +1 @@
+DROP INDEX taxonomy_name_nocase_idx;
@@ -0,0 +1 @@
+CREATE INDEX taxonomy_name_nocase_idx ON taxonomy(name COLLATE NOCASE);
@@ -0,0 +1,15 @@
+CREATE TABLE taxonomy_new (
+    id INTEGER PRIMARY KEY,
+    ancestry TEXT,
+    name TEXT NOT NULL UNIQUE,
+    rank TEXT
+);
+
+INSERT INTO taxonomy_new (id, ancestry, name, rank)
+    SELECT id, ancestry, name, rank FROM taxonomy;
+
+DROP TABLE taxonomy;
+ALTER TABLE taxonomy_new RENAME TO taxonomy;
+
+CREATE UNIQUE INDEX taxonomy_name_idx ON taxonomy(name);
+CREATE INDEX taxonomy_name_nocase_idx ON taxonomy(name COLLATE NOCASE);
@@ -0,0 +1,2 @@
+DROP INDEX taxonomy_parent_id_idx;
+ALTER TABLE taxonomy DROP COLUMN parent_id;
@@ -0,0 +1,12 @@
+ALTER TABLE taxonomy ADD COLUMN parent_id INTEGER;
+
+-- the parent is the second to last taxid in the ancestry (e.g. 12333 in 1/10239/12333/12340),
+-- found by stripping the trailing taxids with rtrim. the root taxon has no ancestry and no parent
+UPDATE taxonomy SET parent_id = CAST(
+    substr(
+        substr(rtrim(ancestry, '0123456789'), 1, length(rtrim(ancestry, '0123456789')) - 1),
+        length(rtrim(substr(rtrim(ancestry, '0123456789'), 1, length(rtrim(ancestry, '0123456789')) - 1), '0123456789')) + 1
+    ) AS INTEGER)
+    WHERE ancestry IS NOT NULL;
+
+CREATE INDEX taxonomy_parent_id_idx ON taxonomy(parent_id);
@@ -0,0 +1 @@
+UPDATE taxonomy SET ancestry = CASE WHEN parent_id IS NULL THEN NULL ELSE trim(ancestry, '/') END;
@@ -0,0 +1,4 @@
+-- surround the ancestry with '/' (e.g. /1/10239/12333/12340/) so that every taxid in it is written
+-- as /taxid/, which a LIKE '%/taxid/%' pattern can match without matching part of another taxid.
+-- the root taxon, which had no ancestry, gets /1/
+UPDATE taxonomy SET ancestry = '/' || COALESCE(ancestry, id) || '/';
@@ -0,0 +1 @@
+ALTER TABLE taxonomy DROP COLUMN common_name;
@@ -0,0 +1,2 @@
+-- the GenBank common name of a taxon (e.g. eubacteria for Bacteria), NULL if it has none
+ALTER TABLE taxonomy ADD COLUMN common_name TEXT;
@@ -9,70 +9,120 @@ extern crate ncbitaxonomy;
 use std::fs::{File, create_dir};
 use std::io;
 use std::io::{Read, Write, BufRead, BufWriter, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::vec::Vec;
-use std::fmt;
 use std::collections::HashMap;
 
+use bio::io::fasta;
 use flate2::Compression;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use seq_io::fastq::Record;
 use ncbitaxonomy::{NcbiTaxonomy, NcbiSqliteTaxonomy};
+use ncbitaxonomy::classification::{ClassifierTool, is_kraken2_report_line, parse_classification_line};
 
-enum FilterTool {
-    Centrifuge,
-    Kraken2
+// paired-end reads are often named with a /1 or /2 suffix to distinguish the mates,
+// strip this so that both mates (and the classification of the pair) share an ID
+fn mate_id(read_id: &str) -> &str {
+    if read_id.ends_with("/1") || read_id.ends_with("/2") {
+        &read_id[..read_id.len() - 2]
+    } else {
+        read_id
+    }
 }
 
-impl fmt::Display for FilterTool {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            FilterTool::Centrifuge => write!(f, "FilterTool::Centrifuge"),
-            FilterTool::Kraken2 => write!(f, "FilterTool::Kraken2")
-        }
-    }
+const FASTA_EXTENSIONS: [&str; 4] = [".fa", ".fasta", ".fna", ".fas"];
+const FASTQ_EXTENSIONS: [&str; 2] = [".fastq", ".fq"];
+
+// FASTA files (e.g. classified contigs) are recognised by their extension, anything else is read as FASTQ
+fn is_fasta(filename: &Path) -> bool {
+    let filename = filename.to_str().unwrap();
+    let filename = filename.strip_suffix(".gz").unwrap_or(filename);
+    FASTA_EXTENSIONS.iter().any(|extension| filename.ends_with(extension))
 }
 
-fn filter_fastq(fastq_filename: &Path, tax_report_filename: &str,
-                taxonomy: &dyn NcbiTaxonomy,
-                output_dir: &Path, filter_tool: &FilterTool, ancestor_id: i32) {
-    if !output_dir.exists() {
-        create_dir(output_dir).unwrap_or_else(|_| panic!("Failed to create output dir {}", output_dir.display()));
-    }
-    let fastq_file = File::open(&fastq_filename).unwrap_or_else(|_| panic!("Failed to open input FASTQ file ({})", fastq_filename.display()));
-    let fastq_decoder: Box<dyn Read> = if fastq_filename.to_str().unwrap().ends_with(".gz") {
-        Box::new(GzDecoder::new(fastq_file))
+// the output file for an input file is in output_dir, with a label (e.g. filtered) inserted before
+// the sequence file extension (and .gz), e.g. sample.R1.fastq.gz becomes sample.R1.filtered.fastq.gz.
+// names without a known extension get the label added at the end (but before .gz)
+fn output_filename(input_filename: &Path, output_dir: &Path, label: &str) -> PathBuf {
+    let filename = input_filename.file_name().and_then(|s| s.to_str()).unwrap();
+    let (filename, gz_extension) = match filename.strip_suffix(".gz") {
+        Some(filename) => (filename, ".gz"),
+        None => (filename, "")
+    };
+    let extension = FASTQ_EXTENSIONS.iter().chain(FASTA_EXTENSIONS.iter())
+        .find(|extension| filename.len() > extension.len() && filename.ends_with(*extension))
+        .unwrap_or(&"");
+    let stem = &filename[..filename.len() - extension.len()];
+    output_dir.join(format!("{}.{}{}{}", stem, label, extension, gz_extension))
+}
+
+fn open_input(filename: &Path) -> BufReader<Box<dyn Read>> {
+    let file = File::open(filename).unwrap_or_else(|_| panic!("Failed to open input file ({})", filename.display()));
+    let decoder: Box<dyn Read> = if filename.to_str().unwrap().ends_with(".gz") {
+        Box::new(GzDecoder::new(file))
     } else {
-        Box::new(fastq_file)
+        Box::new(file)
+    };
+    BufReader::new(decoder)
+}
+
+fn open_fastq(fastq_filename: &Path) -> seq_io::fastq::Reader<BufReader<Box<dyn Read>>> {
+    seq_io::fastq::Reader::new(open_input(fastq_filename))
+}
+
+fn create_output(fastq_filename: &Path, output_dir: &Path, label: &str) -> BufWriter<Box<dyn Write>> {
+    let output_filename = output_filename(fastq_filename, output_dir, label);
+    let output_file = File::create(&output_filename).unwrap_or_else(|_| panic!("Failed to create output file: {}", output_filename.display()));
+    let output_encoder: Box<dyn Write> = if output_filename.to_str().unwrap().ends_with(".gz") {
+        Box::new(GzEncoder::new(output_file, Compression::default()))
+    } else {
+        Box::new(output_file)
     };
-    let mut fastq_reader = seq_io::fastq::Reader::new(BufReader::new(fastq_decoder));
+    BufWriter::new(output_encoder)
+}
 
+// decide which reads to keep from the classifications in the report: a read is kept if
+// its score in the returned map is > 0. when paired is true the mates of a pair share an
+// entry so that the pair is kept or dropped as a whole
+fn classify_reads(tax_report_filename: &str, taxonomy: &dyn NcbiTaxonomy,
+                  filter_tool: &ClassifierTool, ancestor_id: i32, paired: bool) -> HashMap<String, i32> {
     let tax_report_file = File::open(tax_report_filename).unwrap_or_else(|_| panic!("Failed to open input Centrifuge file ({})", tax_report_filename));
     let tax_report_reader = io::BufReader::new(tax_report_file);
 
-    let mut read_valid: HashMap<String, i32> = HashMap::new();
+    let mut classifications = Vec::new();
     for line in tax_report_reader.lines() {
-        let line = line.expect("Unable to read line from centrifuge file");
-        let fields = line.split('\t').collect::<Vec<&str>>();
+        let line = line.expect("Unable to read line from classification report");
+        if *filter_tool == ClassifierTool::Kraken2 && is_kraken2_report_line(&line) {
+            // the summary report has no read IDs, so reads cannot be matched to a taxon with it
+            eprintln!("{} is a Kraken2 report (written with --report), filtering needs the per-read output (written with --output)", tax_report_filename);
+            process::exit(1);
+        }
+        if let Some(classification) = parse_classification_line(*filter_tool, &line).unwrap_or_else(|e| panic!("{}", e)) {
+            classifications.push(classification);
+        }  // else this is a header line
+    }
+
+    // look up all the taxids at once instead of querying the database for each line
+    let mut taxids: Vec<i32> = classifications.iter().filter_map(|classification| classification.taxid).collect();
+    taxids.sort_unstable();
+    taxids.dedup();
+    let is_descendant = taxonomy.are_descendants_taxid(&taxids, ancestor_id);
+
+    let mut read_valid: HashMap<String, i32> = HashMap::new();
+    for classification in classifications {
+        let id = if paired { mate_id(&classification.read_id).to_string() } else { classification.read_id };
 
         match filter_tool {
-            FilterTool::Centrifuge => {
-                if line.starts_with("readID") {
-                    // skip the header
-                    continue;
-                }
-                let id = fields[0].to_owned();
-                let score = fields[3].parse::<i32>().unwrap_or_else(|_| panic!("Failed to read score from ({})", fields[3]));
+            ClassifierTool::Centrifuge => {
+                let score = classification.score;
                 let current_score = match read_valid.get(&id) {
                     Some(value) => *value,
                     None => 0
                 };
                 if score >= current_score {
-                    let taxid = fields[2].parse::<i32>().unwrap();
-
-                    if taxonomy.is_descendant_taxid(taxid, ancestor_id) {
+                    if classification.taxid.is_some_and(|taxid| is_descendant[&taxid]) {
                         read_valid.insert(id, score);
                     } else if score > current_score {
                         // only reset this to zero if this non-descendant taxid is a better fit
@@ -80,84 +130,172 @@ fn filter_fastq(fastq_filename: &Path, tax_report_filename: &str,
                     }
                 }
             },
-            FilterTool::Kraken2 => {
+            ClassifierTool::Kraken2 => {
                 // kraken2 adds an entry each time it sees a read, so for paired end
                 // reads there are 2 entries which might not agree with each other
                 //
                 // this code treats read as invalid if it *ever* shows up as invalid,
                 // i.e. if either read in a pair is invalid
-                let is_classified = fields[0];
-                let id = fields[1].to_owned();
-                if is_classified == "U" {
-                    // this is an unclassified read
-                    read_valid.insert(id, 0);
-                } else {
-                    // is_classified is C
-                    if !read_valid.contains_key(&id) || *(read_valid.get(&id).unwrap()) != 0 {
-                        // only insert key if it is either new or was not previously noted
-                        // as unclassified or not a descendant
-
-                        let name_or_taxid = fields[2];
-                        let taxid = if name_or_taxid.contains("(taxid") {
-                            // taxon name output format
-                            let name_parts = name_or_taxid.split(' ').collect::<Vec<&str>>();
-                            let last_part = name_parts.last().unwrap();
-                            let taxid_part: &str = last_part.split(')').collect::<Vec<&str>>()[0];
-                            taxid_part.parse::<i32>().unwrap()
-                        } else {
-                            name_or_taxid.parse::<i32>().unwrap()
-                        };
-
-                        if taxonomy.is_descendant_taxid(taxid, ancestor_id) {
-                            read_valid.insert(id, 1000);  // make up a score for kraken2
-                        } else  {
-                            read_valid.insert(id, 0);
+                match classification.taxid {
+                    None => {
+                        // this is an unclassified read
+                        read_valid.insert(id, 0);
+                    },
+                    Some(taxid) => {
+                        if !read_valid.contains_key(&id) || *(read_valid.get(&id).unwrap()) != 0 {
+                            // only insert key if it is either new or was not previously noted
+                            // as unclassified or not a descendant
+                            if is_descendant[&taxid] {
+                                read_valid.insert(id, classification.score);
+                            } else  {
+                                read_valid.insert(id, 0);
+                            }
                         }
-
                     }
                 }
             },
         };
     }
+    read_valid
+}
+
+// filter a FASTQ (or FASTA) file, read_valid is the result of classify_reads with paired set to false.
+// if write_rejected is true the records that are not retained are written to a .rejected file
+fn filter_fastq(fastq_filename: &Path, read_valid: &HashMap<String, i32>,
+                output_dir: &Path, dry_run: bool, write_rejected: bool) {
+    if !dry_run && !output_dir.exists() {
+        create_dir(output_dir).unwrap_or_else(|_| panic!("Failed to create output dir {}", output_dir.display()));
+    }
+    let is_valid = |id: &str| read_valid.get(id).is_some_and(|score| *score > 0);
 
     let mut valid_records = 0;
     let mut total_records = 0;
-    let filename_parts: Vec<&str>= fastq_filename.file_name().and_then(|s| s.to_str()).unwrap().split('.').collect();
-    let output_filename = output_dir.to_str().unwrap().to_owned() + "/" + &filename_parts[0].to_owned() + ".filtered." + &filename_parts[1..].join(".");
-    let output_file = File::create(&output_filename).unwrap_or_else(|_| panic!("Failed to create output file: {}", output_filename));
-    let output_encoder: Box<dyn Write> = if output_filename.ends_with(".gz") {
-        Box::new(GzEncoder::new(output_file, Compression::default()))
+    // in a dry run no output file is created, records are only counted
+    let mut output_writer = if dry_run {
+        None
     } else {
-        Box::new(output_file)
+        Some(create_output(fastq_filename, output_dir, "filtered"))
+    };
+    let mut rejected_writer = if dry_run || !write_rejected {
+        None
+    } else {
+        Some(create_output(fastq_filename, output_dir, "rejected"))
+    };
+    if is_fasta(fastq_filename) {
+        // the output is written in the same format as the input
+        let mut fasta_writer = output_writer.map(fasta::Writer::new);
+        let mut rejected_fasta_writer = rejected_writer.map(fasta::Writer::new);
+        for result in fasta::Reader::new(open_input(fastq_filename)).records() {
+            let record = result.expect("Error reading record");
+            total_records += 1;
+            if is_valid(record.id()) {
+                if let Some(ref mut fasta_writer) = fasta_writer {
+                    fasta_writer.write_record(&record).unwrap_or_else(|_| panic!("Failed to write record to output file"));
+                }
+                valid_records += 1;
+            } else if let Some(ref mut rejected_fasta_writer) = rejected_fasta_writer {
+                rejected_fasta_writer.write_record(&record).unwrap_or_else(|_| panic!("Failed to write record to rejected file"));
+            }
+        }
+    } else {
+        let mut fastq_reader = open_fastq(fastq_filename);
+        while let Some(result) = fastq_reader.next() {
+            let record = result.expect("Error reading record");
+            total_records += 1;
+            if is_valid(record.id().unwrap()) {
+                if let Some(ref mut output_writer) = output_writer {
+                    record.write_unchanged(output_writer).unwrap_or_else(|_| panic!("Failed to write record to output file"));
+                }
+                valid_records += 1;
+            } else if let Some(ref mut rejected_writer) = rejected_writer {
+                record.write_unchanged(rejected_writer).unwrap_or_else(|_| panic!("Failed to write record to rejected file"));
+            }
+        }
+    }
+    if dry_run {
+        eprintln!("{} records would be written out of {} total records", valid_records, total_records);
+        println!("{}\t{}\t{}", fastq_filename.display(), valid_records, total_records);
+    } else {
+        eprintln!("{} records written out of {} total records", valid_records, total_records);
+    }
+}
+
+// filter the R1 and R2 files of paired-end reads together, so that both mates of a pair are
+// either kept or dropped (based on the classification of the pair) and the output stays paired.
+// read_valid is the result of classify_reads with paired set to true
+fn filter_fastq_pair(r1_filename: &Path, r2_filename: &Path, read_valid: &HashMap<String, i32>,
+                     output_dir: &Path, dry_run: bool, write_rejected: bool) {
+    if !dry_run && !output_dir.exists() {
+        create_dir(output_dir).unwrap_or_else(|_| panic!("Failed to create output dir {}", output_dir.display()));
+    }
+    let mut r1_reader = open_fastq(r1_filename);
+    let mut r2_reader = open_fastq(r2_filename);
+
+    let mut valid_pairs = 0;
+    let mut total_pairs = 0;
+    // in a dry run no output files are created, pairs are only counted
+    let mut output_writers = if dry_run {
+        None
+    } else {
+        Some((create_output(r1_filename, output_dir, "filtered"), create_output(r2_filename, output_dir, "filtered")))
+    };
+    let mut rejected_writers = if dry_run || !write_rejected {
+        None
+    } else {
+        Some((create_output(r1_filename, output_dir, "rejected"), create_output(r2_filename, output_dir, "rejected")))
     };
-    let mut output_writer = BufWriter::new(output_encoder );
-    while let Some(result) = fastq_reader.next() {
-        let record = result.expect("Error reading record");
-        let id = record.id().unwrap();
-        total_records += 1;
-        if read_valid.contains_key(id) && *read_valid.get(id).unwrap() > 0 {
-            record.write_unchanged(&mut output_writer).unwrap_or_else(|_| panic!("Failed to write record to output file"));
-            valid_records += 1;
+    loop {
+        let (r1_record, r2_record) = match (r1_reader.next(), r2_reader.next()) {
+            (Some(r1_result), Some(r2_result)) => (r1_result.expect("Error reading record"), r2_result.expect("Error reading record")),
+            (None, None) => break,
+            _ => {
+                eprintln!("{} and {} contain different numbers of reads", r1_filename.display(), r2_filename.display());
+                process::exit(1);
+            }
+        };
+        let id = mate_id(r1_record.id().unwrap());
+        let r2_id = mate_id(r2_record.id().unwrap());
+        if id != r2_id {
+            eprintln!("Reads {} and {} are not mates, are {} and {} paired files?", id, r2_id, r1_filename.display(), r2_filename.display());
+            process::exit(1);
+        }
+        total_pairs += 1;
+        if read_valid.get(id).is_some_and(|score| *score > 0) {
+            if let Some((ref mut r1_writer, ref mut r2_writer)) = output_writers {
+                r1_record.write_unchanged(r1_writer).unwrap_or_else(|_| panic!("Failed to write record to output file"));
+                r2_record.write_unchanged(r2_writer).unwrap_or_else(|_| panic!("Failed to write record to output file"));
+            }
+            valid_pairs += 1;
+        } else if let Some((ref mut r1_writer, ref mut r2_writer)) = rejected_writers {
+            r1_record.write_unchanged(r1_writer).unwrap_or_else(|_| panic!("Failed to write record to rejected file"));
+            r2_record.write_unchanged(r2_writer).unwrap_or_else(|_| panic!("Failed to write record to rejected file"));
         }
     }
-    eprintln!("{} records written out of {} total records", valid_records, total_records);
+    if dry_run {
+        eprintln!("{} read pairs would be written out of {} total read pairs", valid_pairs, total_pairs);
+        println!("{},{}\t{}\t{}", r1_filename.display(), r2_filename.display(), valid_pairs, total_pairs);
+    } else {
+        eprintln!("{} read pairs written out of {} total read pairs", valid_pairs, total_pairs);
+    }
 }
 
 pub fn main() {
-    // TODO: write test suite
     let matches = clap_app!(taxonomy_filter_fastq =>
         (version: ncbitaxonomy::VERSION)
         (author: "Peter van Heusden <pvh@sanbi.axc.za>")
-        (about: "Filter FASTQ files whose reads have been classified by Centrifuge or Kraken2, only retaining reads in taxa descending from given ancestor")
+        (about: "Filter FASTQ (or FASTA) files whose reads have been classified by Centrifuge or Kraken2, only retaining reads in taxa descending from given ancestor")
         (@arg TAXDB_URL: -d --db +takes_value "URL for SQLite taxonomy database")
         (@arg ANCESTOR_ID: -A --ancestor_taxid +takes_value +required "Name of ancestor to use as ancestor filter")
         (@group filter_tool +required =>
             (@arg centrifuge: -C --centrifuge !required "Filter using report from Centrifuge")
             (@arg kraken2: -K --kraken2 !required "Filter using report from Kraken2")
         )
-        (@arg OUTPUT_DIR: -d --output_dir "Directory to deposited filtered output files in")
+        (@arg OUTPUT_DIR: -o --output_dir +takes_value "Directory to deposit filtered output files in")
+        (@arg PAIRED: -P --paired "Treat the input files as pairs of R1 and R2 files of paired-end reads, keeping or dropping both reads of a pair")
+        (@arg REJECTED: --rejected "Also write the records that are not retained, to files named like the output files with .rejected instead of .filtered")
+        (@arg DRY_RUN: --dry_run visible_alias("count_only") "Count the records that would be retained without writing any output files, printing the counts for each input file as TSV")
         (@arg TAXONOMY_REPORT_FILENAME: -F --tax_report_filename +takes_value  +required "Output from Kraken2 (default) or Centrifuge")
-        (@arg INPUT_FASTQ: ... +required "FASTA file with RefSeq sequences")
+        (@arg INPUT_FASTQ: ... +required "FASTQ files to filter (or FASTA files, with a .fa, .fasta, .fna or .fas extension)")
         ).get_matches();
 
     let output_dir = match matches.value_of("OUTPUT_DIR") {
@@ -166,9 +304,9 @@ pub fn main() {
     };
 
     let filter_tool = if matches.is_present("centrifuge") {
-        FilterTool::Centrifuge
+        ClassifierTool::Centrifuge
     } else {
-        FilterTool::Kraken2  // default to kraken2
+        ClassifierTool::Kraken2  // default to kraken2
     };
     eprintln!("filter tool {}", filter_tool);
 
@@ -177,6 +315,13 @@ pub fn main() {
 
     let tax_report_filename = matches.value_of("TAXONOMY_REPORT_FILENAME").unwrap();
 
+    let dry_run = matches.is_present("DRY_RUN");
+    let write_rejected = matches.is_present("REJECTED");
+    if dry_run {
+        // counts for read pairs are reported with the two filenames joined by a comma
+        println!("filename\tretained\ttotal");
+    }
+
     let taxdb_url = if matches.is_present("TAXDB_URL") { Some(matches.value_of("TAXDB_URL").unwrap()) } else { None };
 
     let taxonomy = NcbiSqliteTaxonomy::new(taxdb_url);
@@ -186,10 +331,68 @@ pub fn main() {
     }
 
     let input_files: Vec<&str> = matches.values_of("INPUT_FASTQ").unwrap().collect();
+    if matches.is_present("PAIRED") {
+        if let Some(input_file) = input_files.iter().find(|input_file| is_fasta(Path::new(input_file))) {
+            eprintln!("Paired-end mode only supports FASTQ files, {} is a FASTA file", input_file);
+            process::exit(1);
+        }
+        if !input_files.len().is_multiple_of(2) {
+            eprintln!("Paired-end mode needs an R2 file for each R1 file, got {} input files", input_files.len());
+            process::exit(1);
+        }
+        let read_valid = classify_reads(tax_report_filename, &taxonomy, &filter_tool, ancestor_id, true);
+        for pair in input_files.chunks(2) {
+            let r1_path = Path::new(pair[0]);
+            let r2_path = Path::new(pair[1]);
+            eprintln!("processing {} and {}", r1_path.file_name().and_then(|s| s.to_str()).unwrap(),
+                      r2_path.file_name().and_then(|s| s.to_str()).unwrap());
+            filter_fastq_pair(r1_path, r2_path, &read_valid, output_dir, dry_run, write_rejected);
+        }
+        return
+    }
+    let read_valid = classify_reads(tax_report_filename, &taxonomy, &filter_tool, ancestor_id, false);
     for input_file in input_files.iter() {
         let input_file_path = Path::new(input_file);
         eprintln!("processing {}", input_file_path.file_name().and_then(|s| s.to_str()).unwrap());
-        filter_fastq(input_file_path, tax_report_filename, &taxonomy,
-                     output_dir, &filter_tool, ancestor_id);
+        filter_fastq(input_file_path, &read_valid, output_dir, dry_run, write_rejected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_fasta, mate_id, output_filename};
+    use std::path::Path;
+
+    #[test]
+    fn mate_id_strips_suffix() {
+        assert_eq!(mate_id("read1/1"), "read1");
+        assert_eq!(mate_id("read1/2"), "read1");
+        assert_eq!(mate_id("read1"), "read1");
+        assert_eq!(mate_id("read1/3"), "read1/3");
+    }
+
+    #[test]
+    fn fasta_extension() {
+        assert!(is_fasta(Path::new("contigs.fasta")));
+        assert!(is_fasta(Path::new("dir/contigs.fa.gz")));
+        assert!(is_fasta(Path::new("contigs.fna")));
+        assert!(!is_fasta(Path::new("reads.fastq")));
+        assert!(!is_fasta(Path::new("reads.fq.gz")));
+    }
+
+    #[test]
+    fn output_filenames() {
+        let output_dir = Path::new("out");
+        assert_eq!(output_filename(Path::new("reads.fastq"), output_dir, "filtered"), Path::new("out/reads.filtered.fastq"));
+        assert_eq!(output_filename(Path::new("in/sample.R1.fastq.gz"), output_dir, "filtered"), Path::new("out/sample.R1.filtered.fastq.gz"));
+        assert_eq!(output_filename(Path::new("sample.R2.fq.gz"), output_dir, "filtered"), Path::new("out/sample.R2.filtered.fq.gz"));
+        assert_eq!(output_filename(Path::new("contigs.fasta"), output_dir, "filtered"), Path::new("out/contigs.filtered.fasta"));
+        assert_eq!(output_filename(Path::new("reads"), output_dir, "filtered"), Path::new("out/reads.filtered"));
+        assert_eq!(output_filename(Path::new("reads.gz"), output_dir, "filtered"), Path::new("out/reads.filtered.gz"));
+        assert_eq!(output_filename(Path::new("sample.R1.fastq.gz"), output_dir, "rejected"), Path::new("out/sample.R1.rejected.fastq.gz"));
+        // the output is detected as gzipped and in the same format as the input
+        assert!(output_filename(Path::new("contigs.fa.gz"), output_dir, "filtered").to_str().unwrap().ends_with(".gz"));
+        assert!(is_fasta(&output_filename(Path::new("contigs.fa.gz"), output_dir, "filtered")));
+        assert!(!is_fasta(&output_filename(Path::new("sample.R1.fastq.gz"), output_dir, "filtered")));
     }
 }
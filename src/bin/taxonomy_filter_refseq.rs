@@ -4,6 +4,7 @@ extern crate bio;
 extern crate ncbitaxonomy;
 
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::process;
@@ -33,6 +34,42 @@ fn wrap(seq: TextSlice, width: usize) -> Vec<u8> {
     wrapped_seq_vec
 }
 
+// the species name of a RefSeq record, which its description ends with in square brackets. the
+// name itself can contain brackets (e.g. [[Clostridium] scindens]) so this finds the '[' matching
+// the last ']', returning None if the description has no (balanced) brackets
+fn species_name(description: &str) -> Option<&str> {
+    let species_end = description.rfind(']')?;
+    let mut depth = 0;
+    for (i, c) in description[..species_end].char_indices().rev() {
+        match c {
+            ']' => depth += 1,
+            '[' if depth == 0 => return Some(&description[(i+1)..species_end]),
+            '[' => depth -= 1,
+            _ => ()
+        }
+    }
+    None
+}
+
+// find which of the species in a FASTA file descend from the ancestor. this reads the file
+// and queries the taxonomy once for all the species rather than once for each record
+fn accepted_species(input_fasta_filename: &str, taxonomy: &dyn NcbiTaxonomy, ancestor_id: i32) -> HashMap<String, bool> {
+    let input_fasta = File::open(input_fasta_filename).unwrap_or_else(|_| panic!("Failed to open input FASTA file ({})", input_fasta_filename));
+    let mut species_names = HashSet::new();
+    for record in fasta::Reader::new(input_fasta).records() {
+        let record = record.unwrap();
+        if let Some(name) = species_name(record.desc().unwrap_or("unknown")) {
+            species_names.insert(name.to_string());
+        }
+    }
+
+    let species_names: Vec<&str> = species_names.iter().map(|name| name.as_str()).collect();
+    let species_ids = taxonomy.get_ids_by_names(&species_names);
+    let taxids: Vec<i32> = species_ids.values().cloned().collect();
+    let is_descendant = taxonomy.are_descendants_taxid(&taxids, ancestor_id);
+    species_ids.into_iter().map(|(name, taxid)| (name, is_descendant[&taxid])).collect()
+}
+
 pub fn main() {
     // TODO: use functions, write testing suite
     let matches = clap_app!(taxonomy_filter_refseq =>
@@ -43,19 +80,16 @@ pub fn main() {
         (@arg NO_PREDICTED: --no_predicted "Don't accept computationally predicted RNAs and proteins (XM_, XR_ and XP_ accessions)")
         (@arg NO_CURATED: --no_curated "Don't accept curated RNAs and proteins (NM_, NR_ and NP_ accessions)")
         (@arg INPUT_FASTA: +required "FASTA file with RefSeq sequences")
-        (@arg ANCESTOR_NAME: +required "Name of ancestor to use as ancestor filter")
-        (@arg OUTPUT_FASTA: "Output FASTA filename (or stdout if omitted)")
+        (@group ancestor +required =>
+            (@arg ANCESTOR_NAME: "Name of ancestor to use as ancestor filter")
+            (@arg ANCESTOR_ID: -A --ancestor_taxid +takes_value "Taxonomy ID of ancestor to use as ancestor filter (instead of ANCESTOR_NAME)")
+        )
+        (@arg OUTPUT_FASTA: -o --output +takes_value "Output FASTA filename (or stdout if omitted)")
         ).get_matches();
 
-    let no_predicted = match matches.occurrences_of("NO_PREDICTED") {
-        0 => false,
-        _ => true
-    };
+    let no_predicted = matches.occurrences_of("NO_PREDICTED") != 0;
 
-    let no_curated = match matches.occurrences_of("NO_CURATED") {
-        0 => false,
-        _ => true
-    };
+    let no_curated = matches.occurrences_of("NO_CURATED") != 0;
 
     let input_fasta_filename = matches.value_of("INPUT_FASTA").unwrap();
     let input_fasta = File::open(input_fasta_filename).unwrap_or_else(|_| panic!("Failed to open input FASTA file ({})", input_fasta_filename));
@@ -74,25 +108,67 @@ pub fn main() {
 
     let mut output_fasta = fasta::Writer::new(output_file);
 
-    let ancestor_name = matches.value_of("ANCESTOR_NAME").unwrap();
+    // the ancestor is given either by name or by taxid (clap ensures that exactly one is given)
+    let ancestor_id = match matches.value_of("ANCESTOR_ID") {
+        Some(ancestor_id_str) => {
+            let ancestor_id = ancestor_id_str.parse::<i32>().unwrap_or_else(|_| panic!("Failed to interpret ({}) as a taxonomy ID", ancestor_id_str));
+            if !taxonomy.contains_id(ancestor_id) {
+                eprintln!("Taxonomy does not contain an ancestor with taxid {}", ancestor_id);
+                process::exit(1);
+            }
+            ancestor_id
+        },
+        None => {
+            let ancestor_name = matches.value_of("ANCESTOR_NAME").unwrap();
+            match taxonomy.get_id_by_name(ancestor_name) {
+                Some(ancestor_id) => ancestor_id,
+                None => {
+                    eprintln!("Taxonomy does not contain an ancestor named {}", ancestor_name);
+                    process::exit(1);
+                }
+            }
+        }
+    };
 
-    if !taxonomy.contains_name(ancestor_name) {
-        eprintln!("Taxonomy does not contain an ancestor named {}", ancestor_name);
-        process::exit(1);
-    }
+    let accepted = accepted_species(input_fasta_filename, &taxonomy, ancestor_id);
 
     for record in input_fasta_reader.records() {
         let record = record.unwrap();
-        let description = match record.desc() {
-            Some(desc) => desc,
-            None => "unknown"
-        };
+        let description = record.desc().unwrap_or("unknown");
         let division = record.id().as_bytes()[0];
-        let species_start = description.find('[').unwrap_or_else(|| panic!("[ missing in description ({})", description));
-        let species_end = description.rfind(']').unwrap_or_else(|| panic!("] missing in description ({})", description));
-        let species_name = &description[(species_start+1)..species_end];
-        if !(no_predicted && (division == b'X' || division == b'Y')) && !(no_curated && (division == b'N' || division == b'A' || division == b'W')) && taxonomy.contains_name(species_name) && taxonomy.is_descendant(species_name, ancestor_name) {
+        let species_name = match species_name(description) {
+            Some(species_name) => species_name,
+            None => {
+                eprintln!("Skipping record {}: no species name in description ({})", record.id(), description);
+                continue;
+            }
+        };
+        if !(no_predicted && (division == b'X' || division == b'Y')) && !(no_curated && (division == b'N' || division == b'A' || division == b'W')) && accepted.get(species_name) == Some(&true) {
             output_fasta.write(record.id(), record.desc(), wrap(record.seq(), 80).as_slice()).unwrap();
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::species_name;
+
+    #[test]
+    fn species_name_in_brackets() {
+        assert_eq!(species_name("hypothetical protein [Homo sapiens]"), Some("Homo sapiens"));
+        assert_eq!(species_name("capsid protein [Tobacco mosaic virus] partial"), Some("Tobacco mosaic virus"));
+    }
+
+    #[test]
+    fn species_name_nested_brackets() {
+        assert_eq!(species_name("MULTISPECIES: protein [[Clostridium] scindens]"), Some("[Clostridium] scindens"));
+        assert_eq!(species_name("protein [alpha] [[Eubacterium] rectale]"), Some("[Eubacterium] rectale"));
+    }
+
+    #[test]
+    fn species_name_missing_brackets() {
+        assert_eq!(species_name("hypothetical protein"), None);
+        assert_eq!(species_name("hypothetical protein [Homo sapiens"), None);
+        assert_eq!(species_name("hypothetical protein Homo sapiens]"), None);
+    }
+}
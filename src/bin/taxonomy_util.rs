@@ -2,9 +2,13 @@
 extern crate clap;
 extern crate ncbitaxonomy;
 
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
 use std::process;
-use ncbitaxonomy::{NcbiTaxonomy, NcbiSqliteTaxonomy};
+use ncbitaxonomy::{NcbiTaxonomy, NcbiSqliteTaxonomy, NcbiFileTaxonomy, NcbiFileTaxonomyBuilder, NcbiTaxonomyError, TaxonomyIssue, STANDARD_RANKS};
+use ncbitaxonomy::classification::{ClassifierTool, ClassificationSummary, read_classifications};
 use std::process::exit;
 
 fn common_ancestor_distance(taxonomy: &dyn NcbiTaxonomy, name1: &str, name2: &str, only_canonical: bool) {
@@ -19,43 +23,172 @@ fn common_ancestor_distance(taxonomy: &dyn NcbiTaxonomy, name1: &str, name2: &st
     }
 }
 
+// the taxid of a taxon given on the command line either by name or (if is_taxid) by taxid,
+// exiting if the taxonomy does not contain it
+fn taxid_for_arg(taxonomy: &dyn NcbiTaxonomy, value: &str, is_taxid: bool) -> i32 {
+    let taxid = if is_taxid {
+        value.parse::<i32>().ok().filter(|taxid| taxonomy.contains_id(*taxid))
+    } else {
+        taxonomy.get_id_by_name(value)
+    };
+    taxid.unwrap_or_else(|| {
+        eprintln!("{} not found in taxonomy", value);
+        process::exit(1);
+    })
+}
+
+// how often (in lines of nodes.dmp and names.dmp) to update the progress of loading the taxonomy
+const PROGRESS_LINES: usize = 100_000;
+
+// load the taxonomy from the .dmp files in the TAXONOMY_DIR of a subcommand, if show_progress
+// counting the lines read on stderr
+fn load_file_taxonomy(sub_m: &ArgMatches, show_progress: bool) -> NcbiFileTaxonomy {
+    let ncbi_taxonomy_path = Path::new(sub_m.value_of("TAXONOMY_DIR").unwrap());
+
+    let tax_prefix = sub_m.value_of("TAXONOMY_FILENAME_PREFIX").unwrap_or("");
+
+    eprintln!("loading taxonomy");
+    let builder = NcbiFileTaxonomyBuilder::new(ncbi_taxonomy_path).prefix(tax_prefix);
+    let result = if show_progress {
+        let result = builder.build_with_progress(PROGRESS_LINES, |lines| eprint!("\r{} lines read", lines));
+        eprintln!();
+        result
+    } else {
+        builder.build()
+    };
+    match result {
+        Ok(taxonomy) => {
+            eprintln!("taxonomy loaded");
+            if let Err(issues) = taxonomy.validate() {
+                let skipped_names = issues.iter().filter(|issue| matches!(issue, TaxonomyIssue::UnknownNameTaxid { .. })).count();
+                if skipped_names > 0 {
+                    eprintln!("warning: skipped {} names for taxids not found in nodes.dmp", skipped_names);
+                }
+            }
+            taxonomy
+        },
+        Err(NcbiTaxonomyError::MissingFile(path)) => {
+            eprintln!("NCBI Taxonomy file {} not found", path);
+            process::exit(1);
+        },
+        Err(e) => panic!("Failed to load NCBI Taxonomy: {}", e)
+    }
+}
+
+fn to_sqlite(sub_m: &ArgMatches, taxdb_url: &str) {
+    let taxonomy = load_file_taxonomy(sub_m, true);
+
+    if sub_m.is_present("UPDATE") {
+        let counts = taxonomy.update_sqlite(Some(taxdb_url)).unwrap_or_else(|e| {
+            eprintln!("Failed to update taxonomy database {}: {}", taxdb_url, e);
+            process::exit(1);
+        });
+        eprintln!("{} taxa inserted, {} updated, {} deleted and {} unchanged",
+                  counts.inserted, counts.updated, counts.deleted, counts.unchanged);
+    } else if let Err(e) = taxonomy.save_to_sqlite(Some(taxdb_url)) {
+        eprintln!("Failed to save taxonomy database to {}: {}", taxdb_url, e);
+        process::exit(1);
+    }
+}
+
+fn export(sub_m: &ArgMatches) {
+    let taxonomy = load_file_taxonomy(sub_m, false);
+
+    let result = match sub_m.value_of("OUTPUT") {
+        Some(output_filename) => File::create(output_filename).and_then(|output_file| taxonomy.export_tsv(BufWriter::new(output_file))),
+        None => taxonomy.export_tsv(BufWriter::new(io::stdout().lock()))
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to export taxonomy: {}", e);
+        process::exit(1);
+    }
+}
+
 pub fn main() {
     let app_m = clap_app!(taxonomy_util =>
         (version: ncbitaxonomy::VERSION)
         (author: "Peter van Heusden <pvh@sanbi.axc.za>")
         (about: "Utilities for working with the NCBI taxonomy database")
-        (@arg TAXDB_URL: -d --db +takes_value +required "URL for SQLite taxonomy database")
+        (@arg TAXDB_URL: -d --db +takes_value "URL for SQLite taxonomy database")
+        (@subcommand common_ancestor =>
+            (about: "find the most recent common ancestor of two or more taxa")
+            (@arg TAXID: --taxid -i "Interpret TAXA as taxonomy IDs instead of names")
+            (@arg TAXA: ... +required min_values(2) "Names (or taxonomy IDs) of taxa")
+        )
         (@subcommand common_ancestor_distance =>
             (about: "find the tree distance to te common ancestor between two taxa")
             (@arg CANONICAL: --only_canonical "Only consider canonical taxonomic ranks")
             (@arg NAME1: +required "Name of first taxon")
             (@arg NAME2: +required "Name of second taxon")
         )
+        (@subcommand export =>
+            (about: "export taxonomy database loaded from files as a TSV table")
+            (@arg TAXONOMY_FILENAME_PREFIX: -t --tax_prefix +takes_value "String to prepend to names of nodes.dmp and names.dmp")
+            (@arg OUTPUT: -o --output +takes_value "Output TSV filename (or stdout if omitted)")
+            (@arg TAXONOMY_DIR: +required "Directory containing the NCBI taxonomy nodes.dmp and names.dmp files")
+        )
         (@subcommand get_id =>
             (about: "find taxonomy ID for name")
+            (@arg ALL: --all "List all the taxa with this name (homonyms), with their rank and lineage")
             (@arg NAME: +required "Name of taxon")
         )
         (@subcommand get_name =>
             (about: "find name for taxonomy ID")
+            (@arg DISPLAY: --display "Show the GenBank common name if the taxon has one, instead of the scientific name")
             (@arg ID: +required "Taxonomy ID to look up")
         )
         (@subcommand get_lineage =>
             (about: "get lineage for name")
             (@arg SHOW_NAMES: --show_names -S "Show taxon names, not just IDs")
+            (@arg RANKS: --ranks -R "Show taxon names and ranks, e.g. 10239 Viruses (superkingdom)")
+            (@arg STANDARD: --only_standard "Only show taxa at the standard ranks (superkingdom, phylum, class, order, family, genus and species)")
             (@arg DELIMITER: --delimiter -D +takes_value "Delimiter for lineage string")
             (@arg NAME: +required "Name of taxon")
         )
+        (@subcommand descendants =>
+            (about: "list the descendants of a taxon, optionally only those at a given rank")
+            (@arg TAXID: --taxid -i "Interpret ANCESTOR as a taxonomy ID instead of a name")
+            (@arg RANK: --rank -r +takes_value "Only list descendants at this rank (e.g. species)")
+            (@arg SHOW_NAMES: --show_names -S "Show taxon names, not just IDs")
+            (@arg COUNT: --count -c "Only print the number of descendants")
+            (@arg ANCESTOR: +required "Name (or taxonomy ID) of ancestor taxon")
+        )
+        (@subcommand siblings =>
+            (about: "list the other taxa with the same parent as a taxon, e.g. the other species in its genus")
+            (@arg TAXID: --taxid -i "Interpret TAXON as a taxonomy ID instead of a name")
+            (@arg SHOW_NAMES: --show_names -S "Show taxon names, not just IDs")
+            (@arg TAXON: +required "Name (or taxonomy ID) of taxon")
+        )
+        (@subcommand report_summary =>
+            (about: "summarise the read classifications reported by Centrifuge or Kraken2")
+            (@arg REPORT: -r --report +takes_value +required "Classification output from Centrifuge or Kraken2")
+            (@arg TOOL: -T --tool +takes_value possible_values(&["centrifuge", "kraken2"]) default_value("kraken2") "Classifier that produced the report")
+            (@arg TOP: -n --top +takes_value default_value("10") "Number of taxa with the most reads to show")
+        )
         (@subcommand to_sqlite =>
             (about: "save taxonomy database loaded from files to SQLite database file")
             (@arg TAXONOMY_FILENAME_PREFIX: -t --tax_prefix +takes_value "String to prepend to names of nodes.dmp and names.dmp")
+            (@arg UPDATE: -u --update "Update an existing database instead of creating a new one")
             (@arg TAXONOMY_DIR: +required "Directory containing the NCBI taxonomy nodes.dmp and names.dmp files")
         )
     ).get_matches();
 
-    let taxdb_url= app_m.value_of("TAXDB_URL").unwrap();
+    // export only reads the taxonomy files, all other subcommands need a database
+    if let ("export", Some(sub_m)) = app_m.subcommand() {
+        export(sub_m);
+        return
+    }
+    let taxdb_url = app_m.value_of("TAXDB_URL").unwrap_or_else(|| {
+        eprintln!("a taxonomy database is required (use --db)");
+        exit(1);
+    });
     // sqlite URLs are filename paths (the :memory: URL does not make sense for this application)
-    match app_m.subcommand_name() {
-        Some("to_sqlite") => {}, // valid to have a nonexistent database here, we will write to it
+    match app_m.subcommand() {
+        ("to_sqlite", Some(sub_m)) => {
+            // valid to have a nonexistent database here, we will write to it
+            to_sqlite(sub_m, taxdb_url);
+            return
+        },
         _ => {
             let db_path = Path::new(taxdb_url);
             if ! db_path.exists() {
@@ -68,6 +201,17 @@ pub fn main() {
     let taxonomy = NcbiSqliteTaxonomy::new(Some(taxdb_url));
 
     match app_m.subcommand() {
+        ("common_ancestor", Some(sub_m)) => {
+            let is_taxid = sub_m.is_present("TAXID");
+            let taxids: Vec<i32> = sub_m.values_of("TAXA").unwrap().map(|value| taxid_for_arg(&taxonomy, value, is_taxid)).collect();
+            match taxonomy.get_common_ancestor_of(&taxids) {
+                Some(taxid) => println!("{}\t{}", taxid, taxonomy.get_name_by_id(taxid).unwrap_or_default()),
+                None => {
+                    eprintln!("no common ancestor found");
+                    process::exit(1)
+                }
+            }
+        },
         ("common_ancestor_distance", Some(sub_m)) => {
             let only_canonical = sub_m.is_present("CANONICAL");
             let name1 = sub_m.value_of("NAME1").unwrap();
@@ -76,63 +220,121 @@ pub fn main() {
         },
         ("get_id", Some(sub_m)) => {
             let name = sub_m.value_of("NAME").unwrap();
+            if sub_m.is_present("ALL") {
+                let taxids = taxonomy.get_ids_by_name(name);
+                if taxids.is_empty() {
+                    eprintln!("name {} not found in taxonomy", name);
+                    process::exit(1);
+                }
+                for taxid in taxids {
+                    let rank = taxonomy.get_rank_by_id(taxid).unwrap_or_else(|| "no rank".to_string());
+                    let lineage = taxonomy.get_lineage_taxid(taxid).unwrap_or_default().iter()
+                        .map(|id| taxonomy.get_name_by_id(*id).unwrap_or_else(|| id.to_string()))
+                        .collect::<Vec<String>>();
+                    println!("{}\t{}\t{}", taxid, rank, lineage.join(";"));
+                }
+                return
+            }
             match taxonomy.get_id_by_name(name) {
                 Some(val) => println!("{}", val),
-                None => eprintln!("name {} not found in taxonomy", name)
+                None => {
+                    eprintln!("name {} not found in taxonomy, did you mean:", name);
+                    for (taxid, similar_name, _) in taxonomy.search_names(name, 5) {
+                        eprintln!("    {} ({})", similar_name, taxid);
+                    }
+                }
             }
         },
         ("get_name", Some(sub_m)) => {
             let taxid = (sub_m.value_of("ID").unwrap()).parse::<i32>().unwrap();
-            match taxonomy.get_name_by_id(taxid) {
+            let name = if sub_m.is_present("DISPLAY") {
+                taxonomy.get_preferred_display_name(taxid)
+            } else {
+                taxonomy.get_scientific_name_by_id(taxid)
+            };
+            match name {
                 Some(val) => println!("{}", val),
                 None => eprintln!("id {} not found in taxonomy", taxid)
             }
         },
         ("get_lineage", Some(sub_m)) => {
             let show_names = sub_m.is_present("SHOW_NAMES");
+            let show_ranks = sub_m.is_present("RANKS");
+            let only_standard = sub_m.is_present("STANDARD");
             let delimiter = sub_m.value_of("DELIMITER").unwrap_or(";");
             let name = sub_m.value_of("NAME").unwrap();
 
-            match taxonomy.get_lineage(name) {
+            match taxonomy.get_id_by_name(name).and_then(|taxid| taxonomy.get_lineage_with_ranks(taxid)) {
                 None => eprintln!("{} not found in taxonomy", name),
                 Some(lineage) => {
-                    let output_list: Vec<String> = lineage.iter().map(|id| {
-                        match show_names {
-                            false => id.to_string(),
-                            true => taxonomy.get_name_by_id(*id).unwrap() + " (" + id.to_string().as_str() + ")"
-                        }
-                    }).collect();
+                    let output_list: Vec<String> = lineage.iter()
+                        .filter(|(_, _, rank)| !only_standard || STANDARD_RANKS.contains(rank))
+                        .map(|(id, taxon_name, rank)| {
+                            if show_ranks {
+                                format!("{} {} ({})", id, taxon_name, rank)
+                            } else if show_names {
+                                format!("{} ({})", taxon_name, id)
+                            } else {
+                                id.to_string()
+                            }
+                        }).collect();
                     println!("{}", output_list.join(delimiter));
                 }
             }
         }
-        ("to_sqlite", Some(sub_m)) => {
-            let ncbi_taxonomy_path = Path::new(sub_m.value_of("TAXONOMY_DIR").unwrap());
-
-            let tax_prefix = match sub_m.value_of("TAXONOMY_FILENAME_PREFIX") {
-                Some(name) => name,
-                None => ""
-            }.to_string();
-
-            let nodes_path = ncbi_taxonomy_path.join(tax_prefix.clone() + "nodes.dmp");
-            if ! nodes_path.exists() {
-                eprintln!("NCBI Taxonomy {}nodes.dmp file not found in {}", tax_prefix, ncbi_taxonomy_path.to_str().unwrap());
-                process::exit(1);
+        ("descendants", Some(sub_m)) => {
+            let ancestor_taxid = taxid_for_arg(&taxonomy, sub_m.value_of("ANCESTOR").unwrap(), sub_m.is_present("TAXID"));
+            let descendants = match sub_m.value_of("RANK") {
+                Some(rank) => taxonomy.descendants_at_rank(ancestor_taxid, rank),
+                None => taxonomy.get_descendant_taxids(ancestor_taxid)
+            }.unwrap_or_default();
+            if sub_m.is_present("COUNT") {
+                println!("{}", descendants.len());
+            } else if sub_m.is_present("SHOW_NAMES") {
+                let names = taxonomy.get_names_by_ids(&descendants);
+                for taxid in descendants {
+                    println!("{}\t{}", taxid, names.get(&taxid).map(|name| name.as_str()).unwrap_or(""));
+                }
+            } else {
+                for taxid in descendants {
+                    println!("{}", taxid);
+                }
             }
-
-            let names_path = ncbi_taxonomy_path.join(tax_prefix.clone() + "names.dmp");
-            if ! names_path.exists() {
-                eprintln!("NCBI Taxonomy {}names.dmp file not found in {}", tax_prefix, ncbi_taxonomy_path.to_str().unwrap());
-                process::exit(1);
+        },
+        ("siblings", Some(sub_m)) => {
+            let taxid = taxid_for_arg(&taxonomy, sub_m.value_of("TAXON").unwrap(), sub_m.is_present("TAXID"));
+            let siblings = taxonomy.get_siblings_taxids(taxid).unwrap_or_default();
+            if sub_m.is_present("SHOW_NAMES") {
+                let names = taxonomy.get_names_by_ids(&siblings);
+                for taxid in siblings {
+                    println!("{}\t{}", taxid, names.get(&taxid).map(|name| name.as_str()).unwrap_or(""));
+                }
+            } else {
+                for taxid in siblings {
+                    println!("{}", taxid);
+                }
             }
+        },
+        ("report_summary", Some(sub_m)) => {
+            let report_filename = sub_m.value_of("REPORT").unwrap();
+            let tool = match sub_m.value_of("TOOL").unwrap() {
+                "centrifuge" => ClassifierTool::Centrifuge,
+                _ => ClassifierTool::Kraken2
+            };
+            let top_str = sub_m.value_of("TOP").unwrap();
+            let top = top_str.parse::<usize>().unwrap_or_else(|_| panic!("Failed to interpret ({}) as a number of taxa", top_str));
 
-            eprintln!("loading taxonomy");
-            let taxonomy = ncbitaxonomy::NcbiFileTaxonomy::from_ncbi_files(
-                nodes_path.as_path().to_str().unwrap(),
-                names_path.as_path().to_str().unwrap()).expect("Failed to load NCBI Taxonomy");
-            eprintln!("taxonomy loaded");
-
-            taxonomy.save_to_sqlite(Some(taxdb_url)).expect("failed to save taxonomy database to SQLite");
+            let report_file = File::open(report_filename).unwrap_or_else(|_| panic!("Failed to open classification report ({})", report_filename));
+            let classifications = read_classifications(tool, BufReader::new(report_file)).expect("Failed to read classification report");
+            let summary = ClassificationSummary::from_classifications(&classifications);
+            println!("total reads\t{}", summary.total_reads);
+            println!("classified reads\t{}", summary.classified_reads);
+            println!("unclassified reads\t{}", summary.unclassified_reads);
+            for (taxid, reads) in summary.top_taxa(top) {
+                let name = taxonomy.get_name_by_id(taxid).unwrap_or_else(|| "unknown".to_string());
+                let rank = taxonomy.get_rank_by_id(taxid).unwrap_or_else(|| "no rank".to_string());
+                println!("{}\t{}\t{}\t{}", taxid, name, rank, reads);
+            }
         },
         _ => {
             eprintln!("Unknown subcommand");
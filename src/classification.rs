@@ -0,0 +1,209 @@
+//! Parsing of the per-read output of the Centrifuge and Kraken2 read classifiers
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+
+use NcbiTaxonomyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassifierTool {
+    Centrifuge,
+    Kraken2
+}
+
+impl fmt::Display for ClassifierTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ClassifierTool::Centrifuge => write!(f, "Centrifuge"),
+            ClassifierTool::Kraken2 => write!(f, "Kraken2")
+        }
+    }
+}
+
+/// A single line of classifier output, i.e. one classification of one read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadClassification {
+    pub read_id: String,
+    /// the taxid the read was assigned to, None if the read is unclassified
+    pub taxid: Option<i32>,
+    /// classifier score (Kraken2 does not report a score, so a fixed score is used)
+    pub score: i32
+}
+
+// kraken2 does not score its classifications, so make up a score
+const KRAKEN2_SCORE: i32 = 1000;
+
+/// parse_classification_line
+///
+/// parse a line of Centrifuge or Kraken2 output, returning None for the Centrifuge header line
+pub fn parse_classification_line(tool: ClassifierTool, line: &str) -> Result<Option<ReadClassification>, NcbiTaxonomyError> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    let format_error = || NcbiTaxonomyError::ReportFormatError(line.to_string());
+    match tool {
+        ClassifierTool::Centrifuge => {
+            if line.starts_with("readID") {
+                // skip the header
+                return Ok(None)
+            }
+            if fields.len() < 4 {
+                return Err(format_error())
+            }
+            let taxid = fields[2].parse::<i32>().map_err(|_| format_error())?;
+            let score = fields[3].parse::<i32>().map_err(|_| format_error())?;
+            Ok(Some(ReadClassification {
+                read_id: fields[0].to_owned(),
+                // centrifuge reports unclassified reads with taxid 0
+                taxid: if taxid == 0 { None } else { Some(taxid) },
+                score
+            }))
+        },
+        ClassifierTool::Kraken2 => {
+            if fields.len() < 3 {
+                return Err(format_error())
+            }
+            let taxid = match fields[0] {
+                "U" => None,
+                "C" => {
+                    let name_or_taxid = fields[2];
+                    let taxid_str = if name_or_taxid.contains("(taxid") {
+                        // taxon name output format, e.g. "Escherichia coli (taxid 562)"
+                        let last_part = name_or_taxid.split(' ').next_back().unwrap();
+                        last_part.split(')').next().unwrap()
+                    } else {
+                        name_or_taxid
+                    };
+                    Some(taxid_str.parse::<i32>().map_err(|_| format_error())?)
+                },
+                _ => return Err(format_error())
+            };
+            Ok(Some(ReadClassification {
+                read_id: fields[1].to_owned(),
+                taxid,
+                score: KRAKEN2_SCORE
+            }))
+        }
+    }
+}
+
+/// is_kraken2_report_line
+///
+/// true if a line looks like it comes from a Kraken2 report (the per-taxon summary written with
+/// --report, which starts with the percentage of reads) rather than the per-read output
+pub fn is_kraken2_report_line(line: &str) -> bool {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    fields.len() >= 6 && fields[0].trim().parse::<f64>().is_ok() && fields[1].trim().parse::<u64>().is_ok()
+}
+
+/// read_classifications
+///
+/// read all the classifications from Centrifuge or Kraken2 output
+pub fn read_classifications<R: BufRead>(tool: ClassifierTool, reader: R) -> Result<Vec<ReadClassification>, NcbiTaxonomyError> {
+    let mut classifications = Vec::new();
+    for line in reader.lines() {
+        if let Some(classification) = parse_classification_line(tool, &line?)? {
+            classifications.push(classification);
+        }
+    }
+    Ok(classifications)
+}
+
+/// Read counts summarising the output of a classifier
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ClassificationSummary {
+    pub total_reads: usize,
+    pub classified_reads: usize,
+    pub unclassified_reads: usize,
+    pub reads_per_taxid: HashMap<i32, usize>
+}
+
+impl ClassificationSummary {
+    /// from_classifications
+    ///
+    /// summarise classifications, counting each read once. if a read has more than one
+    /// classification the highest scoring one (or the first, in case of a tie) is used
+    pub fn from_classifications(classifications: &[ReadClassification]) -> Self {
+        let mut best: HashMap<&str, &ReadClassification> = HashMap::new();
+        for classification in classifications {
+            let current = best.entry(&classification.read_id).or_insert(classification);
+            if classification.score > current.score {
+                *current = classification;
+            }
+        }
+
+        let mut summary = ClassificationSummary { total_reads: best.len(), ..Default::default() };
+        for classification in best.values() {
+            match classification.taxid {
+                Some(taxid) => {
+                    summary.classified_reads += 1;
+                    *summary.reads_per_taxid.entry(taxid).or_insert(0) += 1;
+                },
+                None => summary.unclassified_reads += 1
+            }
+        }
+        summary
+    }
+
+    /// top_taxa
+    ///
+    /// the count taxa with the most reads, as (taxid, read count) pairs ordered by decreasing read count
+    pub fn top_taxa(&self, count: usize) -> Vec<(i32, usize)> {
+        let mut taxa = self.reads_per_taxid.iter().map(|(taxid, reads)| (*taxid, *reads)).collect::<Vec<(i32, usize)>>();
+        taxa.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        taxa.truncate(count);
+        taxa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[test]
+    fn parse_kraken2_line() {
+        assert_eq!(parse_classification_line(ClassifierTool::Kraken2, "C\tread1\t12340\t150\t12340:116").unwrap(),
+                   Some(ReadClassification { read_id: "read1".to_string(), taxid: Some(12340), score: 1000 }));
+        assert_eq!(parse_classification_line(ClassifierTool::Kraken2, "C\tread1\tEnterobacteria phage 933J (taxid 12340)\t150\t12340:116").unwrap().unwrap().taxid,
+                   Some(12340));
+        assert_eq!(parse_classification_line(ClassifierTool::Kraken2, "U\tread2\t0\t150\t0:116").unwrap().unwrap().taxid, None);
+        assert!(parse_classification_line(ClassifierTool::Kraken2, "X\tread2").is_err());
+    }
+
+    #[test]
+    fn kraken2_report_line() {
+        assert!(is_kraken2_report_line(" 20.00\t1\t1\tU\t0\tunclassified"));
+        assert!(is_kraken2_report_line(" 80.00\t4\t0\tR\t1\troot"));
+        assert!(!is_kraken2_report_line("C\tread1\t12340\t150\t12340:116"));
+        assert!(!is_kraken2_report_line("U\tread5\t0\t150\t0:116"));
+    }
+
+    #[test]
+    fn parse_centrifuge_line() {
+        assert_eq!(parse_classification_line(ClassifierTool::Centrifuge, "readID\tseqID\ttaxID\tscore\t2ndBestScore\thitLength\tqueryLength\tnumMatches").unwrap(), None);
+        assert_eq!(parse_classification_line(ClassifierTool::Centrifuge, "read1\tNC_000913\t12340\t900\t0\t45\t150\t1").unwrap(),
+                   Some(ReadClassification { read_id: "read1".to_string(), taxid: Some(12340), score: 900 }));
+        assert_eq!(parse_classification_line(ClassifierTool::Centrifuge, "read2\tunclassified\t0\t0\t0\t0\t150\t1").unwrap().unwrap().taxid, None);
+    }
+
+    #[test]
+    fn summarise_kraken2() {
+        let reader = BufReader::new(File::open("data/sample_kraken2.txt").unwrap());
+        let summary = ClassificationSummary::from_classifications(&read_classifications(ClassifierTool::Kraken2, reader).unwrap());
+        assert_eq!(summary.total_reads, 5);
+        assert_eq!(summary.classified_reads, 4);
+        assert_eq!(summary.unclassified_reads, 1);
+        assert_eq!(summary.top_taxa(2), vec![(12340, 2), (156615, 1)]);
+    }
+
+    #[test]
+    fn summarise_centrifuge() {
+        let reader = BufReader::new(File::open("data/sample_centrifuge.txt").unwrap());
+        let summary = ClassificationSummary::from_classifications(&read_classifications(ClassifierTool::Centrifuge, reader).unwrap());
+        assert_eq!(summary.total_reads, 4);
+        assert_eq!(summary.classified_reads, 3);
+        assert_eq!(summary.unclassified_reads, 1);
+        assert_eq!(summary.top_taxa(10), vec![(12340, 2), (156615, 1)]);
+    }
+}
@@ -0,0 +1,214 @@
+//! A macro for implementing NcbiTaxonomy by delegating every method to a NcbiSqliteTaxonomy
+
+// implement all the NcbiTaxonomy methods of a type by calling the same method on the
+// NcbiSqliteTaxonomy (or anything that dereferences to one) returned by its method $taxonomy, e.g.
+// a taxonomy using a connection checked out of a pool or a locked Mutex. the value returned by
+// $taxonomy is dropped at the end of each call
+macro_rules! delegate_ncbi_taxonomy {
+    ($taxonomy:ident) => {
+        fn contains_id(&self, taxid: i32) -> bool {
+            self.$taxonomy().contains_id(taxid)
+        }
+
+        fn contains_name(&self, name: &str) -> bool {
+            self.$taxonomy().contains_name(name)
+        }
+
+        fn iter_taxids(&self) -> Box<dyn Iterator<Item = i32> + '_> {
+            // the taxids are all loaded before the taxonomy is released
+            Box::new(self.$taxonomy().iter_taxids().collect::<Vec<i32>>().into_iter())
+        }
+
+        fn len(&self) -> usize {
+            self.$taxonomy().len()
+        }
+
+        fn is_empty(&self) -> bool {
+            self.$taxonomy().is_empty()
+        }
+
+        fn root_taxid(&self) -> Option<i32> {
+            self.$taxonomy().root_taxid()
+        }
+
+        fn count_by_rank(&self) -> HashMap<String, usize> {
+            self.$taxonomy().count_by_rank()
+        }
+
+        fn is_descendant(&self, name: &str, ancestor_name: &str) -> bool {
+            self.$taxonomy().is_descendant(name, ancestor_name)
+        }
+
+        fn is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> bool {
+            self.$taxonomy().is_descendant_taxid(taxid, ancestor_taxid)
+        }
+
+        fn are_descendants_taxid(&self, taxids: &[i32], ancestor_taxid: i32) -> HashMap<i32, bool> {
+            self.$taxonomy().are_descendants_taxid(taxids, ancestor_taxid)
+        }
+
+        fn get_name_by_id(&self, taxid: i32) -> Option<String> {
+            self.$taxonomy().get_name_by_id(taxid)
+        }
+
+        fn get_common_name_by_id(&self, taxid: i32) -> Option<String> {
+            self.$taxonomy().get_common_name_by_id(taxid)
+        }
+
+        fn get_id_by_name(&self, name: &str) -> Option<i32> {
+            self.$taxonomy().get_id_by_name(name)
+        }
+
+        fn get_names_by_ids(&self, taxids: &[i32]) -> HashMap<i32, String> {
+            self.$taxonomy().get_names_by_ids(taxids)
+        }
+
+        fn get_ids_by_names(&self, names: &[&str]) -> HashMap<String, i32> {
+            self.$taxonomy().get_ids_by_names(names)
+        }
+
+        fn get_ids_by_name(&self, name: &str) -> Vec<i32> {
+            self.$taxonomy().get_ids_by_name(name)
+        }
+
+        fn search_names(&self, query: &str, max_results: usize) -> Vec<(i32, String, f64)> {
+            self.$taxonomy().search_names(query, max_results)
+        }
+
+        fn names_with_prefix(&self, prefix: &str, limit: usize) -> Vec<(i32, String)> {
+            self.$taxonomy().names_with_prefix(prefix, limit)
+        }
+
+        fn names_containing(&self, substr: &str, limit: usize) -> Vec<(i32, String)> {
+            self.$taxonomy().names_containing(substr, limit)
+        }
+
+        fn get_lineage_taxid(&self, taxid: i32) -> Option<Vec<i32>> {
+            self.$taxonomy().get_lineage_taxid(taxid)
+        }
+
+        fn get_lineage(&self, name: &str) -> Option<Vec<i32>> {
+            self.$taxonomy().get_lineage(name)
+        }
+
+        fn get_lineage_with_ranks(&self, taxid: i32) -> Option<Vec<(i32, String, Rank)>> {
+            self.$taxonomy().get_lineage_with_ranks(taxid)
+        }
+
+        fn get_children_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+            self.$taxonomy().get_children_taxids(taxid)
+        }
+
+        fn get_siblings_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+            self.$taxonomy().get_siblings_taxids(taxid)
+        }
+
+        fn get_descendant_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+            self.$taxonomy().get_descendant_taxids(taxid)
+        }
+
+        fn count_descendants(&self, taxid: i32) -> Option<usize> {
+            self.$taxonomy().count_descendants(taxid)
+        }
+
+        fn count_leaves(&self, taxid: i32) -> Option<usize> {
+            self.$taxonomy().count_leaves(taxid)
+        }
+
+        fn is_leaf(&self, taxid: i32) -> Option<bool> {
+            self.$taxonomy().is_leaf(taxid)
+        }
+
+        fn get_parent_taxid(&self, taxid: i32) -> Option<i32> {
+            self.$taxonomy().get_parent_taxid(taxid)
+        }
+
+        fn get_depth(&self, taxid: i32) -> Option<usize> {
+            self.$taxonomy().get_depth(taxid)
+        }
+
+        fn get_canonical_depth(&self, taxid: i32) -> Option<usize> {
+            self.$taxonomy().get_canonical_depth(taxid)
+        }
+
+        fn descendants_at_rank(&self, ancestor_taxid: i32, rank: &str) -> Option<Vec<i32>> {
+            self.$taxonomy().descendants_at_rank(ancestor_taxid, rank)
+        }
+
+        fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)> {
+            self.$taxonomy().get_distance_to_common_ancestor_taxid(taxid1, taxid2, only_canonical)
+        }
+
+        fn get_distance_to_common_ancestor(&self, name1: &str, name2: &str, only_canonical: bool) -> Option<(i32, String)> {
+            self.$taxonomy().get_distance_to_common_ancestor(name1, name2, only_canonical)
+        }
+
+        fn get_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32) -> Option<i32> {
+            self.$taxonomy().get_common_ancestor_taxid(taxid1, taxid2)
+        }
+
+        fn get_common_ancestor_of(&self, taxids: &[i32]) -> Option<i32> {
+            self.$taxonomy().get_common_ancestor_of(taxids)
+        }
+
+        fn get_rank_distance(&self, taxid1: i32, taxid2: i32) -> Option<i32> {
+            self.$taxonomy().get_rank_distance(taxid1, taxid2)
+        }
+
+        fn path_between_taxids(&self, taxid1: i32, taxid2: i32) -> Option<Vec<i32>> {
+            self.$taxonomy().path_between_taxids(taxid1, taxid2)
+        }
+
+        fn effective_rank(&self, taxid: i32) -> Option<String> {
+            self.$taxonomy().effective_rank(taxid)
+        }
+
+        fn next_rank_toward(&self, from_taxid: i32, toward_taxid: i32) -> Option<(i32, String)> {
+            self.$taxonomy().next_rank_toward(from_taxid, toward_taxid)
+        }
+
+        fn canonical_ranks(&self) -> Vec<Rank> {
+            self.$taxonomy().canonical_ranks()
+        }
+
+        fn is_canonical_rank(&self, taxid: i32) -> bool {
+            self.$taxonomy().is_canonical_rank(taxid)
+        }
+
+        fn get_rank_by_id(&self, taxid: i32) -> Option<String> {
+            self.$taxonomy().get_rank_by_id(taxid)
+        }
+
+        fn get_rank_enum_by_id(&self, taxid: i32) -> Option<Rank> {
+            self.$taxonomy().get_rank_enum_by_id(taxid)
+        }
+
+        fn get_rank_by_name(&self, name: &str) -> Option<String> {
+            self.$taxonomy().get_rank_by_name(name)
+        }
+
+        fn get_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32> {
+            self.$taxonomy().get_lineage_by_rank(taxid)
+        }
+
+        fn get_canonical_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32> {
+            self.$taxonomy().get_canonical_lineage_by_rank(taxid)
+        }
+
+        fn get_ancestor_at_rank(&self, taxid: i32, rank: &str) -> Option<i32> {
+            self.$taxonomy().get_ancestor_at_rank(taxid, rank)
+        }
+
+        fn get_standard_lineage(&self, taxid: i32) -> Option<Vec<(Rank, Option<i32>)>> {
+            self.$taxonomy().get_standard_lineage(taxid)
+        }
+
+        fn format_standard_lineage(&self, lineage: &[(Rank, Option<i32>)]) -> String {
+            self.$taxonomy().format_standard_lineage(lineage)
+        }
+
+        fn nearest_reference_distance(&self, query: i32, references: &[i32], only_canonical: bool) -> Option<(i32, i32)> {
+            self.$taxonomy().nearest_reference_distance(query, references, only_canonical)
+        }
+    }
+}
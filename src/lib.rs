@@ -1,3 +1,5 @@
+//! ncbitaxonomy: a module for working with a local copy of the NCBI taxonomy database
+
 #![recursion_limit = "1024"]
 #[macro_use]
 extern crate diesel;
@@ -9,10 +11,20 @@ extern crate indextree;
 extern crate core;
 extern crate seq_io;
 extern crate clap;
-
-/// ncbitaxonomy: a module for working with a local copy of the NCBI taxonomy database
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+extern crate serde;
+extern crate flate2;
+extern crate tar;
+extern crate bincode;
+extern crate strsim;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(test)]
+extern crate serde_json;
 
 use thiserror::Error;
+use serde::{Serialize, Deserialize};
 use std::io;
 
 #[derive(Error, Debug)]
@@ -21,8 +33,36 @@ pub enum NcbiTaxonomyError {
     Io(#[from] io::Error),
     #[error("format error in nodes.dmp in line {0}")]
     NodeFileFormatError(String),
+    #[error("format error in names.dmp in line {0}")]
+    NamesFileFormatError(String),
+    #[error(transparent)]
+    ParseIntError(#[from] ::std::num::ParseIntError),
+    #[error("NCBI Taxonomy file {0} not found")]
+    MissingFile(String),
+    #[error("format error in classification report in line {0}")]
+    ReportFormatError(String),
+    #[error("format error in merged.dmp in line {0}")]
+    MergedFileFormatError(String),
+    #[error("format error in division.dmp in line {0}")]
+    DivisionFileFormatError(String),
+    #[error("format error in host.dmp in line {0}")]
+    HostFileFormatError(String),
+    #[error("format error in typematerial.dmp in line {0}")]
+    TypeMaterialFileFormatError(String),
+    #[error("format error in alias file in line {0}")]
+    AliasFileFormatError(String),
     #[error(transparent)]
-    ParseIntError(#[from] ::std::num::ParseIntError)
+    Database(#[from] diesel::result::Error),
+    #[error("taxon {0} not found in taxonomy")]
+    NotFound(String),
+    #[error("{0} not found in taxdump archive {1}")]
+    MissingArchiveMember(String, String),
+    #[error(transparent)]
+    Cache(#[from] bincode::Error),
+    #[error("{0} is not a taxonomy cache file or was written by a different version")]
+    StaleCache(String),
+    #[error("{} problems found in taxonomy, the first is: {}", .0.len(), .0[0])]
+    InvalidTaxonomy(Vec<TaxonomyIssue>)
 }
 
 #[derive(Error, Debug)]
@@ -31,19 +71,45 @@ pub enum ToSqliteError {
     Diesel(#[from] diesel::result::Error),
     #[error(transparent)]
     MigrationError(#[from] diesel_migrations::RunMigrationsError),
+    #[error(transparent)]
+    ConnectionError(#[from] diesel::ConnectionError),
     #[error("Error looking up id {0}")]
-    IdLookupError(String)
+    IdLookupError(String),
+    #[error("No database URL given and DATABASE_URL is not set")]
+    MissingDatabaseUrl,
+    #[cfg(feature = "r2d2")]
+    #[error(transparent)]
+    PoolError(#[from] diesel::r2d2::PoolError)
 }
 
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader,BufRead};
+use std::io::{BufReader, BufRead, BufWriter, Write};
+use flate2::read::GzDecoder;
 use indextree::{Arena, NodeId, Traverse};
 pub use indextree::NodeEdge;
-use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
 
+#[allow(non_local_definitions, mismatched_lifetime_syntaxes)]
 pub mod models;
+#[allow(non_local_definitions)]
 pub mod schema;
+pub mod classification;
+pub mod rank;
+pub mod queries;
+#[macro_use]
+mod delegate;
+pub mod shared;
+#[cfg(feature = "r2d2")]
+pub mod pool;
+
+pub use rank::{Rank, CANONICAL_RANKS, STANDARD_RANKS};
+pub use shared::SharedNcbiSqliteTaxonomy;
+#[cfg(feature = "r2d2")]
+pub use pool::NcbiSqlitePool;
 
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
@@ -52,9 +118,13 @@ use std::env;
 
 use self::models::*;
 use diesel::expression::dsl::count;
+use diesel::expression::dsl::sql;
+use diesel::sql_types::{BigInt, Bool, Text};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+embed_migrations!();
+
 fn establish_connection(db_url: Option<&str>) -> SqliteConnection {
     dotenv().ok();
 
@@ -67,21 +137,665 @@ fn establish_connection(db_url: Option<&str>) -> SqliteConnection {
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
-fn get_canonical_ranks() -> HashSet<String> {
-    // canonical ranks (+ superkingdom) as they appear in the NCBI taxonomy database
-    HashSet::from_iter(vec!["superkingdom", "kingdom", "phylum", "class", "order", "family", "genus", "species"].iter().map(|x| (*x).to_string()))
+// db_url, or DATABASE_URL if db_url is None
+fn try_database_url(db_url: Option<&str>) -> Result<String, ToSqliteError> {
+    dotenv().ok();
+
+    match db_url {
+        Some(val) => Ok(val.to_owned()),
+        None => env::var("DATABASE_URL").map_err(|_| ToSqliteError::MissingDatabaseUrl)
+    }
+}
+
+// like establish_connection, but returning an error instead of panicking
+fn try_establish_connection(db_url: Option<&str>) -> Result<SqliteConnection, ToSqliteError> {
+    Ok(SqliteConnection::establish(&try_database_url(db_url)?)?)
+}
+
+// break the cycles of parents in a tree (which a malformed nodes.dmp can create), so that
+// walking up from any node ends at a root. for each cycle the link from its smallest taxid to
+// that taxon's parent is removed and returned
+fn break_parent_cycles(arena: &mut Arena<i32>) -> Vec<(i32, i32)> {
+    let node_count = arena.count();
+    let roots: Vec<NodeId> = (0..node_count).map(NodeId::new).filter(|node_id| arena[*node_id].parent().is_none()).collect();
+    // the taxa on (or below) a cycle cannot be reached from a root
+    if roots.iter().map(|root| root.descendants(arena).count()).sum::<usize>() == node_count {
+        return vec![]
+    }
+
+    let mut cycle_links = Vec::new();
+    let mut reached: HashSet<NodeId> = roots.iter().flat_map(|root| root.descendants(arena)).collect();
+    for index in 0..node_count {
+        let node_id = NodeId::new(index);
+        if reached.contains(&node_id) {
+            continue
+        }
+        // walk up until a node is seen again, which is on the cycle
+        let mut path = vec![node_id];
+        let mut current = arena[node_id].parent().unwrap();
+        while !path.contains(&current) {
+            path.push(current);
+            current = arena[current].parent().unwrap();
+        }
+        let cycle_start = path.iter().position(|path_node_id| *path_node_id == current).unwrap();
+        let cycle_node_id = *path[cycle_start..].iter().min_by_key(|path_node_id| arena[**path_node_id].data).unwrap();
+        let parent_taxid = arena[arena[cycle_node_id].parent().unwrap()].data;
+        cycle_links.push((arena[cycle_node_id].data, parent_taxid));
+        cycle_node_id.detach(arena);
+        reached.extend(cycle_node_id.descendants(arena));
+    }
+    cycle_links.sort_unstable();
+    cycle_links
+}
+
+// call f on each line read from a reader, stopping at the first error
+fn for_each_line_in<R: BufRead, F>(reader: R, mut f: F) -> Result<(), NcbiTaxonomyError>
+    where F: FnMut(&str) -> Result<(), NcbiTaxonomyError> {
+    for line_maybe in reader.lines() {
+        f(&line_maybe?)?;
+    }
+    Ok(())
+}
+
+// call f on each line of a file (which is decompressed if its name ends in .gz), stopping at the first error
+#[cfg(not(feature = "mmap"))]
+fn for_each_line<F>(filename: &str, f: F) -> Result<(), NcbiTaxonomyError>
+    where F: FnMut(&str) -> Result<(), NcbiTaxonomyError> {
+    let file = File::open(filename)?;
+    if filename.ends_with(".gz") {
+        for_each_line_in(BufReader::new(GzDecoder::new(file)), f)
+    } else {
+        for_each_line_in(BufReader::new(file), f)
+    }
+}
+
+// call f on each line of the member of a .tar.gz archive with the given file name (wherever
+// it is in the archive), stopping at the first error
+fn for_each_line_in_archive<F>(archive_filename: &str, member_name: &str, f: F) -> Result<(), NcbiTaxonomyError>
+    where F: FnMut(&str) -> Result<(), NcbiTaxonomyError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive_filename)?));
+    for entry_maybe in archive.entries()? {
+        let entry = entry_maybe?;
+        if entry.path()?.file_name().is_some_and(|name| name == member_name) {
+            return for_each_line_in(BufReader::new(entry), f)
+        }
+    }
+    Err(NcbiTaxonomyError::MissingArchiveMember(member_name.to_string(), archive_filename.to_string()))
+}
+
+// memory mapped version of for_each_line: this avoids copying each line out of the file
+// into a buffer, which helps when loading the large names.dmp and nodes.dmp files of the
+// full NCBI Taxonomy, especially when they are already in the page cache
+#[cfg(feature = "mmap")]
+fn for_each_line<F>(filename: &str, mut f: F) -> Result<(), NcbiTaxonomyError>
+    where F: FnMut(&str) -> Result<(), NcbiTaxonomyError> {
+    let file = File::open(filename)?;
+    if filename.ends_with(".gz") {
+        // a compressed file has to be decompressed through a buffer anyway
+        return for_each_line_in(BufReader::new(GzDecoder::new(file)), f)
+    }
+    // safety: the file is only read, and the dmp files are not expected to change while loading
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    let mut contents: &[u8] = &mmap;
+    if contents.last() == Some(&b'\n') {
+        contents = &contents[..contents.len() - 1];
+    }
+    if contents.is_empty() {
+        return Ok(())
+    }
+    for line in contents.split(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = std::str::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        f(line)?;
+    }
+    Ok(())
+}
+
+// counts the lines read while loading a taxonomy, calling callback with the number of lines
+// read so far every `every` lines
+struct LineProgress<'a> {
+    lines: usize,
+    every: usize,
+    callback: &'a mut dyn FnMut(usize)
+}
+
+impl<'a> LineProgress<'a> {
+    fn new(every: usize, callback: &'a mut dyn FnMut(usize)) -> Self {
+        LineProgress { lines: 0, every: every.max(1), callback }
+    }
+
+    fn line(&mut self) {
+        self.lines += 1;
+        if self.lines.is_multiple_of(self.every) {
+            (self.callback)(self.lines);
+        }
+    }
+
+    // report the lines read since the last call of the callback
+    fn finish(&mut self) {
+        if !self.lines.is_multiple_of(self.every) {
+            (self.callback)(self.lines);
+        }
+    }
+}
+
+// the fields of a line of nodes.dmp that are used. the fields after the rank are optional so
+// that (e.g. test) files with only the first three fields can still be read
+struct NodesLine {
+    taxid: i32,
+    parent_taxid: i32,
+    rank: String,
+    division_id: Option<i32>,
+    genetic_code: Option<u8>,
+    mito_genetic_code: Option<u8>
+}
+
+// parse a line of nodes.dmp
+fn parse_nodes_line(line: &str) -> Result<NodesLine, NcbiTaxonomyError> {
+    let fields = split_dmp_line(line);
+    if fields.len() < 3 {
+        return Err(NcbiTaxonomyError::NodeFileFormatError(line.to_string()))
+    }
+    // the (optional) integer field at an index
+    let optional_field = |index: usize| fields.get(index).map(|field| field.parse::<i32>()).transpose();
+    let genetic_code_field = |index: usize| fields.get(index).map(|field| field.parse::<u8>()).transpose();
+    Ok(NodesLine {
+        taxid: fields[0].parse::<i32>()?,
+        parent_taxid: fields[1].parse::<i32>()?,
+        rank: fields[2].to_string(),
+        // field 3 is the EMBL code, 5 and 7 are flags for whether the division and genetic code are inherited
+        division_id: optional_field(4)?,
+        genetic_code: genetic_code_field(6)?,
+        mito_genetic_code: genetic_code_field(8)?
+    })
+}
+
+// parse all the lines of nodes.dmp, as passed to a callback by for_each_nodes_line
+#[cfg(not(feature = "parallel"))]
+fn parse_nodes_lines<N>(for_each_nodes_line: N) -> Result<Vec<NodesLine>, NcbiTaxonomyError>
+    where N: FnOnce(&mut dyn FnMut(&str) -> Result<(), NcbiTaxonomyError>) -> Result<(), NcbiTaxonomyError> {
+    let mut nodes = Vec::new();
+    for_each_nodes_line(&mut |line| {
+        nodes.push(parse_nodes_line(line)?);
+        Ok(())
+    })?;
+    Ok(nodes)
+}
+
+// parallel version of parse_nodes_lines: the lines are read (serially) in chunks and each chunk
+// is parsed with rayon while the next one is being read
+#[cfg(feature = "parallel")]
+fn parse_nodes_lines<N>(for_each_nodes_line: N) -> Result<Vec<NodesLine>, NcbiTaxonomyError>
+    where N: FnOnce(&mut dyn FnMut(&str) -> Result<(), NcbiTaxonomyError>) -> Result<(), NcbiTaxonomyError> {
+    use rayon::prelude::*;
+
+    const CHUNK_SIZE: usize = 100_000;
+    let mut chunks: Vec<Vec<String>> = Vec::new();
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    for_each_nodes_line(&mut |line| {
+        chunk.push(line.to_string());
+        if chunk.len() == CHUNK_SIZE {
+            chunks.push(std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_SIZE)));
+        }
+        Ok(())
+    })?;
+    chunks.push(chunk);
+    let parsed_chunks = chunks.par_iter()
+        .map(|chunk| chunk.iter().map(|line| parse_nodes_line(line)).collect::<Result<Vec<_>, _>>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(parsed_chunks.into_iter().flatten().collect())
+}
+
+// split a line from a .dmp file into its fields. lines end with a "\t|" terminator
+fn split_dmp_line(line: &str) -> Vec<&str> {
+    let line = line.strip_suffix("\t|").unwrap_or(line);
+    line.split("\t|\t").collect()
+}
+
+// similarity of a name to a search query, between 0 (nothing in common) and 1 (the same apart
+// from case). this is the Jaro-Winkler similarity, which favours names that share a prefix with
+// the query, so it handles typos towards the end of a name better than typos at the start
+fn name_similarity(query: &str, name: &str) -> f64 {
+    strsim::jaro_winkler(&query.to_lowercase(), &name.to_lowercase())
+}
+
+// the max_results names most similar to a query, as (taxid, name, similarity) sorted by decreasing similarity
+fn most_similar_names<'a, I>(query: &str, names: I, max_results: usize) -> Vec<(i32, String, f64)>
+    where I: Iterator<Item = (i32, &'a str)> {
+    let mut scores = names.map(|(taxid, name)| (taxid, name, name_similarity(query, name))).collect::<Vec<_>>();
+    scores.sort_unstable_by(|a, b| b.2.total_cmp(&a.2).then(a.1.cmp(b.1)));
+    scores.into_iter().take(max_results).map(|(taxid, name, score)| (taxid, name.to_string(), score)).collect()
+}
+
+// the first limit of the names that a filter accepts, as (taxid, name) sorted by name
+fn matching_names<'a, I, F>(names: I, filter: F, limit: usize) -> Vec<(i32, String)>
+    where I: Iterator<Item = (i32, &'a str)>, F: Fn(&str) -> bool {
+    let mut matches = names.filter(|(_, name)| filter(name)).collect::<Vec<_>>();
+    matches.sort_unstable_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(&b.0)));
+    matches.into_iter().take(limit).map(|(taxid, name)| (taxid, name.to_string())).collect()
+}
+
+// check whether a taxon name is name itself or the unique name NCBI gives one of several taxa
+// with the same name (a homonym), e.g. "Prunella <angiosperm>" and "Prunella <birds>"
+fn is_name_or_homonym(taxon_name: &str, name: &str) -> bool {
+    match taxon_name.strip_prefix(name) {
+        Some(rest) => rest.is_empty() || (rest.starts_with(" <") && rest.ends_with('>')),
+        None => false
+    }
+}
+
+/// strip_disambiguation
+///
+/// the name without the qualifier that NCBI adds to make the names of homonyms unique, e.g.
+/// "Prunella" for "Prunella <bird>". a name without a qualifier is returned as it is
+pub fn strip_disambiguation(name: &str) -> &str {
+    match name.rfind(" <") {
+        Some(start) if name.ends_with('>') => &name[..start],
+        _ => name
+    }
+}
+
+// escape the characters that have a special meaning in a SQL LIKE pattern (with \ as escape character)
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// check whether a rank (as it appears in the NCBI taxonomy database) is one of canonical_ranks
+fn is_canonical(canonical_ranks: &[Rank], rank: &str) -> bool {
+    canonical_ranks.contains(&Rank::from(rank))
+}
+
+// the position of a rank (as it appears in the NCBI taxonomy database) in canonical_ranks, None
+// if it is not one of them
+fn canonical_level(canonical_ranks: &[Rank], rank: &str) -> Option<usize> {
+    let rank = Rank::from(rank);
+    canonical_ranks.iter().position(|canonical_rank| *canonical_rank == rank)
+}
+
+/// A taxon whose canonical rank is not more specific than the rank of its nearest canonical ancestor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankIssue {
+    pub taxid: i32,
+    pub rank: String,
+    pub ancestor_taxid: i32,
+    pub ancestor_rank: String
+}
+
+/// A problem with the structure of a taxonomy loaded from (possibly hand-edited or partial) dump files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaxonomyIssue {
+    /// the parent of taxid in nodes.dmp is one of its own descendants. this link is left out of the tree
+    Cycle { taxid: i32, parent_taxid: i32 },
+    /// taxid appears more than once in nodes.dmp with different parents. only the first parent is used
+    MultipleParents { taxid: i32, parent_taxids: Vec<i32> },
+    /// taxid is the parent of other taxa but has no line of its own in nodes.dmp
+    UndefinedTaxon { taxid: i32 },
+    /// taxid has a name in names.dmp but is not in nodes.dmp. the name is left out
+    UnknownNameTaxid { taxid: i32, name: String }
+}
+
+impl fmt::Display for TaxonomyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaxonomyIssue::Cycle { taxid, parent_taxid } =>
+                write!(f, "parent {} of taxid {} is also its descendant", parent_taxid, taxid),
+            TaxonomyIssue::MultipleParents { taxid, parent_taxids } =>
+                write!(f, "taxid {} has more than one parent: {:?}", taxid, parent_taxids),
+            TaxonomyIssue::UndefinedTaxon { taxid } =>
+                write!(f, "taxid {} has children but is not defined in nodes.dmp", taxid),
+            TaxonomyIssue::UnknownNameTaxid { taxid, name } =>
+                write!(f, "name {} is given for taxid {} which is not in nodes.dmp", name, taxid)
+        }
+    }
+}
+
+/// A taxon and all its descendants, e.g. for serializing a subtree to JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaxonNode {
+    pub taxid: i32,
+    pub name: String,
+    pub rank: String,
+    pub children: Vec<TaxonNode>
 }
 
 pub trait NcbiTaxonomy {
     fn contains_id(&self, taxid: i32) -> bool;
     fn contains_name(&self, name: &str) -> bool;
+    fn iter_taxids(&self) -> Box<dyn Iterator<Item = i32> + '_>;
+    fn len(&self) -> usize;
+
+    /// is_empty
+    ///
+    /// check whether the taxonomy contains no taxa at all
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// root_taxid
+    ///
+    /// the taxid of the root of the taxonomy, i.e. the only taxon without a parent. this is 1 for
+    /// the NCBI Taxonomy but not for an extracted subtree. None if the taxonomy is empty or has more
+    /// than one taxon without a parent (e.g. an invalid nodes.dmp with a taxon whose parent is missing)
+    fn root_taxid(&self) -> Option<i32>;
+
+    /// count_by_rank
+    ///
+    /// count the taxa of each rank, e.g. the number of species. taxa without a rank are counted
+    /// under 'no rank'
+    fn count_by_rank(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for taxid in self.iter_taxids() {
+            let rank = self.get_rank_by_id(taxid).unwrap_or_else(|| "no rank".to_string());
+            *counts.entry(rank).or_insert(0) += 1;
+        }
+        counts
+    }
     fn is_descendant(&self, name: &str, ancestor_name: &str) -> bool;
     fn is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> bool;
+    fn are_descendants_taxid(&self, taxids: &[i32], ancestor_taxid: i32) -> HashMap<i32, bool>;
+
+    /// is_ancestor
+    ///
+    /// check if a named taxon is an ancestor of another named taxon. this is is_descendant with
+    /// the arguments swapped: is_ancestor(a, b) == is_descendant(b, a), so (like is_descendant) a
+    /// taxon counts as its own ancestor
+    fn is_ancestor(&self, name: &str, descendant_name: &str) -> bool {
+        self.is_descendant(descendant_name, name)
+    }
+
+    /// is_ancestor_taxid
+    ///
+    /// check if the taxon with taxid is an ancestor of the taxon with descendant_taxid, i.e.
+    /// is_descendant_taxid(descendant_taxid, taxid)
+    fn is_ancestor_taxid(&self, taxid: i32, descendant_taxid: i32) -> bool {
+        self.is_descendant_taxid(descendant_taxid, taxid)
+    }
+
+    /// is_in_clade_taxid
+    ///
+    /// check if the taxon with taxid is in the clade rooted at clade_root, i.e. is clade_root
+    /// itself or one of its descendants. is_descendant_taxid already counts a taxon as its own
+    /// descendant, so this gives the same answer, but says so in its name
+    fn is_in_clade_taxid(&self, taxid: i32, clade_root: i32) -> bool {
+        self.is_descendant_taxid(taxid, clade_root)
+    }
+
+    /// get_name_by_id
+    ///
+    /// get the scientific name of a taxon. this is an alias of get_scientific_name_by_id, kept
+    /// because it is used so widely
     fn get_name_by_id(&self, taxid: i32) -> Option<String>;
+
+    /// get_scientific_name_by_id
+    ///
+    /// get the scientific name of a taxon (never a synonym or common name)
+    fn get_scientific_name_by_id(&self, taxid: i32) -> Option<String> {
+        self.get_name_by_id(taxid)
+    }
+
+    /// get_common_name_by_id
+    ///
+    /// get the GenBank common name (e.g. eubacteria for Bacteria) of a taxon, None if the taxon
+    /// is not found or has no common name
+    fn get_common_name_by_id(&self, taxid: i32) -> Option<String>;
+
+    /// get_preferred_display_name
+    ///
+    /// get the name to show to people for a taxon: its GenBank common name if it has one,
+    /// otherwise its scientific name
+    fn get_preferred_display_name(&self, taxid: i32) -> Option<String> {
+        self.get_common_name_by_id(taxid).or_else(|| self.get_scientific_name_by_id(taxid))
+    }
+
     fn get_id_by_name(&self, name: &str) -> Option<i32>;
-    fn get_lineage(&self, name: &str) -> Option<Vec<i32>>;
+    fn get_names_by_ids(&self, taxids: &[i32]) -> HashMap<i32, String>;
+    fn get_ids_by_names(&self, names: &[&str]) -> HashMap<String, i32>;
+    fn get_ids_by_name(&self, name: &str) -> Vec<i32>;
+    fn search_names(&self, query: &str, max_results: usize) -> Vec<(i32, String, f64)>;
+    fn names_with_prefix(&self, prefix: &str, limit: usize) -> Vec<(i32, String)>;
+    fn names_containing(&self, substr: &str, limit: usize) -> Vec<(i32, String)>;
+    fn get_lineage_taxid(&self, taxid: i32) -> Option<Vec<i32>>;
+
+    /// get_lineage
+    ///
+    /// get the list of IDs of the ancestors of a named taxon, from the root down to the taxon itself
+    fn get_lineage(&self, name: &str) -> Option<Vec<i32>> {
+        self.get_lineage_taxid(self.get_id_by_name(name)?)
+    }
+
+    /// get_lineage_with_ranks
+    ///
+    /// get the (taxid, name, rank) of the ancestors of a taxon, from the root down to the taxon
+    /// itself. taxa without a name get an empty name
+    fn get_lineage_with_ranks(&self, taxid: i32) -> Option<Vec<(i32, String, Rank)>> {
+        let lineage = self.get_lineage_taxid(taxid)?;
+        let mut names = self.get_names_by_ids(&lineage);
+        Some(lineage.into_iter()
+            .map(|taxid| (taxid, names.remove(&taxid).unwrap_or_default(), self.get_rank_enum_by_id(taxid).unwrap_or(Rank::NoRank)))
+            .collect())
+    }
+    fn get_children_taxids(&self, taxid: i32) -> Option<Vec<i32>>;
+
+    /// get_siblings_taxids
+    ///
+    /// get the IDs of the other children of the parent of a taxon (e.g. the other species in its
+    /// genus), sorted. an empty list for the root, None if the taxon is not found
+    fn get_siblings_taxids(&self, taxid: i32) -> Option<Vec<i32>>;
+    fn get_descendant_taxids(&self, taxid: i32) -> Option<Vec<i32>>;
+
+    /// count_descendants
+    ///
+    /// get the number of descendants of a taxon (the size of its clade, not including the taxon
+    /// itself), without listing them
+    fn count_descendants(&self, taxid: i32) -> Option<usize>;
+
+    /// count_leaves
+    ///
+    /// get the number of leaves (taxa with no children) among the descendants of a taxon, 0 if
+    /// the taxon is itself a leaf
+    fn count_leaves(&self, taxid: i32) -> Option<usize>;
+
+    /// is_leaf
+    ///
+    /// check whether a taxon has no children (e.g. a species without strains), None if the taxon
+    /// is not found
+    fn is_leaf(&self, taxid: i32) -> Option<bool>;
+
+    fn get_parent_taxid(&self, taxid: i32) -> Option<i32>;
+    fn get_depth(&self, taxid: i32) -> Option<usize>;
+    fn get_canonical_depth(&self, taxid: i32) -> Option<usize>;
+    fn descendants_at_rank(&self, ancestor_taxid: i32, rank: &str) -> Option<Vec<i32>>;
     fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)>;
     fn get_distance_to_common_ancestor(&self, name1: &str, name2: &str, only_canonical: bool) -> Option<(i32, String)>;
+
+    /// get_common_ancestor_taxid
+    ///
+    /// get the most recent common ancestor of two taxa, which is one of the taxa itself if it
+    /// is an ancestor of the other
+    fn get_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32) -> Option<i32> {
+        self.get_common_ancestor_of(&[taxid1, taxid2])
+    }
+    fn get_common_ancestor_of(&self, taxids: &[i32]) -> Option<i32>;
+
+    /// get_rank_distance
+    ///
+    /// get the number of canonical rank levels (by default superkingdom, kingdom, phylum, class,
+    /// order, family, genus and species) between two taxa and their most recent common ancestor,
+    /// e.g. 2 for two species in the same family. unlike get_distance_to_common_ancestor_taxid, which
+    /// counts steps in the tree (only_canonical counts only the canonical ancestors that are
+    /// there), this counts the levels of the rank ladder, including the levels that are missing
+    /// from the lineages. each taxon is placed at the level of its effective_rank (so taxa with
+    /// 'no rank' count as their nearest canonical ancestor) and the root one level above
+    /// least specific canonical rank. if the taxa are at different levels the distance from the
+    /// more specific one is used
+    fn get_rank_distance(&self, taxid1: i32, taxid2: i32) -> Option<i32> {
+        let common_ancestor = self.get_common_ancestor_taxid(taxid1, taxid2)?;
+        let canonical_ranks = self.canonical_ranks();
+        let canonical_level = |taxid| self.effective_rank(taxid)
+            .and_then(|rank| canonical_level(&canonical_ranks, &rank))
+            .map_or(-1, |level| level as i32);
+        Some(canonical_level(taxid1).max(canonical_level(taxid2)) - canonical_level(common_ancestor))
+    }
+
+    /// path_between_taxids
+    ///
+    /// get the taxids on the path in the tree from taxid1 up to the common ancestor and down to
+    /// taxid2, including both taxa and the common ancestor (once). if one taxon is an ancestor of
+    /// the other, the path only goes up (or down)
+    fn path_between_taxids(&self, taxid1: i32, taxid2: i32) -> Option<Vec<i32>> {
+        let lineage1 = self.get_lineage_taxid(taxid1)?;
+        let lineage2 = self.get_lineage_taxid(taxid2)?;
+        // both lineages start at the root, so they share at least one taxon
+        let common_length = lineage1.iter().zip(lineage2.iter()).take_while(|(a, b)| a == b).count();
+        if common_length == 0 {
+            return None
+        }
+        let mut path: Vec<i32> = lineage1[common_length - 1..].iter().rev().cloned().collect();
+        path.extend_from_slice(&lineage2[common_length..]);
+        Some(path)
+    }
+
+    /// canonical_ranks
+    ///
+    /// the ranks that count as canonical (for only_canonical and the other methods about
+    /// canonical ranks), from least to most specific. CANONICAL_RANKS unless it was changed
+    /// with with_canonical_ranks
+    fn canonical_ranks(&self) -> Vec<Rank>;
+    fn effective_rank(&self, taxid: i32) -> Option<String>;
+    fn next_rank_toward(&self, from_taxid: i32, toward_taxid: i32) -> Option<(i32, String)>;
+    fn is_canonical_rank(&self, taxid: i32) -> bool;
+    fn get_rank_by_id(&self, taxid: i32) -> Option<String>;
+    fn get_rank_enum_by_id(&self, taxid: i32) -> Option<Rank>;
+
+    /// get_rank_by_name
+    ///
+    /// get the rank of a named taxon, None if the taxon is not found or has 'no rank'
+    fn get_rank_by_name(&self, name: &str) -> Option<String> {
+        self.get_rank_by_id(self.get_id_by_name(name)?)
+    }
+    fn get_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32>;
+
+    /// get_canonical_lineage_by_rank
+    ///
+    /// like get_lineage_by_rank, but only for the canonical ranks
+    fn get_canonical_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32> {
+        let canonical_ranks = self.canonical_ranks();
+        self.get_lineage_by_rank(taxid).into_iter().filter(|(rank, _)| is_canonical(&canonical_ranks, rank)).collect()
+    }
+
+    /// get_ancestor_at_rank
+    ///
+    /// get the closest taxon with a given rank in the lineage of a taxon (including the taxon
+    /// itself), e.g. the family a species belongs to. None if there is no taxon with that rank
+    fn get_ancestor_at_rank(&self, taxid: i32, rank: &str) -> Option<i32> {
+        self.get_lineage_by_rank(taxid).get(rank).cloned()
+    }
+
+    /// lineage_rank_gaps
+    ///
+    /// get the canonical_ranks that are missing from the lineage of a taxon, e.g. family for a
+    /// genus whose parent is an order. only the ranks down to the rank of the taxon are checked
+    /// (for a taxon with 'no rank' or another rank outside the rank hierarchy, down to the rank
+    /// of its nearest canonical ancestor). empty if the taxon is not found
+    fn lineage_rank_gaps(&self, taxid: i32) -> Vec<Rank> {
+        let canonical_ranks = self.canonical_ranks();
+        // the position in canonical_ranks of the most specific rank to check
+        let last = match self.get_rank_enum_by_id(taxid) {
+            Some(ref rank) if canonical_ranks.contains(rank) => canonical_ranks.iter().position(|canonical_rank| canonical_rank == rank),
+            Some(ref rank) if rank.level().is_some() => canonical_ranks.iter()
+                .rposition(|canonical_rank| canonical_rank.level().is_some_and(|level| Some(level) <= rank.level())),
+            _ => self.effective_rank(taxid).and_then(|rank| canonical_level(&canonical_ranks, &rank))
+        };
+        match last {
+            Some(last) => canonical_ranks[..=last].iter()
+                .filter(|rank| self.get_ancestor_at_rank(taxid, &rank.to_string()).is_none())
+                .cloned()
+                .collect(),
+            None => vec![]
+        }
+    }
+
+    /// get_standard_lineage
+    ///
+    /// get the taxa at the STANDARD_RANKS (superkingdom, phylum, class, order, family, genus and
+    /// species) in the lineage of a taxon. the result always has an entry for each rank, with
+    /// None for the ranks that are missing from the lineage
+    fn get_standard_lineage(&self, taxid: i32) -> Option<Vec<(Rank, Option<i32>)>> {
+        if !self.contains_id(taxid) {
+            return None
+        }
+        Some(STANDARD_RANKS.iter()
+            .map(|rank| (rank.clone(), self.get_ancestor_at_rank(taxid, &rank.to_string())))
+            .collect())
+    }
+
+    /// format_standard_lineage
+    ///
+    /// format a lineage from get_standard_lineage as used by amplicon pipelines, e.g.
+    /// "k__Viruses;p__;c__;o__;f__;g__;s__Enterobacteria phage 933J"
+    fn format_standard_lineage(&self, lineage: &[(Rank, Option<i32>)]) -> String {
+        lineage.iter()
+            .map(|(rank, taxid)| format!("{}__{}",
+                                         rank.standard_prefix().unwrap_or(""),
+                                         taxid.and_then(|taxid| self.get_name_by_id(taxid)).unwrap_or_default()))
+            .collect::<Vec<String>>()
+            .join(";")
+    }
+
+    /// nearest_reference_distance
+    ///
+    /// find the reference taxon closest to the query taxon, returning the reference taxid and
+    /// the distance (in steps in the tree) from the query to its common ancestor with that reference
+    fn nearest_reference_distance(&self, query: i32, references: &[i32], only_canonical: bool) -> Option<(i32, i32)> {
+        let mut nearest: Option<(i32, i32)> = None;
+        for reference in references {
+            if let Some((distance, _)) = self.get_distance_to_common_ancestor_taxid(*reference, query, only_canonical) {
+                if distance == 0 {
+                    // the query is itself in the reference set, nothing can be closer
+                    return Some((*reference, 0))
+                }
+                if nearest.is_none_or(|(_, nearest_distance)| distance < nearest_distance) {
+                    nearest = Some((*reference, distance));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+// the start of a taxonomy cache file, the last byte is the version of the cache format and
+// must be changed whenever TaxonomyCache changes
+const CACHE_HEADER: &[u8] = b"NCBITAXCACHE\x0b";
+
+// the contents of a taxonomy cache file. everything is stored in vectors in the order of the nodes
+// in the arena (i.e. in NodeId order), because deserializing large HashMaps is slow. a NodeId is
+// stored as the (zero based) index of its node
+#[derive(Serialize, Deserialize)]
+struct TaxonomyCache {
+    taxids: Vec<i32>,
+    // the number of children of each node, and the children of all the nodes one after the other
+    child_counts: Vec<u32>,
+    children: Vec<u32>,
+    // the distinct ranks, and the index of the rank of each node in that list
+    rank_names: Vec<String>,
+    ranks: Vec<Option<u16>>,
+    names: Vec<Option<String>>,
+    common_names: Vec<Option<String>>,
+    division_ids: Vec<Option<i32>>,
+    division_names: Vec<(i32, String)>,
+    genetic_codes: Vec<Option<u8>>,
+    mito_genetic_codes: Vec<Option<u8>>,
+    // name_to_node is rebuilt from names (where a name used by more than one taxon maps to the
+    // last one), these are the names where that gives a different node
+    name_overrides: Vec<(String, u32)>,
+    merged: Vec<(i32, i32)>,
+    aliases: Vec<(i32, i32)>,
+    hosts: Vec<(i32, Vec<String>)>,
+    type_material: Vec<(i32, Vec<String>)>,
+    deleted: Vec<i32>,
+    load_issues: Vec<TaxonomyIssue>,
+    // the names of the canonical ranks, as they appear in the NCBI taxonomy database
+    canonical_ranks: Vec<String>
 }
 
 #[derive(Debug)]
@@ -90,7 +804,21 @@ pub struct NcbiFileTaxonomy {
     name_to_node: HashMap<String, NodeId>,
     id_to_node: HashMap<i32, NodeId>,
     id_to_name: HashMap<i32, String>,
-    id_to_rank: HashMap<i32, String>
+    id_to_common_name: HashMap<i32, String>,
+    id_to_rank: HashMap<i32, String>,
+    id_merged_from: HashMap<i32, i32>,
+    // user defined alias taxid to real taxid mapping, kept apart from the merged.dmp mapping
+    id_aliases: HashMap<i32, i32>,
+    deleted_ids: HashSet<i32>,
+    id_to_division_id: HashMap<i32, i32>,
+    division_names: HashMap<i32, String>,
+    id_to_genetic_code: HashMap<i32, u8>,
+    id_to_mito_genetic_code: HashMap<i32, u8>,
+    id_to_hosts: HashMap<i32, Vec<String>>,
+    id_to_type_material: HashMap<i32, Vec<String>>,
+    // problems found while loading the dump files, reported by validate
+    load_issues: Vec<TaxonomyIssue>,
+    canonical_ranks: Vec<Rank>
 }
 
 impl NcbiFileTaxonomy {
@@ -108,27 +836,83 @@ impl NcbiFileTaxonomy {
     /// let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/nodes.dmp", "data/names.dmp");
     /// ```
     pub fn from_ncbi_files(nodes_filename: &str, names_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).build()
+    }
+
+    // read the nodes.dmp and names.dmp files, as done by NcbiFileTaxonomyBuilder::build. the
+    // lines are only counted when there is a progress callback
+    fn from_dmp_files(nodes_filename: &str, names_filename: &str, progress: Option<&mut LineProgress>) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        match progress {
+            None => NcbiFileTaxonomy::from_dmp_lines(|f| for_each_line(nodes_filename, f),
+                                                     |f| for_each_line(names_filename, f)),
+            Some(progress) => {
+                // the files are read one after the other, but both closures need the progress
+                let progress = RefCell::new(progress);
+                let tree = NcbiFileTaxonomy::from_dmp_lines(|f| for_each_line(nodes_filename, |line| { progress.borrow_mut().line(); f(line) }),
+                                                            |f| for_each_line(names_filename, |line| { progress.borrow_mut().line(); f(line) }))?;
+                progress.into_inner().finish();
+                Ok(tree)
+            }
+        }
+    }
+
+    /// from_taxdump_archive
+    ///
+    /// Reads the `nodes.dmp` and `names.dmp` files straight from a `taxdump.tar.gz` archive as
+    /// downloaded from the NCBI Taxonomy FTP site, without extracting it first
+    pub fn from_taxdump_archive(path: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        // the archive is read once for each file so that the order of the files in it does not matter
+        NcbiFileTaxonomy::from_dmp_lines(|f| for_each_line_in_archive(path, "nodes.dmp", f),
+                                         |f| for_each_line_in_archive(path, "names.dmp", f))
+    }
+
+    // build the taxonomy from the lines of nodes.dmp and names.dmp, as passed to a callback by
+    // for_each_nodes_line and for_each_names_line
+    fn from_dmp_lines<N, M>(for_each_nodes_line: N, for_each_names_line: M) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError>
+        where N: FnOnce(&mut dyn FnMut(&str) -> Result<(), NcbiTaxonomyError>) -> Result<(), NcbiTaxonomyError>,
+              M: FnOnce(&mut dyn FnMut(&str) -> Result<(), NcbiTaxonomyError>) -> Result<(), NcbiTaxonomyError> {
         let mut child_ids_by_parent_id: HashMap<i32, Vec<i32>> = HashMap::new();
-        let mut id_to_rank = HashMap::new();
-        let nodes_file = File::open(nodes_filename)?;
-        for line_maybe in BufReader::new(nodes_file).lines() {
-            let line = line_maybe?;
-            let mut fields = line.split("\t|\t");
-            let id_str = fields.next().ok_or_else(|| NcbiTaxonomyError::NodeFileFormatError(line.clone()))?;
-            let parent_id_str = fields.next().ok_or_else(|| NcbiTaxonomyError::NodeFileFormatError(line.clone()))?;
-            let rank = fields.next().ok_or_else(|| NcbiTaxonomyError::NodeFileFormatError(line.clone()))?.to_string();
-            let id = id_str.parse::<i32>()?;
-            let parent_id = parent_id_str.parse::<i32>()?;
-            id_to_rank.insert(id, rank);
+        // the taxa with more than one line in nodes.dmp, with the parents of the extra lines
+        let mut extra_parent_ids: Vec<(i32, i32)> = Vec::new();
+        let mut id_to_rank: HashMap<i32, String> = HashMap::new();
+        let mut id_to_division_id = HashMap::new();
+        let mut id_to_genetic_code = HashMap::new();
+        let mut id_to_mito_genetic_code = HashMap::new();
+        // parsing the lines can be done in parallel, but building the tree cannot
+        for nodes_line in parse_nodes_lines(for_each_nodes_line)? {
+            let id = nodes_line.taxid;
+            let parent_id = nodes_line.parent_taxid;
+            match id_to_rank.entry(id) {
+                Entry::Occupied(_) => {
+                    // only the first line for a taxon is used
+                    extra_parent_ids.push((id, parent_id));
+                    continue
+                },
+                Entry::Vacant(entry) => { entry.insert(nodes_line.rank); }
+            }
+            if let Some(division_id) = nodes_line.division_id {
+                id_to_division_id.insert(id, division_id);
+            }
+            if let Some(genetic_code) = nodes_line.genetic_code {
+                id_to_genetic_code.insert(id, genetic_code);
+            }
+            if let Some(mito_genetic_code) = nodes_line.mito_genetic_code {
+                id_to_mito_genetic_code.insert(id, mito_genetic_code);
+            }
             if parent_id != id {  // this happens for the root node
                 // thanks to https://stackoverflow.com/questions/33243784/append-to-vector-as-value-of-hashmap/33243862
                 // for this way to get the existing entry or insert an empty list.
-                child_ids_by_parent_id.entry(parent_id).or_insert_with(Vec::new).push(id);
+                child_ids_by_parent_id.entry(parent_id).or_default().push(id);
             }
         }
 
+        // the children of each taxon are appended in order of taxid rather than in the order of
+        // nodes.dmp, so that traversals of the tree are in the same order whatever the file order
+        for child_ids in child_ids_by_parent_id.values_mut() {
+            child_ids.sort_unstable();
+        }
         let mut keys = child_ids_by_parent_id.keys().collect::<Vec<&i32>>();
-        keys.sort_unstable_by(|a, b| a.cmp(b));
+        keys.sort_unstable();
 
         let mut arena: Arena<i32> = Arena::new();
 
@@ -139,7 +923,7 @@ impl NcbiFileTaxonomy {
                 None => arena.new_node(*id),
             };
             id_to_node.insert(*id, node_id);
-            for child in child_ids_by_parent_id.get(&id).expect("ID not found in child_ids_by_parent_id") {
+            for child in child_ids_by_parent_id.get(id).expect("ID not found in child_ids_by_parent_id") {
                 let child_node_id = match id_to_node.get(child) {
                     Some(child_node_id) => *child_node_id,
                     None => arena.new_node(*child),
@@ -149,118 +933,954 @@ impl NcbiFileTaxonomy {
                 node_id.append(child_node_id, &mut arena).unwrap();  // might return Failure, in which case we panic!
             }
         }
+        // taxa that are neither a parent nor a child, e.g. the root (which is its own parent) in a
+        // taxonomy without other taxa, still need a node
+        for id in id_to_rank.keys() {
+            id_to_node.entry(*id).or_insert_with(|| arena.new_node(*id));
+        }
+
+        let mut load_issues = Vec::new();
+        let mut extra_parent_ids_by_id: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for (id, parent_id) in extra_parent_ids {
+            extra_parent_ids_by_id.entry(id).or_default().push(parent_id);
+        }
+        for (id, extra_parent_ids) in extra_parent_ids_by_id {
+            // the first parent is the one in the tree (or the taxon itself for the root)
+            let first_parent_id = id_to_node.get(&id).and_then(|node_id| arena[*node_id].parent())
+                .map(|parent_node_id| arena[parent_node_id].data)
+                .unwrap_or(id);
+            let mut parent_ids = vec![first_parent_id];
+            parent_ids.extend(extra_parent_ids.into_iter().filter(|parent_id| *parent_id != first_parent_id));
+            if parent_ids.len() > 1 {
+                load_issues.push(TaxonomyIssue::MultipleParents { taxid: id, parent_taxids: parent_ids });
+            }
+        }
+        // a cycle of parents would make the ancestors of its taxa endless
+        for (id, parent_id) in break_parent_cycles(&mut arena) {
+            load_issues.push(TaxonomyIssue::Cycle { taxid: id, parent_taxid: parent_id });
+        }
 
         // now its time to read the names_filename that maps names to IDs
         let mut name_to_node = HashMap::new();
         let mut id_to_name = HashMap::new();
-        let name_file = File::open(names_filename)?;
-        for line_maybe in BufReader::new(name_file).lines() {
-            let line = line_maybe?;
-            let fields = line.split("\t|\t").collect::<Vec<&str>>();
-            if fields[3].starts_with("scientific name") {
+        let mut id_to_common_name = HashMap::new();
+        // the plain names (name_txt) of taxa with a unique name, None for a plain name of more than one taxon
+        let mut plain_name_to_node: HashMap<String, Option<NodeId>> = HashMap::new();
+        for_each_names_line(&mut |line| {
+            let fields = split_dmp_line(line);
+            if fields.len() < 4 {
+                return Err(NcbiTaxonomyError::NamesFileFormatError(line.to_string()))
+            }
+            if fields[3].starts_with("genbank common name") {
+                let id = fields[0].parse::<i32>().map_err(NcbiTaxonomyError::ParseIntError)?;
+                // common names are not looked up by name, so they need no unique name
+                if id_to_node.contains_key(&id) {
+                    id_to_common_name.insert(id, fields[1].to_string());
+                }
+            } else if fields[3].starts_with("scientific name") {
                 let id_str = fields[0];
-                let id = id_str.parse::<i32>().or_else(|e| Err(NcbiTaxonomyError::ParseIntError(e)))?;
-                let name = if fields[2] != "" { fields[2].to_string() } else { fields[1].to_string() };
-                let node_id = id_to_node.get(&id).expect("ID not found in id_to_node");
+                let id = id_str.parse::<i32>().map_err(NcbiTaxonomyError::ParseIntError)?;
+                let name = if !fields[2].is_empty() { fields[2].to_string() } else { fields[1].to_string() };
+                let node_id = match id_to_node.get(&id) {
+                    Some(node_id) => node_id,
+                    None => {
+                        // names.dmp and nodes.dmp can come from different taxdump releases,
+                        // so skip names for taxa that are not in the tree rather than failing
+                        load_issues.push(TaxonomyIssue::UnknownNameTaxid { taxid: id, name });
+                        return Ok(())
+                    }
+                };
+                if !fields[2].is_empty() && fields[2] != fields[1] {
+                    plain_name_to_node.entry(fields[1].to_string())
+                        .and_modify(|plain_node_id| *plain_node_id = None)
+                        .or_insert(Some(*node_id));
+                }
                 id_to_name.insert(id, name.clone());
                 name_to_node.insert(name, *node_id);
             }
+            Ok(())
+        })?;
+        // a plain name also finds its taxon (e.g. "environmental samples" as well as "environmental
+        // samples <bacteriophages>"), unless it is the name of another taxon or of several taxa
+        for (name, node_id) in plain_name_to_node {
+            if let Some(node_id) = node_id {
+                name_to_node.entry(name).or_insert(node_id);
+            }
         }
 
-        let tree = NcbiFileTaxonomy { arena, name_to_node, id_to_node, id_to_name, id_to_rank };
+        let tree = NcbiFileTaxonomy { arena, name_to_node, id_to_node, id_to_name, id_to_common_name, id_to_rank, id_merged_from: HashMap::new(),
+                                     id_aliases: HashMap::new(), deleted_ids: HashSet::new(), id_to_division_id, division_names: HashMap::new(),
+                                     id_to_genetic_code, id_to_mito_genetic_code, id_to_hosts: HashMap::new(),
+                                     id_to_type_material: HashMap::new(), load_issues,
+                                     canonical_ranks: CANONICAL_RANKS.to_vec() };
         Ok(tree)
     }
 
-    pub fn save_to_sqlite(&self, db_url: Option<&str>) -> Result<SqliteConnection, ToSqliteError> {
-        // design of storing a tree in a relational DB inspired by:
-        // https://makandracards.com/makandra/45275-storing-trees-in-databases
-        use schema::taxonomy;
-        let connection = establish_connection(db_url);
-
-        embed_migrations!();
-        embedded_migrations::run(&connection)?;
-
-        connection.transaction::<_, ToSqliteError, _>(|| {
-            for (id, nodeid) in self.id_to_node.iter() {
-                let mut ancestors_vec = nodeid.ancestors(&self.arena).map(|nodeid| self.get_id_by_node(nodeid).unwrap().to_string()).collect::<Vec<String>>();
-                ancestors_vec.reverse();
-                let ancestors_string = ancestors_vec.join("/");
-                let name = match self.id_to_name.get(id) {
-                    Some(val) => val,
-                    None => { return Err(ToSqliteError::IdLookupError(id.to_string())) }
-                };
+    /// from_ncbi_files_with_merged
+    ///
+    /// Like from_ncbi_files, but also reads the `merged.dmp` file so that taxids that NCBI has
+    /// merged into other taxa are still found (e.g. in the output of classifiers built with an
+    /// older taxonomy)
+    pub fn from_ncbi_files_with_merged(nodes_filename: &str, names_filename: &str, merged_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).merged(merged_filename).build()
+    }
 
-                let taxon_record = NewTaxon {
-                    id,
-                    ancestry: match ancestors_string  {
-                        v if v == "1" => None,
-                        _ => Some(&ancestors_string[..])
-                    },
-                    name,
-                    rank: match self.id_to_rank.get(id) {
-                        Some(v) => Some(&v[..]),
-                        None => None
-                    }
+    /// from_ncbi_files_with_aliases
+    ///
+    /// Like from_ncbi_files, but also reads a tab separated file of `alias_taxid<TAB>real_taxid`
+    /// lines, so that user defined taxids (e.g. those of a custom database) are treated as the
+    /// real taxids in lookups. empty lines and lines starting with '#' are skipped
+    pub fn from_ncbi_files_with_aliases(nodes_filename: &str, names_filename: &str, aliases_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).with_aliases(aliases_filename).build()
+    }
 
-                };
-                diesel::insert_into(taxonomy::table)
-                    .values(&taxon_record   )
-                    .execute(&connection)?;
+    // read the alias taxid to real taxid mapping from a tab separated file
+    fn load_aliases(&mut self, aliases_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let id_aliases = &mut self.id_aliases;
+        for_each_line(aliases_filename, |line| {
+            if line.trim().is_empty() || line.starts_with('#') {
+                return Ok(())
             }
+            let fields: Vec<&str> = line.split('\t').map(|field| field.trim()).collect();
+            if fields.len() != 2 {
+                return Err(NcbiTaxonomyError::AliasFileFormatError(line.to_string()))
+            }
+            id_aliases.insert(fields[0].parse::<i32>()?, fields[1].parse::<i32>()?);
             Ok(())
-        })?;
-        Ok(connection)
+        })
     }
 
-    /// get_node_by_id
+    /// from_ncbi_files_with_delnodes
     ///
-    /// get a NodeId from a numeric NCBI Taxonomy ID
-    pub fn get_node_by_id(&self, id: i32) -> Option<&NodeId> {
-        self.id_to_node.get(&id)
+    /// Like from_ncbi_files, but also reads the `delnodes.dmp` file listing the taxids that NCBI
+    /// has deleted, so that is_deleted can tell a deleted taxid apart from one that never existed
+    pub fn from_ncbi_files_with_delnodes(nodes_filename: &str, names_filename: &str, delnodes_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).delnodes(delnodes_filename).build()
     }
 
-    /// traversal
+    // read the deleted taxids from delnodes.dmp
+    fn load_delnodes(&mut self, delnodes_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let deleted_ids = &mut self.deleted_ids;
+        for_each_line(delnodes_filename, |line| {
+            deleted_ids.insert(split_dmp_line(line)[0].parse::<i32>()?);
+            Ok(())
+        })
+    }
+
+    /// validate
     ///
-    /// traverse the tree nodes (in depth first order) from the node with a given NCBI Taxonomy ID
-    pub fn traversal(&self, from: i32) -> Option<Traverse<i32>> {
-        match self.get_node_by_id(from) {
-            Some(node_id) => Some(node_id.traverse(&self.arena)),
-            None => None
+    /// check the structure of the taxonomy, returning the problems found: cycles of parents, taxa
+    /// with more than one parent, parents missing from nodes.dmp and names for taxa missing from
+    /// nodes.dmp. the taxonomy can still be used, with the problematic links and names left out
+    pub fn validate(&self) -> Result<(), Vec<TaxonomyIssue>> {
+        let mut issues = self.load_issues.clone();
+        let mut undefined_ids: Vec<i32> = self.id_to_node.keys().filter(|taxid| !self.id_to_rank.contains_key(taxid)).cloned().collect();
+        undefined_ids.sort_unstable();
+        issues.extend(undefined_ids.into_iter().map(|taxid| TaxonomyIssue::UndefinedTaxon { taxid }));
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
         }
     }
 
-    /// get_id_by_node
+    /// with_canonical_ranks
     ///
-    /// get the NCBI Taxonomy ID held by the node with a given NodeId
-    pub fn get_id_by_node(&self, node_id: NodeId) -> Option<i32> {
-        match self.arena.get(node_id)  {
-            Some(node) => Some(node.data),
-            None => None
-        }
+    /// use another set of canonical ranks (e.g. with subspecies and strain added), from least to
+    /// most specific, instead of CANONICAL_RANKS. this changes what only_canonical and all the
+    /// other methods about canonical ranks count
+    pub fn with_canonical_ranks(mut self, canonical_ranks: &[Rank]) -> Self {
+        self.canonical_ranks = canonical_ranks.to_vec();
+        self
     }
-}
 
-impl NcbiTaxonomy for NcbiFileTaxonomy {
-    /// contains_id
+    /// is_deleted
     ///
-    /// check whether the taxonomy contains a (number) ID
-    fn contains_id(&self, id: i32) -> bool {
-        self.id_to_node.contains_key(&id)
+    /// check whether a taxid has been deleted from the NCBI Taxonomy. this is only known if
+    /// `delnodes.dmp` was loaded, otherwise it is always false
+    pub fn is_deleted(&self, taxid: i32) -> bool {
+        self.deleted_ids.contains(&taxid)
     }
 
-    /// contains_name
+    /// from_ncbi_files_with_division
     ///
-    /// check whether the taxonomy contains a node with the specified name
+    /// Like from_ncbi_files, but also reads the `division.dmp` file so that get_division gives
+    /// the names of the divisions (e.g. Phages) rather than their ids
+    pub fn from_ncbi_files_with_division(nodes_filename: &str, names_filename: &str, division_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).division(division_filename).build()
+    }
+
+    // read the division names from division.dmp
+    fn load_division(&mut self, division_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let division_names = &mut self.division_names;
+        for_each_line(division_filename, |line| {
+            let fields = split_dmp_line(line);
+            if fields.len() < 3 {
+                return Err(NcbiTaxonomyError::DivisionFileFormatError(line.to_string()))
+            }
+            division_names.insert(fields[0].parse::<i32>()?, fields[2].to_string());
+            Ok(())
+        })
+    }
+
+    /// get_division
     ///
-    /// **note:** the name used is what is reported as a the 'scientific name' in the NCBI Taxonomy database.
-    /// synonyms are currently not supported
-    fn contains_name(&self, name: &str) -> bool {
-        self.name_to_node.contains_key(name)
+    /// get the name of the NCBI division (e.g. Bacteria or Phages) of a taxon. if `division.dmp`
+    /// was not loaded, the division id (as a string) is returned instead
+    pub fn get_division(&self, taxid: i32) -> Option<String> {
+        let division_id = self.id_to_division_id.get(&self.resolve_taxid(taxid)?)?;
+        match self.division_names.get(division_id) {
+            Some(name) => Some(name.clone()),
+            None => Some(division_id.to_string())
+        }
     }
 
-    /// is_descendant
+    /// from_ncbi_files_with_host
     ///
-    /// check if a certain named node is a descendant of another named named
+    /// Like from_ncbi_files, but also reads the `host.dmp` file (part of the new taxdump) that
+    /// lists the potential hosts of taxa, mostly viruses
+    pub fn from_ncbi_files_with_host(nodes_filename: &str, names_filename: &str, host_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).host(host_filename).build()
+    }
+
+    // read the comma separated potential hosts of taxa from host.dmp
+    fn load_host(&mut self, host_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let id_to_hosts = &mut self.id_to_hosts;
+        for_each_line(host_filename, |line| {
+            let fields = split_dmp_line(line);
+            if fields.len() < 2 {
+                return Err(NcbiTaxonomyError::HostFileFormatError(line.to_string()))
+            }
+            let hosts = fields[1].split(',').map(|host| host.trim()).filter(|host| !host.is_empty()).map(|host| host.to_string());
+            id_to_hosts.entry(fields[0].parse::<i32>()?).or_default().extend(hosts);
+            Ok(())
+        })
+    }
+
+    /// get_hosts
+    ///
+    /// get the potential hosts of a taxon (e.g. bacteria for a phage, or vertebrates and human)
+    /// from host.dmp. None if the taxon is not found or host.dmp lists no hosts for it
+    pub fn get_hosts(&self, taxid: i32) -> Option<Vec<String>> {
+        self.id_to_hosts.get(&self.resolve_taxid(taxid)?).cloned()
+    }
+
+    /// from_ncbi_files_with_type_material
+    ///
+    /// Like from_ncbi_files, but also reads the `typematerial.dmp` file (part of the new taxdump)
+    /// that lists the type strains and specimens of species
+    pub fn from_ncbi_files_with_type_material(nodes_filename: &str, names_filename: &str, type_material_filename: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        NcbiFileTaxonomyBuilder::default().nodes(nodes_filename).names(names_filename).type_material(type_material_filename).build()
+    }
+
+    // read the type material identifiers (the fourth field, after the taxon name and type of
+    // material) from typematerial.dmp
+    fn load_type_material(&mut self, type_material_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let id_to_type_material = &mut self.id_to_type_material;
+        for_each_line(type_material_filename, |line| {
+            let fields = split_dmp_line(line);
+            if fields.len() < 4 {
+                return Err(NcbiTaxonomyError::TypeMaterialFileFormatError(line.to_string()))
+            }
+            id_to_type_material.entry(fields[0].parse::<i32>()?).or_default().push(fields[3].to_string());
+            Ok(())
+        })
+    }
+
+    /// get_type_material
+    ///
+    /// get the identifiers of the type material of a taxon (e.g. the culture collection numbers
+    /// of the type strain of a species, like ATCC 11775) from typematerial.dmp, in the order they
+    /// are listed. None if the taxon is not found or has no type material
+    pub fn get_type_material(&self, taxid: i32) -> Option<Vec<String>> {
+        self.id_to_type_material.get(&self.resolve_taxid(taxid)?).cloned()
+    }
+
+    /// get_genetic_code
+    ///
+    /// get the id of the NCBI genetic code (translation table) used by a taxon
+    pub fn get_genetic_code(&self, taxid: i32) -> Option<u8> {
+        self.id_to_genetic_code.get(&self.resolve_taxid(taxid)?).cloned()
+    }
+
+    /// get_mito_genetic_code
+    ///
+    /// get the id of the NCBI genetic code (translation table) used by the mitochondria of a taxon
+    pub fn get_mito_genetic_code(&self, taxid: i32) -> Option<u8> {
+        self.id_to_mito_genetic_code.get(&self.resolve_taxid(taxid)?).cloned()
+    }
+
+    // read the old taxid to new taxid mapping from merged.dmp
+    fn load_merged(&mut self, merged_filename: &str) -> Result<(), NcbiTaxonomyError> {
+        let id_merged_from = &mut self.id_merged_from;
+        for_each_line(merged_filename, |line| {
+            let fields = split_dmp_line(line);
+            if fields.len() < 2 {
+                return Err(NcbiTaxonomyError::MergedFileFormatError(line.to_string()))
+            }
+            id_merged_from.insert(fields[0].parse::<i32>()?, fields[1].parse::<i32>()?);
+            Ok(())
+        })
+    }
+
+    /// save_cache
+    ///
+    /// save the taxonomy to a binary cache file that is much faster to load (with load_cache)
+    /// than parsing the .dmp files again
+    pub fn save_cache(&self, path: &str) -> Result<(), NcbiTaxonomyError> {
+        // the node at (zero based) index i in the arena has NodeId::new(i)
+        let node_count = self.arena.count();
+        let node_index: HashMap<NodeId, u32> = (0..node_count).map(|index| (NodeId::new(index), index as u32)).collect();
+        let mut cache = TaxonomyCache {
+            taxids: Vec::with_capacity(node_count),
+            child_counts: Vec::with_capacity(node_count),
+            children: Vec::with_capacity(node_count),
+            rank_names: Vec::new(),
+            ranks: Vec::with_capacity(node_count),
+            names: Vec::with_capacity(node_count),
+            common_names: Vec::with_capacity(node_count),
+            division_ids: Vec::with_capacity(node_count),
+            genetic_codes: Vec::with_capacity(node_count),
+            mito_genetic_codes: Vec::with_capacity(node_count),
+            division_names: self.division_names.iter().map(|(division_id, name)| (*division_id, name.clone())).collect(),
+            name_overrides: Vec::new(),
+            merged: self.id_merged_from.iter().map(|(old_taxid, taxid)| (*old_taxid, *taxid)).collect(),
+            aliases: self.id_aliases.iter().map(|(alias_taxid, taxid)| (*alias_taxid, *taxid)).collect(),
+            hosts: self.id_to_hosts.iter().map(|(taxid, hosts)| (*taxid, hosts.clone())).collect(),
+            type_material: self.id_to_type_material.iter().map(|(taxid, type_material)| (*taxid, type_material.clone())).collect(),
+            deleted: self.deleted_ids.iter().cloned().collect(),
+            load_issues: self.load_issues.clone(),
+            canonical_ranks: self.canonical_ranks.iter().map(|rank| rank.to_string()).collect()
+        };
+        let mut rank_index: HashMap<&str, u16> = HashMap::new();
+        let mut last_node_with_name: HashMap<&str, u32> = HashMap::new();
+        for index in 0..node_count {
+            let node_id = NodeId::new(index);
+            let taxid = self.arena[node_id].data;
+            cache.taxids.push(taxid);
+            let child_count = cache.children.len();
+            cache.children.extend(node_id.children(&self.arena).map(|child| node_index[&child]));
+            cache.child_counts.push((cache.children.len() - child_count) as u32);
+            let rank_names = &mut cache.rank_names;
+            let rank = self.id_to_rank.get(&taxid).map(|rank| {
+                *rank_index.entry(rank).or_insert_with(|| {
+                    rank_names.push(rank.clone());
+                    (rank_names.len() - 1) as u16
+                })
+            });
+            cache.ranks.push(rank);
+            let name = self.id_to_name.get(&taxid);
+            if let Some(name) = name {
+                last_node_with_name.insert(name, index as u32);
+            }
+            cache.names.push(name.cloned());
+            cache.common_names.push(self.id_to_common_name.get(&taxid).cloned());
+            cache.division_ids.push(self.id_to_division_id.get(&taxid).cloned());
+            cache.genetic_codes.push(self.id_to_genetic_code.get(&taxid).cloned());
+            cache.mito_genetic_codes.push(self.id_to_mito_genetic_code.get(&taxid).cloned());
+        }
+        for (name, node_id) in self.name_to_node.iter() {
+            if last_node_with_name.get(name.as_str()) != Some(&node_index[node_id]) {
+                cache.name_overrides.push((name.clone(), node_index[node_id]));
+            }
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CACHE_HEADER)?;
+        bincode::serialize_into(&mut writer, &cache)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// load_cache
+    ///
+    /// load a taxonomy saved with save_cache. a cache written by a different version of the cache
+    /// format gives a NcbiTaxonomyError::StaleCache error, in which case the taxonomy should be
+    /// read from the .dmp files again, as does a cache whose contents are inconsistent (e.g. a
+    /// child or rank index out of range). the nodes are saved in NodeId order and recreated in
+    /// the same order when loading, so every taxon keeps the same NodeId
+    pub fn load_cache(path: &str) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        let contents = std::fs::read(path)?;
+        if !contents.starts_with(CACHE_HEADER) {
+            return Err(NcbiTaxonomyError::StaleCache(path.to_string()))
+        }
+        let cache: TaxonomyCache = bincode::deserialize(&contents[CACHE_HEADER.len()..])?;
+        drop(contents);
+        let stale_cache = || NcbiTaxonomyError::StaleCache(path.to_string());
+
+        let node_count = cache.taxids.len();
+        if cache.child_counts.len() != node_count {
+            return Err(stale_cache())
+        }
+        let mut arena: Arena<i32> = Arena::new();
+        let mut id_to_node = HashMap::with_capacity(node_count);
+        for taxid in &cache.taxids {
+            id_to_node.insert(*taxid, arena.new_node(*taxid));
+        }
+        let mut children = cache.children.iter();
+        for (index, child_count) in cache.child_counts.iter().enumerate() {
+            for child in children.by_ref().take(*child_count as usize) {
+                let child = *child as usize;
+                // every node has at most one parent, so a child can only be appended once
+                if child >= node_count || arena[NodeId::new(child)].parent().is_some() {
+                    return Err(stale_cache())
+                }
+                NodeId::new(index).append(NodeId::new(child), &mut arena).map_err(|_| stale_cache())?;
+            }
+        }
+
+        let mut id_to_rank = HashMap::with_capacity(node_count);
+        for (taxid, rank) in cache.taxids.iter().zip(cache.ranks) {
+            if let Some(rank) = rank {
+                let rank = cache.rank_names.get(rank as usize).ok_or_else(stale_cache)?;
+                id_to_rank.insert(*taxid, rank.clone());
+            }
+        }
+
+        let mut id_to_name = HashMap::with_capacity(node_count);
+        let mut name_to_node = HashMap::with_capacity(node_count);
+        for (index, (taxid, name)) in cache.taxids.iter().zip(cache.names).enumerate() {
+            if let Some(name) = name {
+                id_to_name.insert(*taxid, name.clone());
+                name_to_node.insert(name, NodeId::new(index));
+            }
+        }
+        for (name, index) in cache.name_overrides {
+            if index as usize >= node_count {
+                return Err(stale_cache())
+            }
+            name_to_node.insert(name, NodeId::new(index as usize));
+        }
+
+        let id_to_common_name = cache.taxids.iter().zip(cache.common_names)
+            .filter_map(|(taxid, common_name)| common_name.map(|common_name| (*taxid, common_name)))
+            .collect();
+        let id_merged_from = cache.merged.into_iter().collect();
+        let id_aliases = cache.aliases.into_iter().collect();
+        let id_to_hosts = cache.hosts.into_iter().collect();
+        let id_to_type_material = cache.type_material.into_iter().collect();
+        let id_to_division_id = cache.taxids.iter().zip(cache.division_ids)
+            .filter_map(|(taxid, division_id)| division_id.map(|division_id| (*taxid, division_id)))
+            .collect();
+        let id_to_genetic_code = cache.taxids.iter().zip(cache.genetic_codes)
+            .filter_map(|(taxid, genetic_code)| genetic_code.map(|genetic_code| (*taxid, genetic_code)))
+            .collect();
+        let id_to_mito_genetic_code = cache.taxids.iter().zip(cache.mito_genetic_codes)
+            .filter_map(|(taxid, genetic_code)| genetic_code.map(|genetic_code| (*taxid, genetic_code)))
+            .collect();
+        let deleted_ids = cache.deleted.into_iter().collect();
+        let division_names = cache.division_names.into_iter().collect();
+        let canonical_ranks = cache.canonical_ranks.iter().map(|rank| Rank::from(rank.as_str())).collect();
+        Ok(NcbiFileTaxonomy { arena, name_to_node, id_to_node, id_to_name, id_to_common_name, id_to_rank, id_merged_from, id_aliases, deleted_ids,
+                              id_to_division_id, division_names, id_to_genetic_code, id_to_mito_genetic_code, id_to_hosts,
+                              id_to_type_material, load_issues: cache.load_issues, canonical_ranks })
+    }
+
+    /// resolve_merged
+    ///
+    /// get the taxid currently used for a taxid, following the merged.dmp mapping if the taxid
+    /// has been merged into another taxon. returns None if the taxid is not known
+    pub fn resolve_merged(&self, taxid: i32) -> Option<i32> {
+        if self.id_to_node.contains_key(&taxid) {
+            Some(taxid)
+        } else {
+            self.id_merged_from.get(&taxid).filter(|new_taxid| self.id_to_node.contains_key(new_taxid)).cloned()
+        }
+    }
+
+    /// resolve_alias
+    ///
+    /// get the real taxid for a taxid, following the user defined aliases (see
+    /// from_ncbi_files_with_aliases) if the taxid is an alias. returns None if the taxid is not known
+    pub fn resolve_alias(&self, taxid: i32) -> Option<i32> {
+        if self.id_to_node.contains_key(&taxid) {
+            Some(taxid)
+        } else {
+            self.id_aliases.get(&taxid).filter(|real_taxid| self.id_to_node.contains_key(real_taxid)).cloned()
+        }
+    }
+
+    /// get_aliases
+    ///
+    /// get the user defined aliases of a taxid (the reverse of resolve_alias), sorted
+    pub fn get_aliases(&self, taxid: i32) -> Vec<i32> {
+        let mut aliases: Vec<i32> = self.id_aliases.iter()
+            .filter(|(_, real_taxid)| **real_taxid == taxid)
+            .map(|(alias_taxid, _)| *alias_taxid)
+            .collect();
+        aliases.sort_unstable();
+        aliases
+    }
+
+    // get the taxid in the taxonomy for a taxid, following merged taxids and then aliases
+    fn resolve_taxid(&self, taxid: i32) -> Option<i32> {
+        self.resolve_merged(taxid).or_else(|| self.resolve_alias(taxid))
+    }
+
+    // like get_node_by_id, but following merged taxids and aliases
+    fn get_node_by_id_or_merged(&self, taxid: i32) -> Option<&NodeId> {
+        self.id_to_node.get(&self.resolve_taxid(taxid)?)
+    }
+
+    /// save_to_sqlite
+    ///
+    /// save the taxonomy to the SQLite database at db_url (or DATABASE_URL if db_url is None),
+    /// returning an error if the database cannot be opened
+    pub fn save_to_sqlite(&self, db_url: Option<&str>) -> Result<SqliteConnection, ToSqliteError> {
+        let connection = try_establish_connection(db_url)?;
+        self.save_to_sqlite_connection(&connection)?;
+        Ok(connection)
+    }
+
+    /// save_to_sqlite_connection
+    ///
+    /// save the taxonomy to an already open SQLite database (e.g. an in-memory one),
+    /// creating the taxonomy table if needed
+    pub fn save_to_sqlite_connection(&self, connection: &SqliteConnection) -> Result<(), ToSqliteError> {
+        embedded_migrations::run(connection)?;
+
+        connection.transaction::<_, ToSqliteError, _>(|| {
+            // diesel inserts the rows of a batch one statement at a time on SQLite, so the rows
+            // are inserted with one statement for each chunk of taxa instead
+            let ancestries = self.ancestries();
+            let mut taxa: Vec<(&i32, &NodeId)> = self.id_to_node.iter().collect();
+            taxa.sort_unstable_by_key(|(id, _)| **id);
+            for chunk in taxa.chunks(SQLITE_MAX_VARIABLES / queries::NEW_TAXON_COLUMNS) {
+                let taxon_records = chunk.iter()
+                    .map(|(id, nodeid)| self.new_taxon(id, **nodeid, &ancestries[*id]))
+                    .collect::<Result<Vec<NewTaxon>, ToSqliteError>>()?;
+                queries::insert_taxa(&taxon_records).execute(connection)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// update_sqlite
+    ///
+    /// like save_to_sqlite, but for a database that already holds a (possibly older) taxonomy
+    pub fn update_sqlite(&self, db_url: Option<&str>) -> Result<SqliteUpdateCounts, ToSqliteError> {
+        let connection = try_establish_connection(db_url)?;
+        self.update_sqlite_connection(&connection)
+    }
+
+    /// update_sqlite_connection
+    ///
+    /// make the taxonomy in an already open SQLite database match this taxonomy: new taxa are
+    /// inserted, changed taxa are updated and taxa that are no longer in this taxonomy (e.g. those
+    /// listed in delnodes.dmp or merged.dmp) are deleted. all changes are made in one transaction
+    pub fn update_sqlite_connection(&self, connection: &SqliteConnection) -> Result<SqliteUpdateCounts, ToSqliteError> {
+        use schema::taxonomy;
+
+        embedded_migrations::run(connection)?;
+
+        connection.transaction::<_, ToSqliteError, _>(|| {
+            let existing: HashMap<i32, Taxon> = taxonomy::table.load::<Taxon>(connection)?
+                .into_iter()
+                .map(|taxon| (taxon.id, taxon))
+                .collect();
+            let mut counts = SqliteUpdateCounts::default();
+            self.for_each_new_taxon(|taxon_record| {
+                match existing.get(taxon_record.id) {
+                    Some(taxon) if taxon.ancestry.as_deref() == taxon_record.ancestry && taxon.name == taxon_record.name
+                        && taxon.rank == taxon_record.rank && taxon.parent_id == taxon_record.parent_id
+                        && taxon.common_name.as_deref() == taxon_record.common_name => {
+                        counts.unchanged += 1;
+                        return Ok(())
+                    },
+                    Some(_) => counts.updated += 1,
+                    None => counts.inserted += 1
+                }
+                // REPLACE also removes a row that holds this name under another taxid. if that
+                // taxon is still in the taxonomy its row is written again with its new name
+                diesel::replace_into(taxonomy::table)
+                    .values(&taxon_record)
+                    .execute(connection)?;
+                Ok(())
+            })?;
+
+            let removed: Vec<i32> = existing.keys().filter(|taxid| !self.id_to_node.contains_key(taxid)).cloned().collect();
+            for chunk in removed.chunks(SQLITE_MAX_VARIABLES) {
+                diesel::delete(taxonomy::table.filter(taxonomy::id.eq_any(chunk)))
+                    .execute(connection)?;
+            }
+            counts.deleted = removed.len();
+            Ok(counts)
+        })
+    }
+
+    // call f with the row of the taxonomy table for each taxon
+    fn for_each_new_taxon<F>(&self, mut f: F) -> Result<(), ToSqliteError>
+        where F: FnMut(NewTaxon) -> Result<(), ToSqliteError> {
+        let ancestries = self.ancestries();
+        for (id, nodeid) in self.id_to_node.iter() {
+            f(self.new_taxon(id, *nodeid, &ancestries[id])?)?;
+        }
+        Ok(())
+    }
+
+    // the ancestry column of the taxonomy table (see queries::format_ancestry) for each taxon.
+    // rather than following the parents of every taxon up to the root, the tree is walked once
+    // from each root, extending the ancestry of a taxon for each of its children
+    fn ancestries(&self) -> HashMap<i32, String> {
+        // design of storing a tree in a relational DB inspired by:
+        // https://makandracards.com/makandra/45275-storing-trees-in-databases
+        let mut ancestries = HashMap::with_capacity(self.id_to_node.len());
+        let mut ancestry = String::from("/");
+        // the length of ancestry before each taxon on the current path was added to it
+        let mut prefix_lengths = Vec::new();
+        for root_node_id in self.id_to_node.values().filter(|node_id| self.arena[**node_id].parent().is_none()) {
+            for node_edge in root_node_id.traverse(&self.arena) {
+                match node_edge {
+                    NodeEdge::Start(node_id) => {
+                        let id = self.arena[node_id].data;
+                        prefix_lengths.push(ancestry.len());
+                        ancestry.push_str(&id.to_string());
+                        ancestry.push('/');
+                        ancestries.insert(id, ancestry.clone());
+                    },
+                    NodeEdge::End(_) => ancestry.truncate(prefix_lengths.pop().unwrap())
+                }
+            }
+        }
+        ancestries
+    }
+
+    // the row of the taxonomy table for a taxon, given its ancestry
+    fn new_taxon<'a>(&'a self, id: &'a i32, nodeid: NodeId, ancestry: &'a str) -> Result<NewTaxon<'a>, ToSqliteError> {
+        let name = match self.id_to_name.get(id) {
+            Some(val) => val,
+            None => { return Err(ToSqliteError::IdLookupError(id.to_string())) }
+        };
+
+        Ok(NewTaxon {
+            id,
+            ancestry: Some(ancestry),
+            name,
+            rank: match self.id_to_rank.get(id) {
+                Some(v) => &v[..],
+                None => "no rank"
+            },
+            parent_id: self.arena[nodeid].parent().map(|parent_nodeid| self.get_id_by_node(parent_nodeid).unwrap()),
+            common_name: self.id_to_common_name.get(id).map(|common_name| common_name.as_str())
+        })
+    }
+
+    /// get_node_by_id
+    ///
+    /// get a NodeId from a numeric NCBI Taxonomy ID
+    pub fn get_node_by_id(&self, id: i32) -> Option<&NodeId> {
+        self.id_to_node.get(&id)
+    }
+
+    /// traversal
+    ///
+    /// traverse the tree nodes (in depth first order) from the node with a given NCBI Taxonomy ID.
+    /// the children of each node are visited in order of taxid, so the order is reproducible
+    pub fn traversal(&self, from: i32) -> Option<Traverse<'_, i32>> {
+        self.get_node_by_id(from).map(|node_id| node_id.traverse(&self.arena))
+    }
+
+    /// traversal_with_depth
+    ///
+    /// like traversal, but skip the nodes more than max_depth levels below the starting node
+    /// (max_depth 0 only yields the starting node itself)
+    pub fn traversal_with_depth(&self, from: i32, max_depth: usize) -> Option<impl Iterator<Item = NodeEdge<NodeId>> + '_> {
+        // depth of the next node to start, relative to the starting node
+        let mut depth = 0;
+        Some(self.traversal(from)?.filter(move |node_edge| {
+            match node_edge {
+                NodeEdge::Start(_) => {
+                    depth += 1;
+                    depth <= max_depth + 1
+                },
+                NodeEdge::End(_) => {
+                    depth -= 1;
+                    depth <= max_depth
+                }
+            }
+        }))
+    }
+
+    /// ancestors_of
+    ///
+    /// iterate over the taxids of the ancestors of a taxon, from its parent up to the root.
+    /// the taxon itself is not included
+    pub fn ancestors_of(&self, taxid: i32) -> Option<impl Iterator<Item = i32> + '_> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        Some(node_id.ancestors(&self.arena).skip(1).map(move |node_id| self.get_id_by_node(node_id).unwrap()))
+    }
+
+    /// rank_consistency_report
+    ///
+    /// find the taxa whose canonical rank is not more specific than that of their nearest canonical
+    /// ancestor (e.g. a family below a genus). the issues are sorted by taxid
+    pub fn rank_consistency_report(&self) -> Vec<RankIssue> {
+        let rank_level = |taxid: i32| {
+            self.id_to_rank.get(&taxid)
+                .and_then(|rank| canonical_level(&self.canonical_ranks, rank))
+        };
+        let mut issues = Vec::new();
+        for root in self.id_to_node.values().filter(|node_id| self.arena[**node_id].parent().is_none()) {
+            // the nearest canonical ancestors of the current node, as (taxid, rank level)
+            let mut canonical_ancestors: Vec<(i32, usize)> = Vec::new();
+            for node_edge in root.traverse(&self.arena) {
+                match node_edge {
+                    NodeEdge::Start(node_id) => {
+                        let taxid = self.get_id_by_node(node_id).unwrap();
+                        if let Some(level) = rank_level(taxid) {
+                            if let Some((ancestor_taxid, ancestor_level)) = canonical_ancestors.last() {
+                                if *ancestor_level >= level {
+                                    issues.push(RankIssue {
+                                        taxid,
+                                        rank: self.id_to_rank[&taxid].clone(),
+                                        ancestor_taxid: *ancestor_taxid,
+                                        ancestor_rank: self.id_to_rank[ancestor_taxid].clone()
+                                    });
+                                }
+                            }
+                            canonical_ancestors.push((taxid, level));
+                        }
+                    },
+                    NodeEdge::End(node_id) => {
+                        let taxid = self.get_id_by_node(node_id).unwrap();
+                        if canonical_ancestors.last().map(|(ancestor_taxid, _)| *ancestor_taxid) == Some(taxid) {
+                            canonical_ancestors.pop();
+                        }
+                    }
+                }
+            }
+        }
+        issues.sort_unstable_by_key(|issue| issue.taxid);
+        issues
+    }
+
+    // build a new taxonomy from the taxa below (and including) root_nodes that keep accepts. keep
+    // has to accept the parent of every taxon it accepts (below the roots), as the descendants of a
+    // taxon that is not kept are left out. the names, ranks, divisions and genetic codes of the
+    // taxa are copied
+    fn subtaxonomy<F: Fn(i32) -> bool>(&self, root_nodes: &[NodeId], keep: F) -> NcbiFileTaxonomy {
+        let mut arena: Arena<i32> = Arena::new();
+        let mut id_to_node: HashMap<i32, NodeId> = HashMap::new();
+        for root_node in root_nodes {
+            // descendants lists each taxon after its parent
+            for node_id in root_node.descendants(&self.arena) {
+                let taxid = self.arena[node_id].data;
+                if !keep(taxid) {
+                    continue
+                }
+                let parent_node_id = if node_id == *root_node {
+                    None
+                } else {
+                    match self.arena[node_id].parent().and_then(|parent| id_to_node.get(&self.arena[parent].data)) {
+                        Some(parent_node_id) => Some(*parent_node_id),
+                        None => continue  // the parent was not kept
+                    }
+                };
+                let new_node_id = arena.new_node(taxid);
+                if let Some(parent_node_id) = parent_node_id {
+                    parent_node_id.append(new_node_id, &mut arena).unwrap();
+                }
+                id_to_node.insert(taxid, new_node_id);
+            }
+        }
+
+        let copy_kept = |map: &HashMap<i32, String>| -> HashMap<i32, String> {
+            map.iter().filter(|(taxid, _)| id_to_node.contains_key(taxid)).map(|(taxid, value)| (*taxid, value.clone())).collect()
+        };
+        let id_to_name = copy_kept(&self.id_to_name);
+        let id_to_common_name = copy_kept(&self.id_to_common_name);
+        let id_to_rank = copy_kept(&self.id_to_rank);
+        // this keeps the plain names of the kept taxa with a unique name as well
+        let name_to_node = self.name_to_node.iter()
+            .filter_map(|(name, node_id)| id_to_node.get(&self.arena[*node_id].data).map(|new_node_id| (name.clone(), *new_node_id)))
+            .collect();
+        let id_merged_from = self.id_merged_from.iter()
+            .filter(|(_, new_taxid)| id_to_node.contains_key(new_taxid))
+            .map(|(taxid, new_taxid)| (*taxid, *new_taxid))
+            .collect();
+        let id_aliases = self.id_aliases.iter()
+            .filter(|(_, real_taxid)| id_to_node.contains_key(real_taxid))
+            .map(|(alias_taxid, real_taxid)| (*alias_taxid, *real_taxid))
+            .collect();
+        let id_to_division_id = self.id_to_division_id.iter()
+            .filter(|(taxid, _)| id_to_node.contains_key(taxid))
+            .map(|(taxid, division_id)| (*taxid, *division_id))
+            .collect();
+        let id_to_genetic_code = self.id_to_genetic_code.iter()
+            .filter(|(taxid, _)| id_to_node.contains_key(taxid))
+            .map(|(taxid, genetic_code)| (*taxid, *genetic_code))
+            .collect();
+        let id_to_mito_genetic_code = self.id_to_mito_genetic_code.iter()
+            .filter(|(taxid, _)| id_to_node.contains_key(taxid))
+            .map(|(taxid, genetic_code)| (*taxid, *genetic_code))
+            .collect();
+        let id_to_hosts = self.id_to_hosts.iter()
+            .filter(|(taxid, _)| id_to_node.contains_key(taxid))
+            .map(|(taxid, hosts)| (*taxid, hosts.clone()))
+            .collect();
+        let id_to_type_material = self.id_to_type_material.iter()
+            .filter(|(taxid, _)| id_to_node.contains_key(taxid))
+            .map(|(taxid, type_material)| (*taxid, type_material.clone()))
+            .collect();
+        NcbiFileTaxonomy { arena, name_to_node, id_to_node, id_to_name, id_to_common_name, id_to_rank, id_merged_from, id_aliases,
+                           deleted_ids: self.deleted_ids.clone(), id_to_division_id, division_names: self.division_names.clone(),
+                           id_to_genetic_code, id_to_mito_genetic_code, id_to_hosts, id_to_type_material,
+                           load_issues: Vec::new(),
+                           canonical_ranks: self.canonical_ranks.clone() }
+    }
+
+    /// prune_to_taxids
+    ///
+    /// build a reduced taxonomy that only contains the given taxa and their ancestors, e.g. to
+    /// export the tree of a set of organisms of interest. gives NcbiTaxonomyError::NotFound if
+    /// one of the taxids is not in the taxonomy
+    pub fn prune_to_taxids(&self, taxids: &[i32]) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        let mut kept_taxids = HashSet::new();
+        let mut root_nodes = Vec::new();
+        for taxid in taxids {
+            let node_id = self.get_node_by_id_or_merged(*taxid).ok_or_else(|| NcbiTaxonomyError::NotFound(taxid.to_string()))?;
+            for ancestor_node_id in node_id.ancestors(&self.arena) {
+                if !kept_taxids.insert(self.arena[ancestor_node_id].data) {
+                    // the rest of the lineage has already been added
+                    break
+                }
+                if self.arena[ancestor_node_id].parent().is_none() {
+                    root_nodes.push(ancestor_node_id);
+                }
+            }
+        }
+        Ok(self.subtaxonomy(&root_nodes, |taxid| kept_taxids.contains(&taxid)))
+    }
+
+    /// extract_subtree
+    ///
+    /// build a taxonomy of a taxon and all its descendants, with that taxon as the root (without
+    /// a parent), e.g. to save all the Viruses to their own SQLite database. None if the taxon is
+    /// not found
+    pub fn extract_subtree(&self, root_taxid: i32) -> Option<NcbiFileTaxonomy> {
+        let root_node_id = self.get_node_by_id_or_merged(root_taxid)?;
+        Some(self.subtaxonomy(&[*root_node_id], |_| true))
+    }
+
+    /// to_json_tree
+    ///
+    /// build a TaxonNode tree (which can be serialized with serde) of a taxon and all its descendants.
+    /// note that the tree from the root of the taxonomy includes every taxon, which produces a very
+    /// large document, so rather serialize the subtree of a more specific taxon
+    pub fn to_json_tree(&self, root_taxid: i32) -> Option<TaxonNode> {
+        // the TaxonNodes on the path from the root to the current node, whose children are still being built
+        let mut stack: Vec<TaxonNode> = Vec::new();
+        for node_edge in self.traversal(root_taxid)? {
+            match node_edge {
+                NodeEdge::Start(node_id) => {
+                    let taxid = self.get_id_by_node(node_id).unwrap();
+                    stack.push(TaxonNode {
+                        taxid,
+                        name: self.id_to_name.get(&taxid).cloned().unwrap_or_default(),
+                        rank: self.id_to_rank.get(&taxid).cloned().unwrap_or_default(),
+                        children: Vec::new()
+                    });
+                },
+                NodeEdge::End(_) => {
+                    let taxon_node = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(taxon_node),
+                        None => return Some(taxon_node)
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// export_tsv
+    ///
+    /// write the taxonomy as a table of tab separated values, with a header line and one row per
+    /// taxon with the columns taxid, parent_taxid (empty for the root), rank, name and lineage (the
+    /// names of the taxa from the root to the taxon, separated by ;). the taxa are written in depth
+    /// first order while traversing the tree, so only the lineage of the current taxon is kept
+    pub fn export_tsv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "taxid\tparent_taxid\trank\tname\tlineage")?;
+        // a taxonomy built with prune_to_taxids can have more than one root
+        let mut roots = self.id_to_node.iter()
+            .filter(|(_, node_id)| self.arena[**node_id].parent().is_none())
+            .map(|(taxid, _)| *taxid)
+            .collect::<Vec<i32>>();
+        roots.sort_unstable();
+        for root in roots {
+            // the taxids and names of the taxa from the root to the current taxon
+            let mut lineage: Vec<(i32, &str)> = Vec::new();
+            for node_edge in self.traversal(root).unwrap() {
+                match node_edge {
+                    NodeEdge::Start(node_id) => {
+                        let taxid = self.get_id_by_node(node_id).unwrap();
+                        let parent_taxid = lineage.last().map(|(parent_taxid, _)| parent_taxid.to_string()).unwrap_or_default();
+                        lineage.push((taxid, self.id_to_name.get(&taxid).map(|name| &name[..]).unwrap_or("")));
+                        writeln!(writer, "{}\t{}\t{}\t{}\t{}", taxid, parent_taxid,
+                                 self.id_to_rank.get(&taxid).map(|rank| &rank[..]).unwrap_or("no rank"),
+                                 lineage.last().unwrap().1,
+                                 lineage.iter().map(|(_, name)| *name).collect::<Vec<&str>>().join(";"))?;
+                    },
+                    NodeEdge::End(_) => {
+                        lineage.pop();
+                    }
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    /// get_id_by_node
+    ///
+    /// get the NCBI Taxonomy ID held by the node with a given NodeId
+    pub fn get_id_by_node(&self, node_id: NodeId) -> Option<i32> {
+        self.arena.get(node_id).map(|node| node.data)
+    }
+}
+
+impl NcbiTaxonomy for NcbiFileTaxonomy {
+    /// contains_id
+    ///
+    /// check whether the taxonomy contains a (number) ID
+    fn contains_id(&self, id: i32) -> bool {
+        self.resolve_taxid(id).is_some()
+    }
+
+    /// contains_name
+    ///
+    /// check whether get_id_by_name finds a taxon with this name
+    fn contains_name(&self, name: &str) -> bool {
+        self.get_id_by_name(name).is_some()
+    }
+
+    /// iter_taxids
+    ///
+    /// iterate over the taxids of all the taxa in the taxonomy (in no particular order). taxids
+    /// that were merged into other taxa are not included
+    fn iter_taxids(&self) -> Box<dyn Iterator<Item = i32> + '_> {
+        Box::new(self.id_to_node.keys().cloned())
+    }
+
+    /// len
+    ///
+    /// the number of taxa in the taxonomy
+    fn len(&self) -> usize {
+        self.id_to_node.len()
+    }
+
+    fn root_taxid(&self) -> Option<i32> {
+        let mut roots = self.id_to_node.iter()
+            .filter(|(_, node_id)| self.arena[**node_id].parent().is_none())
+            .map(|(taxid, _)| *taxid);
+        match (roots.next(), roots.next()) {
+            (Some(root), None) => Some(root),
+            _ => None
+        }
+    }
+
+    /// is_descendant
+    ///
+    /// check if a certain named node is a descendant of another named named
     fn is_descendant(&self, name: &str, ancestor_name: &str) -> bool {
         let id = match self.get_id_by_name(name) {
             Some(id) => id,
@@ -275,13 +1895,14 @@ impl NcbiTaxonomy for NcbiFileTaxonomy {
 
     /// is_descendant_taxid
     ///
-    /// check if a certain node with taxid is a descendant of another taxid
+    /// check if a certain node with taxid is a descendant of another taxid (a taxon counts as a
+    /// descendant of itself)
     fn is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> bool {
-        let id = match self.id_to_node.get(&taxid) {
+        let id = match self.get_node_by_id_or_merged(taxid) {
             Some(id) => id,
             None => return false
         };
-        let ancestor_id = match self.id_to_node.get(&ancestor_taxid) {
+        let ancestor_id = match self.get_node_by_id_or_merged(ancestor_taxid) {
             Some(id) => id,
             None => return false
         };
@@ -293,63 +1914,252 @@ impl NcbiTaxonomy for NcbiFileTaxonomy {
         false
     }
 
+    /// are_descendants_taxid
+    ///
+    /// check for each of a list of taxids whether it is a descendant of ancestor_taxid
+    fn are_descendants_taxid(&self, taxids: &[i32], ancestor_taxid: i32) -> HashMap<i32, bool> {
+        taxids.iter().map(|taxid| (*taxid, self.is_descendant_taxid(*taxid, ancestor_taxid))).collect()
+    }
+
     /// get_name_by_id
     ///
     /// get the scientific name associated with a given NCBI Taxonomy ID
     fn get_name_by_id(&self, id: i32) -> Option<String> {
-        self.id_to_name.get(&id).cloned()
+        self.id_to_name.get(&self.resolve_taxid(id)?).cloned()
     }
 
+    /// get_common_name_by_id
+    ///
+    /// get the 'genbank common name' of a taxon from names.dmp
+    fn get_common_name_by_id(&self, taxid: i32) -> Option<String> {
+        self.id_to_common_name.get(&self.resolve_taxid(taxid)?).cloned()
+    }
+
+    /// get_id_by_name
+    ///
+    /// get the taxid of a taxon by its name. the name can be given with or without the qualifier
+    /// NCBI adds to the names of homonyms: a plain name (e.g. "environmental samples") finds the
+    /// taxon with that name plus a qualifier ("environmental samples <bacteriophages>") if only one
+    /// taxon has that plain name, and a name with a qualifier that is not found finds the taxon
+    /// with the name without it (e.g. "Viruses <viruses>" finds "Viruses"), as long as that
+    /// taxon has no qualifier of its own
     fn get_id_by_name(&self, name: &str) -> Option<i32> {
-        match self.name_to_node.get(name) {
-            Some(nodeid) => self.get_id_by_node(*nodeid),
-            None => None
+        if let Some(nodeid) = self.name_to_node.get(name) {
+            return self.get_id_by_node(*nodeid)
         }
+        let stripped_name = strip_disambiguation(name);
+        if stripped_name == name {
+            return None
+        }
+        let taxid = self.get_id_by_node(*self.name_to_node.get(stripped_name)?)?;
+        // the stripped name can be the plain name of a taxon with another qualifier
+        if self.id_to_name.get(&taxid).map(|taxon_name| taxon_name.as_str()) == Some(stripped_name) { Some(taxid) } else { None }
     }
 
-    /// get_lineage
+    /// get_names_by_ids
     ///
-    /// get the list of IDs of a taxon and its parents (up to the root)
-    fn get_lineage(&self, name: &str) -> Option<Vec<i32>> {
-        let node_id = match self.name_to_node.get(name)  {
-            Some(val) => val,
-            None => return None
-        };
+    /// get the names of many taxa at once, keyed by taxid. taxids that are not found are left out
+    fn get_names_by_ids(&self, taxids: &[i32]) -> HashMap<i32, String> {
+        taxids.iter().filter_map(|taxid| self.get_name_by_id(*taxid).map(|name| (*taxid, name))).collect()
+    }
 
-        Some(node_id.ancestors(&self.arena).map(|node_id| *(&self.get_id_by_node(node_id).unwrap())).collect())
+    /// get_ids_by_names
+    ///
+    /// get the taxids of many names at once, keyed by name. names that are not found are left out
+    fn get_ids_by_names(&self, names: &[&str]) -> HashMap<String, i32> {
+        names.iter().filter_map(|name| self.get_id_by_name(name).map(|taxid| (name.to_string(), taxid))).collect()
     }
 
-    /// get_distance_to_common_ancestor_id
+    /// get_ids_by_name
     ///
-    /// get the distance (in steps in the tree) between taxid1 and the common ancestor with taxid2
-    fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)> {
-        let canonical_ranks = get_canonical_ranks();
-        if taxid1 == taxid2 {
-            return Some((0, taxid1))
-        }
+    /// get the taxids of all the taxa with a name, sorted. besides the taxon with exactly that name
+    /// this finds homonyms, whose names NCBI makes unique with a suffix, e.g. "Prunella <birds>"
+    fn get_ids_by_name(&self, name: &str) -> Vec<i32> {
+        let mut taxids: Vec<i32> = self.id_to_name.iter()
+            .filter(|(_, taxon_name)| is_name_or_homonym(taxon_name, name))
+            .map(|(taxid, _)| *taxid)
+            .collect();
+        taxids.sort_unstable();
+        taxids
+    }
 
-        let taxon1 = self.id_to_node.get(&taxid1)?;
-        let taxon2 = self.id_to_node.get(&taxid2)?;
+    /// search_names
+    ///
+    /// find the names most similar to a (possibly misspelled) query, as (taxid, name, similarity)
+    /// sorted by decreasing similarity. the similarity is the Jaro-Winkler similarity (ignoring
+    /// case), from 0 to 1
+    fn search_names(&self, query: &str, max_results: usize) -> Vec<(i32, String, f64)> {
+        most_similar_names(query, self.id_to_name.iter().map(|(taxid, name)| (*taxid, name.as_str())), max_results)
+    }
 
-        let mut ancestors_distance1 = HashMap::new();
-        let mut current_distance = 0;
-        let taxid1_rank = self.id_to_rank.get(&taxid1)?;
-        if !only_canonical || canonical_ranks.contains(taxid1_rank) {
-            // we know that taxid1 != taxid2, so either taxid2 is an ancestor of
-            // taxid1 or there is a common ancestor further back. in the first case,
-            // taxid2 will be found in the ancestors of taxid1, so thifs distance will
-            // not be used. in the second case, taxid1 might be found in the ancestors
-            // of taxid2, so this distance will still not be used
-            ancestors_distance1.insert(taxid1, 0);
-        }
-        for node in taxon1.ancestors(&self.arena) {
-            let nodeid = self.get_id_by_node(node)?;
-            let rank = self.id_to_rank.get(&nodeid)?;
+    /// names_with_prefix
+    ///
+    /// find the names that start with a prefix (ignoring ASCII case, like SQLite's LIKE), as
+    /// (taxid, name) sorted by name. at most limit names are returned
+    fn names_with_prefix(&self, prefix: &str, limit: usize) -> Vec<(i32, String)> {
+        let prefix = prefix.to_ascii_lowercase();
+        matching_names(self.id_to_name.iter().map(|(taxid, name)| (*taxid, name.as_str())),
+                       |name| name.to_ascii_lowercase().starts_with(&prefix), limit)
+    }
 
-            if !only_canonical || canonical_ranks.contains(rank) {
-                current_distance += 1;
-                if nodeid == taxid2 {
-                    // taxid2 is an ancestor of taxid1
+    /// names_containing
+    ///
+    /// find the names that contain a string (ignoring ASCII case, like SQLite's LIKE), as
+    /// (taxid, name) sorted by name. at most limit names are returned
+    fn names_containing(&self, substr: &str, limit: usize) -> Vec<(i32, String)> {
+        let substr = substr.to_ascii_lowercase();
+        matching_names(self.id_to_name.iter().map(|(taxid, name)| (*taxid, name.as_str())),
+                       |name| name.to_ascii_lowercase().contains(&substr), limit)
+    }
+
+    /// get_lineage_taxid
+    ///
+    /// get the list of IDs of the ancestors of a taxon, from the root down to the taxon itself
+    fn get_lineage_taxid(&self, taxid: i32) -> Option<Vec<i32>> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+
+        let mut lineage: Vec<i32> = node_id.ancestors(&self.arena).map(|node_id| self.get_id_by_node(node_id).unwrap()).collect();
+        lineage.reverse();
+        Some(lineage)
+    }
+
+    /// get_children_taxids
+    ///
+    /// get the IDs of the direct children of a taxon (an empty list for a leaf), sorted
+    fn get_children_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        Some(node_id.children(&self.arena).map(|child| self.get_id_by_node(child).unwrap()).collect())
+    }
+
+    fn get_siblings_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        // the children of a node are sorted by taxid
+        Some(match self.arena[*node_id].parent() {
+            Some(parent_node_id) => parent_node_id.children(&self.arena)
+                .filter(|child| child != node_id)
+                .map(|child| self.get_id_by_node(child).unwrap())
+                .collect(),
+            None => Vec::new()
+        })
+    }
+
+    /// get_descendant_taxids
+    ///
+    /// get the IDs of all the descendants of a taxon, not including the taxon itself (an empty
+    /// list for a leaf)
+    fn get_descendant_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        Some(node_id.descendants(&self.arena)
+            .skip(1) // descendants starts with the node itself
+            .map(|node_id| self.get_id_by_node(node_id).unwrap())
+            .collect())
+    }
+
+    /// count_descendants
+    ///
+    /// count the nodes started while traversing the subtree of a taxon, except the taxon itself
+    fn count_descendants(&self, taxid: i32) -> Option<usize> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        Some(node_id.traverse(&self.arena)
+            .filter(|node_edge| matches!(node_edge, NodeEdge::Start(_)))
+            .count() - 1)
+    }
+
+    /// count_leaves
+    ///
+    /// count the nodes in the subtree of a taxon that are ended right after they are started
+    /// while traversing it (i.e. have no children), except the taxon itself
+    fn count_leaves(&self, taxid: i32) -> Option<usize> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        let mut started = None;
+        let mut leaves = 0;
+        for node_edge in node_id.traverse(&self.arena) {
+            match node_edge {
+                NodeEdge::Start(start_node_id) => started = Some(start_node_id),
+                NodeEdge::End(end_node_id) => {
+                    if started == Some(end_node_id) && end_node_id != *node_id {
+                        leaves += 1;
+                    }
+                    started = None;
+                }
+            }
+        }
+        Some(leaves)
+    }
+
+    fn is_leaf(&self, taxid: i32) -> Option<bool> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        Some(node_id.children(&self.arena).next().is_none())
+    }
+
+    /// get_parent_taxid
+    ///
+    /// get the ID of the parent of a taxon, None for the root or if the taxon is not found
+    fn get_parent_taxid(&self, taxid: i32) -> Option<i32> {
+        self.ancestors_of(taxid)?.next()
+    }
+
+    /// get_depth
+    ///
+    /// get the number of steps in the tree from a taxon up to the root (0 for the root itself)
+    fn get_depth(&self, taxid: i32) -> Option<usize> {
+        Some(self.ancestors_of(taxid)?.count())
+    }
+
+    /// get_canonical_depth
+    ///
+    /// like get_depth, but only counting the ancestors with a canonical rank
+    fn get_canonical_depth(&self, taxid: i32) -> Option<usize> {
+        Some(self.ancestors_of(taxid)?
+            .filter(|ancestor_taxid| self.id_to_rank.get(ancestor_taxid).is_some_and(|rank| is_canonical(&self.canonical_ranks, rank)))
+            .count())
+    }
+
+    /// descendants_at_rank
+    ///
+    /// get the IDs of the descendants of a taxon that have a given rank (e.g. all the species
+    /// in a genus), an empty list if there are none
+    fn descendants_at_rank(&self, ancestor_taxid: i32, rank: &str) -> Option<Vec<i32>> {
+        let ancestor_node_id = self.get_node_by_id_or_merged(ancestor_taxid)?;
+        Some(ancestor_node_id.descendants(&self.arena)
+            .skip(1) // descendants starts with the node itself
+            .map(|node_id| self.get_id_by_node(node_id).unwrap())
+            .filter(|taxid| self.id_to_rank.get(taxid).is_some_and(|taxid_rank| taxid_rank == rank))
+            .collect())
+    }
+
+    /// get_distance_to_common_ancestor_id
+    ///
+    /// get the distance (in steps in the tree) between taxid1 and the common ancestor with taxid2
+    fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)> {
+        let taxid1 = self.resolve_taxid(taxid1)?;
+        let taxid2 = self.resolve_taxid(taxid2)?;
+        if taxid1 == taxid2 {
+            return Some((0, taxid1))
+        }
+
+        let taxon1 = self.id_to_node.get(&taxid1)?;
+        let taxon2 = self.id_to_node.get(&taxid2)?;
+
+        let mut ancestors_distance1 = HashMap::new();
+        let mut current_distance = 0;
+        let taxid1_rank = self.id_to_rank.get(&taxid1)?;
+        if !only_canonical || is_canonical(&self.canonical_ranks, taxid1_rank) {
+            // we know that taxid1 != taxid2, so either taxid2 is an ancestor of
+            // taxid1 or there is a common ancestor further back. in the first case,
+            // taxid2 will be found in the ancestors of taxid1, so thifs distance will
+            // not be used. in the second case, taxid1 might be found in the ancestors
+            // of taxid2, so this distance will still not be used
+            ancestors_distance1.insert(taxid1, 0);
+        }
+        for node in taxon1.ancestors(&self.arena) {
+            let nodeid = self.get_id_by_node(node)?;
+            let rank = self.id_to_rank.get(&nodeid)?;
+
+            if !only_canonical || is_canonical(&self.canonical_ranks, rank) {
+                current_distance += 1;
+                if nodeid == taxid2 {
+                    // taxid2 is an ancestor of taxid1
                     return Some((current_distance, taxid2))
                 }
                 ancestors_distance1.insert(nodeid, current_distance);
@@ -362,7 +2172,7 @@ impl NcbiTaxonomy for NcbiFileTaxonomy {
         for node in taxon2.ancestors(&self.arena) {
             let nodeid = self.get_id_by_node(node).unwrap();
             let rank = self.id_to_rank.get(&nodeid)?;
-            if !only_canonical || canonical_ranks.contains(rank) {
+            if !only_canonical || is_canonical(&self.canonical_ranks, rank) {
                 current_distance += 1;
                 if ancestors_distance1.contains_key(&nodeid) {
                     // the distance to te common ancestor is the distance from taxon2
@@ -378,418 +2188,3071 @@ impl NcbiTaxonomy for NcbiFileTaxonomy {
     ///
     /// find the distance in the tree between name1 and name2
     fn get_distance_to_common_ancestor(&self, name1: &str, name2: &str, only_canonical: bool) -> Option<(i32, String)> {
-        let taxon1 = self.name_to_node.get(name1)?;
+        let taxid1 = self.get_id_by_name(name1)?;
+
+        let taxid2 = self.get_id_by_name(name2)?;
+
+        self.get_distance_to_common_ancestor_taxid(taxid1, taxid2, only_canonical)
+            .and_then(|(distance, taxid)| Some((distance, self.get_name_by_id(taxid)?)))
+    }
+
+    /// get_common_ancestor_of
+    ///
+    /// get the most recent common ancestor of a list of taxa, None if the list is empty or
+    /// any of the taxa is not found
+    fn get_common_ancestor_of(&self, taxids: &[i32]) -> Option<i32> {
+        let (first, rest) = taxids.split_first()?;
+        // candidate common ancestors, from the first taxon up to the root
+        let mut candidates: Vec<i32> = self.get_node_by_id_or_merged(*first)?.ancestors(&self.arena)
+            .map(|node_id| self.get_id_by_node(node_id).unwrap())
+            .collect();
+        for taxid in rest {
+            let node_id = self.get_node_by_id_or_merged(*taxid)?;
+            // the first ancestor of this taxon that is a candidate is the new most recent common ancestor
+            let position = node_id.ancestors(&self.arena)
+                .filter_map(|node_id| candidates.iter().position(|candidate| *candidate == self.get_id_by_node(node_id).unwrap()))
+                .next()?;
+            candidates.drain(..position);
+        }
+        candidates.first().cloned()
+    }
+
+    /// effective_rank
+    ///
+    /// get the rank of a taxon if it is a canonical rank, otherwise the rank of its nearest
+    /// canonical ancestor (i.e. a way to avoid reporting 'no rank')
+    fn effective_rank(&self, taxid: i32) -> Option<String> {
+        let node_id = self.get_node_by_id_or_merged(taxid)?;
+        for node in node_id.ancestors(&self.arena) {
+            let rank = self.id_to_rank.get(&self.get_id_by_node(node)?)?;
+            if is_canonical(&self.canonical_ranks, rank) {
+                return Some(rank.clone())
+            }
+        }
+        None
+    }
+
+    /// next_rank_toward
+    ///
+    /// get the taxid and rank of the child of from_taxid that is on the path to (or is) toward_taxid
+    fn next_rank_toward(&self, from_taxid: i32, toward_taxid: i32) -> Option<(i32, String)> {
+        let from_taxid = self.resolve_taxid(from_taxid)?;
+        let toward_node = self.get_node_by_id_or_merged(toward_taxid)?;
+        let mut previous_taxid = None;
+        for node in toward_node.ancestors(&self.arena) {
+            let taxid = self.get_id_by_node(node)?;
+            if taxid == from_taxid {
+                let child_taxid = previous_taxid?;
+                return Some((child_taxid, self.id_to_rank.get(&child_taxid)?.clone()))
+            }
+            previous_taxid = Some(taxid);
+        }
+        None
+    }
+
+    fn canonical_ranks(&self) -> Vec<Rank> {
+        self.canonical_ranks.clone()
+    }
+
+    /// is_canonical_rank
+    ///
+    /// check whether a taxon has one of the canonical ranks (by default superkingdom, kingdom,
+    /// ..., species)
+    fn is_canonical_rank(&self, taxid: i32) -> bool {
+        match self.resolve_taxid(taxid).and_then(|taxid| self.id_to_rank.get(&taxid)) {
+            Some(rank) => is_canonical(&self.canonical_ranks, rank),
+            None => false
+        }
+    }
+
+    /// get_rank_by_id
+    ///
+    /// get the rank of a taxon, None if the taxon is not found or has 'no rank'
+    fn get_rank_by_id(&self, taxid: i32) -> Option<String> {
+        self.id_to_rank.get(&self.resolve_taxid(taxid)?).filter(|rank| *rank != "no rank").cloned()
+    }
+
+    /// get_rank_enum_by_id
+    ///
+    /// get the rank of a taxon as a Rank, None if the taxon is not found
+    fn get_rank_enum_by_id(&self, taxid: i32) -> Option<Rank> {
+        self.id_to_rank.get(&self.resolve_taxid(taxid)?).map(|rank| Rank::from(rank.as_str()))
+    }
+
+    /// get_lineage_by_rank
+    ///
+    /// get the taxids in the lineage of a taxon (including the taxon itself) keyed by rank. taxa
+    /// with 'no rank' are left out, and if a rank occurs more than once the taxon closest to the
+    /// starting taxon is used
+    fn get_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32> {
+        let mut lineage = HashMap::new();
+        if let Some(node_id) = self.get_node_by_id_or_merged(taxid) {
+            for node in node_id.ancestors(&self.arena) {
+                let ancestor_taxid = self.get_id_by_node(node).unwrap();
+                if let Some(rank) = self.id_to_rank.get(&ancestor_taxid) {
+                    if rank != "no rank" {
+                        lineage.entry(rank.clone()).or_insert(ancestor_taxid);
+                    }
+                }
+            }
+        }
+        lineage
+    }
+}
+
+/// NcbiFileTaxonomyBuilder
+///
+/// Configures loading a NcbiFileTaxonomy from a directory containing the NCBI Taxonomy
+/// database dump (`.dmp`) files. The `nodes.dmp` and `names.dmp` files are always required,
+/// other files are loaded only when asked for. By default a requested file that is missing
+/// is skipped, use `require()` to treat it as an error instead. The path of each file can also
+/// be given on its own (e.g. with `nodes()` and `merged()`), in which case the directory is not
+/// used for it, and `NcbiFileTaxonomyBuilder::default()` has no directory (so the paths of
+/// `nodes.dmp` and `names.dmp` are relative to the current directory).
+///
+/// # Examples
+///
+/// ```
+/// use ncbitaxonomy::*;
+///
+/// let taxonomy = NcbiFileTaxonomyBuilder::new("data")
+///     .prefix("sample_tree_")
+///     .build()
+///     .unwrap();
+/// assert!(taxonomy.contains_name("Viruses"));
+///
+/// let taxonomy = NcbiFileTaxonomyBuilder::default()
+///     .nodes("data/sample_tree_nodes.dmp")
+///     .names("data/sample_tree_names.dmp")
+///     .merged("data/sample_tree_merged.dmp")
+///     .build()
+///     .unwrap();
+/// assert!(taxonomy.contains_id(999001));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NcbiFileTaxonomyBuilder {
+    taxdump_dir: PathBuf,
+    prefix: String,
+    require_optional: bool,
+    merged: bool,
+    delnodes: bool,
+    division: bool,
+    host: bool,
+    type_material: bool,
+    aliases: Option<PathBuf>,
+    canonical_ranks: Option<Vec<Rank>>,
+    validate: bool,
+    // paths given for .dmp files (by their name, e.g. nodes.dmp) instead of the taxdump directory
+    dmp_paths: HashMap<String, PathBuf>
+}
+
+impl Default for NcbiFileTaxonomyBuilder {
+    fn default() -> Self {
+        NcbiFileTaxonomyBuilder::new("")
+    }
+}
+
+impl NcbiFileTaxonomyBuilder {
+    pub fn new<P: AsRef<Path>>(taxdump_dir: P) -> Self {
+        NcbiFileTaxonomyBuilder {
+            taxdump_dir: taxdump_dir.as_ref().to_path_buf(),
+            prefix: String::new(),
+            require_optional: false,
+            merged: false,
+            delnodes: false,
+            division: false,
+            host: false,
+            type_material: false,
+            aliases: None,
+            canonical_ranks: None,
+            validate: false,
+            dmp_paths: HashMap::new()
+        }
+    }
+
+    /// nodes
+    ///
+    /// read `nodes.dmp` from this path rather than from the taxdump directory
+    pub fn nodes<P: AsRef<Path>>(self, nodes_path: P) -> Self {
+        self.dmp_file("nodes.dmp", nodes_path)
+    }
+
+    /// names
+    ///
+    /// read `names.dmp` from this path rather than from the taxdump directory
+    pub fn names<P: AsRef<Path>>(self, names_path: P) -> Self {
+        self.dmp_file("names.dmp", names_path)
+    }
+
+    /// merged
+    ///
+    /// like with_merged, but read `merged.dmp` from this path
+    pub fn merged<P: AsRef<Path>>(self, merged_path: P) -> Self {
+        self.with_merged().dmp_file("merged.dmp", merged_path)
+    }
+
+    /// delnodes
+    ///
+    /// like with_delnodes, but read `delnodes.dmp` from this path
+    pub fn delnodes<P: AsRef<Path>>(self, delnodes_path: P) -> Self {
+        self.with_delnodes().dmp_file("delnodes.dmp", delnodes_path)
+    }
+
+    /// division
+    ///
+    /// like with_division, but read `division.dmp` from this path
+    pub fn division<P: AsRef<Path>>(self, division_path: P) -> Self {
+        self.with_division().dmp_file("division.dmp", division_path)
+    }
+
+    /// host
+    ///
+    /// like with_host, but read `host.dmp` from this path
+    pub fn host<P: AsRef<Path>>(self, host_path: P) -> Self {
+        self.with_host().dmp_file("host.dmp", host_path)
+    }
+
+    /// type_material
+    ///
+    /// like with_type_material, but read `typematerial.dmp` from this path
+    pub fn type_material<P: AsRef<Path>>(self, type_material_path: P) -> Self {
+        self.with_type_material().dmp_file("typematerial.dmp", type_material_path)
+    }
+
+    // use path for the .dmp file filename. unlike the files in the taxdump directory, it is not
+    // checked beforehand, so a missing file gives the error of opening it
+    fn dmp_file<P: AsRef<Path>>(mut self, filename: &str, path: P) -> Self {
+        self.dmp_paths.insert(filename.to_string(), path.as_ref().to_path_buf());
+        self
+    }
+
+    /// prefix
+    ///
+    /// string to prepend to the names of the `.dmp` files (e.g. `nodes.dmp`)
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// with_merged
+    ///
+    /// also load `merged.dmp`, see NcbiFileTaxonomy::from_ncbi_files_with_merged
+    pub fn with_merged(mut self) -> Self {
+        self.merged = true;
+        self
+    }
+
+    /// with_delnodes
+    ///
+    /// also load `delnodes.dmp`, see NcbiFileTaxonomy::from_ncbi_files_with_delnodes
+    pub fn with_delnodes(mut self) -> Self {
+        self.delnodes = true;
+        self
+    }
+
+    /// with_division
+    ///
+    /// also load `division.dmp`, see NcbiFileTaxonomy::from_ncbi_files_with_division
+    pub fn with_division(mut self) -> Self {
+        self.division = true;
+        self
+    }
+
+    /// with_host
+    ///
+    /// also load `host.dmp`, see NcbiFileTaxonomy::from_ncbi_files_with_host
+    pub fn with_host(mut self) -> Self {
+        self.host = true;
+        self
+    }
+
+    /// with_type_material
+    ///
+    /// also load `typematerial.dmp`, see NcbiFileTaxonomy::from_ncbi_files_with_type_material
+    pub fn with_type_material(mut self) -> Self {
+        self.type_material = true;
+        self
+    }
+
+    /// with_aliases
+    ///
+    /// also load the taxid aliases in aliases_path (which, unlike the `.dmp` files, is not looked
+    /// for in the taxdump directory), see NcbiFileTaxonomy::from_ncbi_files_with_aliases
+    pub fn with_aliases<P: AsRef<Path>>(mut self, aliases_path: P) -> Self {
+        self.aliases = Some(aliases_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// canonical_ranks
+    ///
+    /// use another set of canonical ranks, see NcbiFileTaxonomy::with_canonical_ranks
+    pub fn canonical_ranks(mut self, canonical_ranks: &[Rank]) -> Self {
+        self.canonical_ranks = Some(canonical_ranks.to_vec());
+        self
+    }
+
+    /// validate
+    ///
+    /// fail with NcbiTaxonomyError::InvalidTaxonomy if NcbiFileTaxonomy::validate finds problems
+    pub fn validate(mut self) -> Self {
+        self.validate = true;
+        self
+    }
+
+    /// require
+    ///
+    /// treat a missing optional file as an error
+    pub fn require(mut self) -> Self {
+        self.require_optional = true;
+        self
+    }
+
+    /// optional
+    ///
+    /// skip optional files that are missing (the default)
+    pub fn optional(mut self) -> Self {
+        self.require_optional = false;
+        self
+    }
+
+    // find the path to a .dmp file, returning None if the file is missing but not required
+    fn dmp_path(&self, filename: &str, required: bool) -> Result<Option<PathBuf>, NcbiTaxonomyError> {
+        if let Some(path) = self.dmp_paths.get(filename) {
+            return Ok(Some(path.clone()))
+        }
+        let path = self.taxdump_dir.join(self.prefix.clone() + filename);
+        if path.exists() {
+            Ok(Some(path))
+        } else if required || self.require_optional {
+            Err(NcbiTaxonomyError::MissingFile(path.to_string_lossy().into_owned()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn build(&self) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        self.build_taxonomy(None)
+    }
+
+    /// build_with_progress
+    ///
+    /// like build, but calls progress with the number of lines of `nodes.dmp` and `names.dmp`
+    /// read so far every `every` lines (and once more when both are read), e.g. to show the
+    /// progress of loading the full taxonomy
+    pub fn build_with_progress<F: FnMut(usize)>(&self, every: usize, mut progress: F) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        self.build_taxonomy(Some(&mut LineProgress::new(every, &mut progress)))
+    }
+
+    fn build_taxonomy(&self, progress: Option<&mut LineProgress>) -> Result<NcbiFileTaxonomy, NcbiTaxonomyError> {
+        let nodes_path = self.dmp_path("nodes.dmp", true)?.unwrap();
+        let names_path = self.dmp_path("names.dmp", true)?.unwrap();
+        let mut tree = NcbiFileTaxonomy::from_dmp_files(&nodes_path.to_string_lossy(), &names_path.to_string_lossy(), progress)?;
+        if self.merged {
+            if let Some(merged_path) = self.dmp_path("merged.dmp", false)? {
+                tree.load_merged(&merged_path.to_string_lossy())?;
+            }
+        }
+        if self.delnodes {
+            if let Some(delnodes_path) = self.dmp_path("delnodes.dmp", false)? {
+                tree.load_delnodes(&delnodes_path.to_string_lossy())?;
+            }
+        }
+        if self.division {
+            if let Some(division_path) = self.dmp_path("division.dmp", false)? {
+                tree.load_division(&division_path.to_string_lossy())?;
+            }
+        }
+        if self.host {
+            if let Some(host_path) = self.dmp_path("host.dmp", false)? {
+                tree.load_host(&host_path.to_string_lossy())?;
+            }
+        }
+        if self.type_material {
+            if let Some(type_material_path) = self.dmp_path("typematerial.dmp", false)? {
+                tree.load_type_material(&type_material_path.to_string_lossy())?;
+            }
+        }
+        if let Some(aliases_path) = &self.aliases {
+            tree.load_aliases(&aliases_path.to_string_lossy())?;
+        }
+        if let Some(canonical_ranks) = &self.canonical_ranks {
+            tree = tree.with_canonical_ranks(canonical_ranks);
+        }
+        if self.validate {
+            tree.validate().map_err(NcbiTaxonomyError::InvalidTaxonomy)?;
+        }
+        Ok(tree)
+    }
+}
+
+/// the number of rows changed by NcbiFileTaxonomy::update_sqlite
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SqliteUpdateCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize
+}
+
+// the maximum number of parameters in a SQLite query (SQLITE_MAX_VARIABLE_NUMBER in SQLite
+// before version 3.32), used to split queries with long IN (...) lists
+const SQLITE_MAX_VARIABLES: usize = 999;
+
+/// the default number of taxa whose ancestry and rank a NcbiSqliteTaxonomy keeps in memory.
+/// each entry takes roughly 150 bytes (mostly the ancestry string) so the default cache uses
+/// at most about 15 MB
+pub const DEFAULT_SQLITE_CACHE_SIZE: usize = 100_000;
+
+/// the number of bytes of the database file that NcbiSqliteTaxonomy::new_read_only memory maps
+/// (PRAGMA mmap_size), enough for a database of the full NCBI taxonomy. SQLite limits this to
+/// its compile time SQLITE_MAX_MMAP_SIZE
+pub const SQLITE_READ_ONLY_MMAP_SIZE: i64 = 1 << 30;
+
+// the ancestry, rank and parent of a taxon, as stored in the taxonomy table
+#[derive(Clone)]
+struct CachedTaxon {
+    ancestry: Option<String>,
+    rank: String,
+    parent_id: Option<i32>
+}
+
+// the connection of a NcbiSqliteTaxonomy, either its own or one checked out of a NcbiSqlitePool
+enum TaxonomyConnection {
+    Owned(SqliteConnection),
+    #[cfg(feature = "r2d2")]
+    Pooled(diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<SqliteConnection>>)
+}
+
+impl std::ops::Deref for TaxonomyConnection {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        match self {
+            TaxonomyConnection::Owned(connection) => connection,
+            #[cfg(feature = "r2d2")]
+            TaxonomyConnection::Pooled(connection) => connection
+        }
+    }
+}
+
+/// A NcbiTaxonomy stored in a SQLite database
+///
+/// a NcbiSqliteTaxonomy can be moved to another thread but not shared between threads, as its
+/// connection and cache are not Sync. wrap it in a SharedNcbiSqliteTaxonomy to share it, or use a
+/// NcbiSqlitePool (with the r2d2 feature) to query from several threads in parallel
+pub struct NcbiSqliteTaxonomy {
+    connection: TaxonomyConnection,
+    cache: RefCell<HashMap<i32, CachedTaxon>>,
+    cache_size: usize,
+    canonical_ranks: Vec<Rank>
+}
+
+impl NcbiSqliteTaxonomy {
+    pub fn new(db_url: Option<&str>) -> Self {
+        NcbiSqliteTaxonomy::from_connection(establish_connection(db_url))
+    }
+
+    pub fn from_connection(connection: SqliteConnection) -> Self {
+        NcbiSqliteTaxonomy {
+            connection: TaxonomyConnection::Owned(connection),
+            cache: RefCell::new(HashMap::new()),
+            cache_size: DEFAULT_SQLITE_CACHE_SIZE,
+            canonical_ranks: CANONICAL_RANKS.to_vec()
+        }
+    }
+
+    // a taxonomy using a connection checked out of a pool, which goes back to the pool when the
+    // taxonomy is dropped. it has no cache, as it only lives for one call of a NcbiSqlitePool method
+    #[cfg(feature = "r2d2")]
+    fn from_pooled_connection(connection: diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<SqliteConnection>>,
+                              canonical_ranks: &[Rank]) -> Self {
+        NcbiSqliteTaxonomy {
+            connection: TaxonomyConnection::Pooled(connection),
+            cache: RefCell::new(HashMap::new()),
+            cache_size: 0,
+            canonical_ranks: canonical_ranks.to_vec()
+        }
+    }
+
+    /// with_cache_size
+    ///
+    /// set the number of taxa whose ancestry and rank are kept in memory after they are first
+    /// loaded (the default is DEFAULT_SQLITE_CACHE_SIZE). methods that walk the lineage of a taxon,
+    /// like get_distance_to_common_ancestor_taxid, otherwise query the database once per ancestor.
+    /// a larger cache uses more memory (roughly 150 bytes per taxon) but saves more queries when the
+    /// same taxa are looked up repeatedly. the cache is emptied when it is full, and a size of 0
+    /// turns it off
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self.cache.borrow_mut().clear();
+        self
+    }
+
+    /// with_canonical_ranks
+    ///
+    /// use another set of canonical ranks, see NcbiFileTaxonomy::with_canonical_ranks
+    pub fn with_canonical_ranks(mut self, canonical_ranks: &[Rank]) -> Self {
+        self.canonical_ranks = canonical_ranks.to_vec();
+        self
+    }
+
+    /// new_read_only
+    ///
+    /// open the SQLite database at path for reading only, e.g. for tools that run many queries
+    /// (possibly from several processes) and never change the taxonomy. the database is opened
+    /// with a file: URI with mode=ro (so it has to exist) and cache=shared, and the connection
+    /// sets PRAGMA query_only = ON (any attempt to write fails) and PRAGMA mmap_size =
+    /// SQLITE_READ_ONLY_MMAP_SIZE (so that reads come from memory mapped pages instead of
+    /// read() calls). unlike new, DATABASE_URL is not used
+    pub fn new_read_only<P: AsRef<Path>>(path: P) -> Result<Self, ToSqliteError> {
+        use diesel::connection::SimpleConnection;
+
+        // ? and # end the path part of a URI, so they (and %) have to be escaped
+        let escaped_path = path.as_ref().to_string_lossy()
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23");
+        let connection = SqliteConnection::establish(&format!("file:{}?mode=ro&cache=shared", escaped_path))?;
+        connection.batch_execute(&format!("PRAGMA query_only = ON; PRAGMA mmap_size = {};", SQLITE_READ_ONLY_MMAP_SIZE))?;
+        Ok(NcbiSqliteTaxonomy::from_connection(connection))
+    }
+
+    /// new_in_memory
+    ///
+    /// create an empty taxonomy in an in-memory SQLite database, without needing DATABASE_URL
+    pub fn new_in_memory() -> Result<Self, ToSqliteError> {
+        let connection = SqliteConnection::establish(":memory:")?;
+        embedded_migrations::run(&connection)?;
+        Ok(NcbiSqliteTaxonomy::from_connection(connection))
+    }
 
-        let taxon2 = self.name_to_node.get(name2)?;
+    /// from_file_taxonomy
+    ///
+    /// create an in-memory SQLite taxonomy holding a copy of a NcbiFileTaxonomy
+    pub fn from_file_taxonomy(file_taxonomy: &NcbiFileTaxonomy) -> Result<Self, ToSqliteError> {
+        let taxonomy = NcbiSqliteTaxonomy::new_in_memory()?;
+        file_taxonomy.save_to_sqlite_connection(&taxonomy.connection)?;
+        Ok(taxonomy)
+    }
+
+    // load the ancestry and rank of a taxon, from the cache if possible
+    fn get_cached_taxon(&self, taxid: i32) -> Result<CachedTaxon, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        if let Some(cached_taxon) = self.cache.borrow().get(&taxid) {
+            return Ok(cached_taxon.clone());
+        }
+
+        let results: Vec<(Option<String>, String, Option<i32>)> = taxonomy.filter(id.eq(taxid))
+            .select((ancestry, rank, parent_id))
+            .load(&*self.connection)?;
+
+        let cached_taxon = match results.into_iter().next() {
+            Some((taxon_ancestry, taxon_rank, taxon_parent_id)) => CachedTaxon { ancestry: taxon_ancestry, rank: taxon_rank, parent_id: taxon_parent_id },
+            None => return Err(NcbiTaxonomyError::NotFound(taxid.to_string()))
+        };
+        if self.cache_size > 0 {
+            let mut cache = self.cache.borrow_mut();
+            if cache.len() >= self.cache_size {
+                cache.clear();
+            }
+            cache.insert(taxid, cached_taxon.clone());
+        }
+        Ok(cached_taxon)
+    }
+
+    fn get_ancestry_for_taxid(&self, taxid: i32) -> Result<Option<String>, NcbiTaxonomyError> {
+        Ok(self.get_cached_taxon(taxid)?.ancestry)
+    }
+
+    fn get_ancestors(&self, taxid: i32) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        // the root taxon has no ancestry, so it has no ancestors
+        let mut ancestors = queries::parse_ancestry(self.get_ancestry_for_taxid(taxid)?.as_deref())?;
+        ancestors.reverse();
+        Ok(ancestors)
+    }
+
+    fn get_rank(&self, taxid: i32) -> Result<String, NcbiTaxonomyError> {
+        Ok(self.get_cached_taxon(taxid)?.rank)
+    }
+
+    /// try_contains_id
+    ///
+    /// like contains_id, but returns database errors instead of panicking
+    pub fn try_contains_id(&self, taxid: i32) -> Result<bool, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let results: Vec<i64> = taxonomy.filter(id.eq(taxid))
+            .select(count(id))
+            .load(&*self.connection)?;
+
+        Ok(results[0] == 1)
+    }
+
+    /// try_contains_name
+    ///
+    /// like contains_name, but returns database errors instead of panicking. like try_get_id_by_name
+    /// this finds a taxon by its plain name as well as by its unique name
+    pub fn try_contains_name(&self, name_str: &str) -> Result<bool, NcbiTaxonomyError> {
+        match self.try_get_id_by_name(name_str) {
+            Ok(_) => Ok(true),
+            Err(NcbiTaxonomyError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// try_is_descendant_taxid
+    ///
+    /// like is_descendant_taxid, but returns database errors instead of panicking
+    pub fn try_is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> Result<bool, NcbiTaxonomyError> {
+        Ok(self.try_are_descendants_taxid(&[taxid], ancestor_taxid)?[&taxid])
+    }
+
+    /// try_are_descendants_taxid
+    ///
+    /// like are_descendants_taxid, but returns database errors instead of panicking
+    pub fn try_are_descendants_taxid(&self, taxids: &[i32], ancestor_taxid: i32) -> Result<HashMap<i32, bool>, NcbiTaxonomyError> {
+        let mut descendants: HashMap<i32, bool> = taxids.iter().map(|taxid| (*taxid, false)).collect();
+        for chunk in taxids.chunks(SQLITE_MAX_VARIABLES) {
+            let results: Vec<i32> = queries::descendant_ids_among(chunk, ancestor_taxid).load(&*self.connection)?;
+            for taxid in results {
+                descendants.insert(taxid, true);
+            }
+        }
+        Ok(descendants)
+    }
+
+    /// try_get_name_by_id
+    ///
+    /// like get_name_by_id, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_name_by_id(&self, taxid: i32) -> Result<String, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let results: Vec<String> = taxonomy.filter(id.eq(taxid))
+            .select(name)
+            .load(&*self.connection)?;
+
+        match results.len() {
+            1 => Ok(results[0].clone()),
+            _ => Err(NcbiTaxonomyError::NotFound(taxid.to_string()))
+        }
+    }
+
+    /// try_get_common_name_by_id
+    ///
+    /// like get_common_name_by_id, but returns NcbiTaxonomyError::NotFound if the taxon is not
+    /// found. the common name is None if the taxon has no common name
+    pub fn try_get_common_name_by_id(&self, taxid: i32) -> Result<Option<String>, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let results: Vec<Option<String>> = taxonomy.filter(id.eq(taxid))
+            .select(common_name)
+            .load(&*self.connection)?;
+
+        results.into_iter().next().ok_or_else(|| NcbiTaxonomyError::NotFound(taxid.to_string()))
+    }
+
+    /// try_get_id_by_name
+    ///
+    /// like get_id_by_name, but returns NcbiTaxonomyError::NotFound if the taxon is not found. the
+    /// name can be given with or without the qualifier NCBI adds to the names of homonyms, as for
+    /// NcbiFileTaxonomy::get_id_by_name
+    pub fn try_get_id_by_name(&self, name_str: &str) -> Result<i32, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let results: Vec<i32> = taxonomy.filter(name.eq(name_str))
+            .select(id)
+            .load(&*self.connection)?;
+        if results.len() == 1 {
+            return Ok(results[0])
+        }
+
+        // a name with a qualifier that is not found finds the taxon with the name without it
+        let stripped_name = strip_disambiguation(name_str);
+        if stripped_name != name_str {
+            let results: Vec<i32> = taxonomy.filter(name.eq(stripped_name))
+                .select(id)
+                .load(&*self.connection)?;
+            return match results.len() {
+                1 => Ok(results[0]),
+                _ => Err(NcbiTaxonomyError::NotFound(name_str.to_string()))
+            }
+        }
+
+        // only the unique name of a taxon is stored, e.g. "environmental samples <bacteriophages>",
+        // so the plain name finds it if no other taxon has that name (LIKE ignores case, so check
+        // the names that it finds)
+        let homonyms: Vec<i32> = taxonomy.filter(name.like(format!("{} <%>", escape_like(name_str))).escape('\\'))
+            .select((id, name))
+            .load::<(i32, String)>(&*self.connection)?
+            .into_iter()
+            .filter(|(_, taxon_name)| is_name_or_homonym(taxon_name, name_str))
+            .map(|(taxid, _)| taxid)
+            .collect();
+        match homonyms.len() {
+            1 if results.is_empty() => Ok(homonyms[0]),
+            _ => Err(NcbiTaxonomyError::NotFound(name_str.to_string()))
+        }
+    }
+
+    /// try_get_lineage_taxid
+    ///
+    /// like get_lineage_taxid, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_lineage_taxid(&self, taxid: i32) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        let mut lineage = self.get_ancestors(taxid)?;
+        if lineage.is_empty() {
+            // the root taxon has no ancestry
+            lineage.push(taxid);
+        }
+        lineage.reverse();
+        Ok(lineage)
+    }
+
+    /// try_get_children_taxids
+    ///
+    /// like get_children_taxids, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_children_taxids(&self, taxid: i32) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(taxid)?;
+        let results: Vec<i32> = taxonomy.filter(parent_id.eq(taxid))
+            .select(id)
+            .order(id)
+            .load(&*self.connection)?;
+
+        Ok(results)
+    }
+
+    /// try_get_siblings_taxids
+    ///
+    /// like get_siblings_taxids, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_siblings_taxids(&self, taxid: i32) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let taxon_parent_id = match self.get_cached_taxon(taxid)?.parent_id {
+            Some(taxon_parent_id) => taxon_parent_id,
+            None => return Ok(Vec::new())
+        };
+        let results: Vec<i32> = taxonomy.filter(parent_id.eq(taxon_parent_id).and(id.ne(taxid)))
+            .select(id)
+            .order(id)
+            .load(&*self.connection)?;
+
+        Ok(results)
+    }
+
+    /// try_get_descendant_taxids
+    ///
+    /// like get_descendant_taxids, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_descendant_taxids(&self, ancestor_taxid: i32) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(ancestor_taxid)?;
+        Ok(queries::descendant_ids(ancestor_taxid).load(&*self.connection)?)
+    }
+
+    /// try_root_taxid
+    ///
+    /// like root_taxid, but returns an error if the database cannot be read. the root is the row
+    /// without a parent_id
+    pub fn try_root_taxid(&self) -> Result<Option<i32>, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let roots: Vec<i32> = taxonomy.filter(parent_id.is_null())
+            .select(id)
+            .limit(2)
+            .load(&*self.connection)?;
+        Ok(if roots.len() == 1 { Some(roots[0]) } else { None })
+    }
+
+    /// try_count_descendants
+    ///
+    /// like count_descendants, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_count_descendants(&self, ancestor_taxid: i32) -> Result<usize, NcbiTaxonomyError> {
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(ancestor_taxid)?;
+        let count: i64 = queries::descendant_count(ancestor_taxid).get_result(&*self.connection)?;
+        Ok(count as usize)
+    }
+
+    /// try_count_leaves
+    ///
+    /// like count_leaves, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_count_leaves(&self, ancestor_taxid: i32) -> Result<usize, NcbiTaxonomyError> {
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(ancestor_taxid)?;
+        let count: i64 = queries::leaf_descendant_count(ancestor_taxid).get_result(&*self.connection)?;
+        Ok(count as usize)
+    }
+
+    /// try_is_leaf
+    ///
+    /// like is_leaf, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_is_leaf(&self, taxid: i32) -> Result<bool, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(taxid)?;
+        let has_children: bool = diesel::select(diesel::dsl::exists(taxonomy.filter(parent_id.eq(taxid))))
+            .get_result(&*self.connection)?;
+        Ok(!has_children)
+    }
+
+    /// try_descendants_at_rank
+    ///
+    /// like descendants_at_rank, but returns NcbiTaxonomyError::NotFound if the ancestor is not found
+    pub fn try_descendants_at_rank(&self, ancestor_taxid: i32, rank_str: &str) -> Result<Vec<i32>, NcbiTaxonomyError> {
+        // look up the taxon first so that an unknown taxid is reported as not found
+        self.get_cached_taxon(ancestor_taxid)?;
+        Ok(queries::descendant_ids_at_rank(ancestor_taxid, rank_str).load(&*self.connection)?)
+    }
+
+    // the names matching a LIKE pattern, sorted by name. with a limit only the first limit names
+    // are returned, otherwise all of them
+    fn names_like(&self, pattern: &str, limit: Option<usize>) -> Vec<(i32, String)> {
+        use schema::taxonomy::dsl::*;
+
+        // the ESCAPE character is written into the SQL rather than bound as a parameter (as
+        // .escape() does) because SQLite only uses taxonomy_name_nocase_idx for a LIKE prefix
+        // search if it knows the escape character when the query is planned
+        let query = taxonomy.filter(sql::<Bool>("name LIKE ").bind::<Text, _>(pattern).sql(" ESCAPE '\\'"))
+            .select((id, name))
+            .order((name, id));
+        match limit {
+            Some(limit) => query.limit(limit as i64).load(&*self.connection),
+            None => query.load(&*self.connection)
+        }.expect("Error loading taxonomy")
+    }
+
+    /// get_taxon
+    ///
+    /// get the full row (id, ancestry, name, rank, parent id and common name) of a taxon in one query, None if
+    /// the taxon is not found
+    pub fn get_taxon(&self, taxid: i32) -> Option<Taxon> {
+        found(self.try_get_taxon(taxid))
+    }
+
+    /// try_get_taxon
+    ///
+    /// like get_taxon, but returns NcbiTaxonomyError::NotFound if the taxon is not found
+    pub fn try_get_taxon(&self, taxid: i32) -> Result<Taxon, NcbiTaxonomyError> {
+        use schema::taxonomy::dsl::*;
+
+        let results: Vec<Taxon> = taxonomy.filter(id.eq(taxid))
+            .load(&*self.connection)?;
+
+        results.into_iter().next().ok_or_else(|| NcbiTaxonomyError::NotFound(taxid.to_string()))
+    }
+
+    /// try_get_rank_by_id
+    ///
+    /// like get_rank_by_id, but returns NcbiTaxonomyError::NotFound if the taxon is not found.
+    /// the rank is None if the taxon has 'no rank'
+    pub fn try_get_rank_by_id(&self, taxid: i32) -> Result<Option<String>, NcbiTaxonomyError> {
+        let taxon_rank = self.get_rank(taxid)?;
+        Ok(if taxon_rank == "no rank" { None } else { Some(taxon_rank) })
+    }
+}
+
+// turn the result of a query into an Option, treating a taxon that is not found as None.
+// database errors are still fatal for the (infallible) NcbiTaxonomy methods
+fn found<T>(result: Result<T, NcbiTaxonomyError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(NcbiTaxonomyError::NotFound(_)) => None,
+        Err(e) => panic!("Error loading taxonomy: {}", e)
+    }
+}
+
+impl NcbiTaxonomy for NcbiSqliteTaxonomy {
+
+    fn contains_id(&self, taxid: i32) -> bool {
+        self.try_contains_id(taxid).expect("Error loading taxonomy")
+    }
+
+    fn contains_name(&self, name_str: &str) -> bool {
+        self.try_contains_name(name_str).expect("Error loading taxonomy")
+    }
+
+    /// iter_taxids
+    ///
+    /// iterate over the taxids of all the taxa in the taxonomy, sorted. note that this loads all
+    /// the taxids from the database into memory first (about 10 MB for the full NCBI taxonomy)
+    fn iter_taxids(&self) -> Box<dyn Iterator<Item = i32> + '_> {
+        use schema::taxonomy::dsl::*;
+
+        let taxids: Vec<i32> = taxonomy.select(id)
+            .order(id)
+            .load(&*self.connection)
+            .expect("Error loading taxonomy");
+        Box::new(taxids.into_iter())
+    }
+
+    fn len(&self) -> usize {
+        use schema::taxonomy::dsl::*;
+
+        let taxon_count: i64 = taxonomy.select(count(id))
+            .first(&*self.connection)
+            .expect("Error loading taxonomy");
+        taxon_count as usize
+    }
+
+    fn root_taxid(&self) -> Option<i32> {
+        self.try_root_taxid().expect("Error loading taxonomy")
+    }
+
+    /// count_by_rank
+    ///
+    /// count the taxa of each rank, counting in the database rather than looking up each taxon
+    fn count_by_rank(&self) -> HashMap<String, usize> {
+        use schema::taxonomy::dsl::*;
+
+        // diesel does not allow count() next to the grouped column, so the count is written in SQL
+        let results: Vec<(String, i64)> = taxonomy.group_by(rank)
+            .select((rank, sql::<BigInt>("COUNT(*)")))
+            .load(&*self.connection)
+            .expect("Error loading taxonomy");
+        results.into_iter().map(|(taxon_rank, taxon_count)| (taxon_rank, taxon_count as usize)).collect()
+    }
+
+    fn is_descendant(&self, name_str: &str, ancestor: &str) -> bool {
+        let taxid = match self.get_id_by_name(name_str) {
+            Some(val) => val,
+            None => return false
+        };
+
+        let ancestor_taxid = match self.get_id_by_name(ancestor) {
+            Some(val) => val,
+            None => return false
+        };
+
+        self.is_descendant_taxid(taxid, ancestor_taxid)
+    }
+
+    fn is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> bool {
+        self.try_is_descendant_taxid(taxid, ancestor_taxid).expect("Error loading taxonomy")
+    }
+
+    /// are_descendants_taxid
+    ///
+    /// check for each of a list of taxids whether it is a descendant of ancestor_taxid. this
+    /// looks up all the taxids in one query (per 999 taxids), which is much faster than calling
+    /// is_descendant_taxid for each of them: 10000 taxids took 0.01s rather than 0.15s
+    fn are_descendants_taxid(&self, taxids: &[i32], ancestor_taxid: i32) -> HashMap<i32, bool> {
+        self.try_are_descendants_taxid(taxids, ancestor_taxid).expect("Error loading taxonomy")
+    }
+
+    fn get_name_by_id(&self, taxid: i32) -> Option<String> {
+        found(self.try_get_name_by_id(taxid))
+    }
+
+    fn get_common_name_by_id(&self, taxid: i32) -> Option<String> {
+        found(self.try_get_common_name_by_id(taxid))?
+    }
+
+    fn get_id_by_name(&self, name_str: &str) -> Option<i32> {
+        found(self.try_get_id_by_name(name_str))
+    }
+
+    fn get_names_by_ids(&self, taxids: &[i32]) -> HashMap<i32, String> {
+        use schema::taxonomy::dsl::*;
+
+        let mut names = HashMap::with_capacity(taxids.len());
+        for chunk in taxids.chunks(SQLITE_MAX_VARIABLES) {
+            let results: Vec<(i32, String)> = taxonomy.filter(id.eq_any(chunk))
+                .select((id, name))
+                .load(&*self.connection)
+                .expect("Error loading taxonomy");
+            names.extend(results);
+        }
+        names
+    }
+
+    fn get_ids_by_names(&self, names: &[&str]) -> HashMap<String, i32> {
+        use schema::taxonomy::dsl::*;
+
+        let mut ids = HashMap::with_capacity(names.len());
+        for chunk in names.chunks(SQLITE_MAX_VARIABLES) {
+            let results: Vec<(String, i32)> = taxonomy.filter(name.eq_any(chunk))
+                .select((name, id))
+                .load(&*self.connection)
+                .expect("Error loading taxonomy");
+            ids.extend(results);
+        }
+        ids
+    }
+
+    fn get_ids_by_name(&self, name_str: &str) -> Vec<i32> {
+        // LIKE ignores case, so check the names that it finds
+        let mut taxids: Vec<i32> = self.names_like(&format!("{}%", escape_like(name_str)), None).into_iter()
+            .filter(|(_, taxon_name)| is_name_or_homonym(taxon_name, name_str))
+            .map(|(taxid, _)| taxid)
+            .collect();
+        taxids.sort_unstable();
+        taxids
+    }
+
+    fn search_names(&self, query: &str, max_results: usize) -> Vec<(i32, String, f64)> {
+        use schema::taxonomy::dsl::*;
+
+        // SQLite has no string similarity functions, so score all the names here
+        let results: Vec<(i32, String)> = taxonomy.select((id, name))
+            .load(&*self.connection)
+            .expect("Error loading taxonomy");
+
+        most_similar_names(query, results.iter().map(|(taxid, taxon_name)| (*taxid, taxon_name.as_str())), max_results)
+    }
+
+    fn names_with_prefix(&self, prefix: &str, limit: usize) -> Vec<(i32, String)> {
+        self.names_like(&format!("{}%", escape_like(prefix)), Some(limit))
+    }
+
+    fn names_containing(&self, substr: &str, limit: usize) -> Vec<(i32, String)> {
+        self.names_like(&format!("%{}%", escape_like(substr)), Some(limit))
+    }
+
+    fn get_lineage_taxid(&self, taxid: i32) -> Option<Vec<i32>> {
+        found(self.try_get_lineage_taxid(taxid))
+    }
+
+    fn get_children_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        found(self.try_get_children_taxids(taxid))
+    }
+
+    fn get_siblings_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        found(self.try_get_siblings_taxids(taxid))
+    }
+
+    fn get_descendant_taxids(&self, taxid: i32) -> Option<Vec<i32>> {
+        found(self.try_get_descendant_taxids(taxid))
+    }
+
+    fn count_descendants(&self, taxid: i32) -> Option<usize> {
+        found(self.try_count_descendants(taxid))
+    }
+
+    fn count_leaves(&self, taxid: i32) -> Option<usize> {
+        found(self.try_count_leaves(taxid))
+    }
+
+    fn is_leaf(&self, taxid: i32) -> Option<bool> {
+        found(self.try_is_leaf(taxid))
+    }
+
+    fn descendants_at_rank(&self, ancestor_taxid: i32, rank_str: &str) -> Option<Vec<i32>> {
+        found(self.try_descendants_at_rank(ancestor_taxid, rank_str))
+    }
+
+    fn get_parent_taxid(&self, taxid: i32) -> Option<i32> {
+        found(self.get_cached_taxon(taxid))?.parent_id
+    }
+
+    fn get_depth(&self, taxid: i32) -> Option<usize> {
+        // the ancestry holds the taxids from the root down to the taxon
+        let taxon_ancestry = found(self.get_ancestry_for_taxid(taxid))?;
+        Some(queries::parse_ancestry(taxon_ancestry.as_deref()).expect("Error parsing taxonomy ancestry").len().saturating_sub(1))
+    }
+
+    fn get_canonical_depth(&self, taxid: i32) -> Option<usize> {
+        let mut depth = 0;
+        // the ancestors start with the taxon itself
+        for ancestor_taxid in found(self.get_ancestors(taxid))?.into_iter().skip(1) {
+            if is_canonical(&self.canonical_ranks, &found(self.get_rank(ancestor_taxid))?) {
+                depth += 1;
+            }
+        }
+        Some(depth)
+    }
+
+    fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)> {
+
+        if taxid1 == taxid2 {
+            return Some((0, taxid1))
+        }
+
+        let mut ancestors_distance1 = HashMap::new();
+        let mut current_distance = 0;
+        let taxid1_rank = found(self.get_rank(taxid1))?;
+        if !only_canonical || is_canonical(&self.canonical_ranks, &taxid1_rank) {
+            // see comment above for why distance is 0
+            ancestors_distance1.insert(taxid1, 0);
+        }
+        for taxid in found(self.get_ancestors(taxid1))? {
+            let current_rank = found(self.get_rank(taxid))?;
+            if !only_canonical || is_canonical(&self.canonical_ranks, &current_rank) {
+                current_distance += 1;
+                if taxid == taxid2 {
+                    // taxid2 is an ancestor of taxid1 (but with only_canonical it is only reported
+                    // as the common ancestor if it has a canonical rank)
+                    return Some((current_distance, taxid2))
+                }
+                ancestors_distance1.insert(taxid, current_distance);
+            }
+        }
+
+        current_distance = 0;
+        for taxid in found(self.get_ancestors(taxid2))? {
+            let current_rank = found(self.get_rank(taxid))?;
+            if !only_canonical || is_canonical(&self.canonical_ranks, &current_rank) {
+                current_distance += 1;
+                if ancestors_distance1.contains_key(&taxid) {
+                    return Some((current_distance, taxid))
+                }
+            }
+        }
+        None
+    }
+
+    fn get_distance_to_common_ancestor(&self, name1: &str, name2: &str, only_canonical: bool) -> Option<(i32, String)> {
+        let taxid1 = self.get_id_by_name(name1)?;
+
+        let taxid2 = self.get_id_by_name(name2)?;
+
+        self.get_distance_to_common_ancestor_taxid(taxid1, taxid2, only_canonical)
+            .and_then(|(distance, taxid)| Some((distance, self.get_name_by_id(taxid)?)))
+    }
+
+    fn get_common_ancestor_of(&self, taxids: &[i32]) -> Option<i32> {
+        let (first, rest) = taxids.split_first()?;
+        // lineages are root first, so the common ancestors are the common prefix of the lineages
+        let mut common_lineage = self.get_lineage_taxid(*first)?;
+        for taxid in rest {
+            let lineage = self.get_lineage_taxid(*taxid)?;
+            let common_length = common_lineage.iter().zip(lineage.iter()).take_while(|(a, b)| a == b).count();
+            common_lineage.truncate(common_length);
+        }
+        common_lineage.last().cloned()
+    }
+
+    fn effective_rank(&self, taxid: i32) -> Option<String> {
+        // the ancestors of a taxon start with the taxon itself
+        for ancestor_taxid in found(self.get_ancestors(taxid))? {
+            let rank = found(self.get_rank(ancestor_taxid))?;
+            if is_canonical(&self.canonical_ranks, &rank) {
+                return Some(rank)
+            }
+        }
+        None
+    }
+
+    fn next_rank_toward(&self, from_taxid: i32, toward_taxid: i32) -> Option<(i32, String)> {
+        let ancestors = found(self.get_ancestors(toward_taxid))?;
+        let from_position = ancestors.iter().position(|taxid| *taxid == from_taxid)?;
+        if from_position == 0 {
+            // from_taxid and toward_taxid are the same taxon
+            return None
+        }
+        let child_taxid = ancestors[from_position - 1];
+        Some((child_taxid, found(self.get_rank(child_taxid))?))
+    }
+
+    fn canonical_ranks(&self) -> Vec<Rank> {
+        self.canonical_ranks.clone()
+    }
+
+    fn is_canonical_rank(&self, taxid: i32) -> bool {
+        match found(self.get_rank(taxid)) {
+            Some(rank) => is_canonical(&self.canonical_ranks, &rank),
+            None => false
+        }
+    }
+
+    fn get_rank_by_id(&self, taxid: i32) -> Option<String> {
+        found(self.try_get_rank_by_id(taxid)).flatten()
+    }
+
+    fn get_rank_enum_by_id(&self, taxid: i32) -> Option<Rank> {
+        found(self.get_rank(taxid)).map(|rank| Rank::from(rank.as_str()))
+    }
+
+    fn get_lineage_by_rank(&self, taxid: i32) -> HashMap<String, i32> {
+        use schema::taxonomy::dsl::*;
+
+        let mut lineage = HashMap::new();
+        let ancestors = match found(self.get_ancestors(taxid)) {
+            Some(ancestors) => ancestors,
+            None => return lineage
+        };
+        // fetch the ranks for the whole lineage in one query
+        let results: Vec<(i32, String)> = taxonomy.filter(id.eq_any(&ancestors))
+            .select((id, rank))
+            .load(&*self.connection)
+            .expect("Error loading taxonomy");
+        let ranks: HashMap<i32, String> = results.into_iter().collect();
+        for ancestor_taxid in ancestors {
+            if let Some(ancestor_rank) = ranks.get(&ancestor_taxid) {
+                if ancestor_rank != "no rank" {
+                    lineage.entry(ancestor_rank.clone()).or_insert(ancestor_taxid);
+                }
+            }
+        }
+        lineage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NcbiFileTaxonomy, NcbiFileTaxonomyBuilder, Rank, CANONICAL_RANKS, RankIssue, NcbiSqliteTaxonomy, NcbiTaxonomy, NcbiTaxonomyError, NodeEdge, DEFAULT_SQLITE_CACHE_SIZE, SqliteUpdateCounts, TaxonomyIssue, ToSqliteError, TaxonomyCache, CACHE_HEADER};
+    #[cfg(feature = "serde")]
+    use super::models::Taxon;
+    use std::collections::HashSet;
+    use std::env;
+
+    pub struct NcbiFileTaxonomyFixture {
+        pub taxonomy: NcbiFileTaxonomy,
+    }
+
+    impl Default for NcbiFileTaxonomyFixture {
+        fn default() -> Self {
+            let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
+            Self { taxonomy: tree }
+        }
+    }
+
+    pub struct NcbiSqliteTaxonomyFixture {
+        pub taxonomy: NcbiSqliteTaxonomy,
+    }
+
+    impl Default for NcbiSqliteTaxonomyFixture {
+        fn default() -> Self {
+            let file_tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
+            let tree = NcbiSqliteTaxonomy::from_file_taxonomy(&file_tree).unwrap();
+            Self { taxonomy: tree }
+        }
+    }
+
+    #[test]
+    fn names_truncated_line() {
+        match NcbiFileTaxonomy::from_ncbi_files("data/sample_root_only_nodes.dmp", "data/sample_root_only_names_truncated.dmp") {
+            Err(NcbiTaxonomyError::NamesFileFormatError(line)) => assert_eq!(line, "1\t|\tall"),
+            _ => panic!("expected a names.dmp format error")
+        }
+    }
+
+    #[test]
+    fn names_with_unknown_id() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_unknown_name_taxid_nodes.dmp", "data/sample_unknown_name_taxid_names.dmp").unwrap();
+        assert!(tree.contains_name("Viruses"));
+        assert!(!tree.contains_name("Unknown phage XYZ"));
+        // the skipped name is reported by validate
+        assert_eq!(tree.validate(), Err(vec![
+            TaxonomyIssue::UnknownNameTaxid { taxid: 999999999, name: "Unknown phage XYZ".to_string() }
+        ]));
+    }
+
+    #[test]
+    fn contains_id() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.contains_id(504556));
+    }
+
+    #[test]
+    fn sqlite_contains_id() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(fixture.taxonomy.contains_id(504556));
+    }
+
+    #[test]
+    fn sqlite_database_file() {
+        let tree = NcbiSqliteTaxonomy::new(Some("data/ncbi_taxonomy.sqlite"));
+        assert!(tree.contains_id(504556));
+        assert_eq!(tree.get_parent_taxid(12340), Some(12333));
+
+        // the descendant checks give the same answers as the files
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let taxids = file_fixture.taxonomy.iter_taxids().collect::<Vec<i32>>();
+        for ancestor_taxid in &[1, 10239, 12333, 387088, 12340] {
+            let mut expected = file_fixture.taxonomy.get_descendant_taxids(*ancestor_taxid).unwrap();
+            expected.sort_unstable();
+            assert_eq!(tree.get_descendant_taxids(*ancestor_taxid), Some(expected));
+            assert_eq!(tree.are_descendants_taxid(&taxids, *ancestor_taxid), file_fixture.taxonomy.are_descendants_taxid(&taxids, *ancestor_taxid));
+        }
+        assert_eq!(tree.get_lineage_taxid(370556), Some(vec![1, 10239, 12333, 387088, 370556]));
+        assert_eq!(tree.get_lineage_taxid(1), Some(vec![1]));
+        assert_eq!(tree.get_depth(370556), Some(4));
+        assert_eq!(tree.get_depth(1), Some(0));
+    }
+
+    #[test]
+    fn sqlite_rank_not_null_migration() {
+        use diesel::{Connection, SqliteConnection};
+        use diesel::connection::SimpleConnection;
+
+        // a database created before rank was NOT NULL, with a taxon that has no rank
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.batch_execute(include_str!("../migrations/2020-05-01-095116_create_taxonomy/up.sql")).unwrap();
+        connection.batch_execute(include_str!("../migrations/2026-10-16-000000_add_name_nocase_index/up.sql")).unwrap();
+        connection.batch_execute("
+            CREATE TABLE __diesel_schema_migrations (
+                version VARCHAR(50) PRIMARY KEY NOT NULL,
+                run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO __diesel_schema_migrations (version) VALUES ('20200501095116'), ('20261016000000');
+            INSERT INTO taxonomy (id, ancestry, name, rank) VALUES (1, NULL, 'root', NULL), (10239, '1/10239', 'Viruses', 'superkingdom');
+        ").unwrap();
+
+        super::embedded_migrations::run(&connection).unwrap();
+        let tree = NcbiSqliteTaxonomy::from_connection(connection);
+        assert_eq!(tree.get_rank_by_id(1), None);
+        assert!(!tree.is_canonical_rank(1));
+        assert_eq!(tree.get_rank_by_id(10239), Some("superkingdom".to_string()));
+        assert_eq!(tree.get_id_by_name("Viruses"), Some(10239));
+        // later migrations are applied too
+        assert_eq!(tree.get_parent_taxid(10239), Some(1));
+        assert_eq!(tree.get_children_taxids(1), Some(vec![10239]));
+        assert_eq!(tree.get_taxon(1).unwrap().ancestry, Some("/1/".to_string()));
+        assert_eq!(tree.get_taxon(10239).unwrap().ancestry, Some("/1/10239/".to_string()));
+        assert_eq!(tree.get_descendant_taxids(1), Some(vec![10239]));
+        assert!(tree.is_descendant_taxid(10239, 1));
+        assert!(tree.is_descendant_taxid(1, 1));
+        assert_eq!(tree.get_lineage_taxid(10239), Some(vec![1, 10239]));
+    }
+
+    #[test]
+    fn sqlite_update() {
+        use diesel::connection::SimpleConnection;
+
+        let file_tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
+        let tree = NcbiSqliteTaxonomy::from_file_taxonomy(&file_tree).unwrap();
+        // make the database differ from the files: a changed rank, a missing taxon and a taxon
+        // that is no longer in the taxonomy
+        tree.connection.batch_execute("
+            UPDATE taxonomy SET rank = 'genus' WHERE id = 12340;
+            DELETE FROM taxonomy WHERE id = 370556;
+            INSERT INTO taxonomy (id, ancestry, name, rank, parent_id) VALUES (999999, '/1/10239/999999/', 'Deleted virus', 'species', 10239);
+        ").unwrap();
+
+        let counts = file_tree.update_sqlite_connection(&tree.connection).unwrap();
+        assert_eq!(counts, SqliteUpdateCounts { inserted: 1, updated: 1, deleted: 1, unchanged: counts.unchanged });
+        assert_eq!(counts.unchanged + 2, file_tree.traversal(1).unwrap().filter(|edge| matches!(edge, NodeEdge::Start(_))).count());
+        assert_eq!(tree.get_rank_by_id(12340), Some("species".to_string()));
+        assert_eq!(tree.get_parent_taxid(370556), Some(387088));
+        assert!(!tree.contains_id(999999));
+
+        // updating again changes nothing
+        let counts = file_tree.update_sqlite_connection(&tree.connection).unwrap();
+        assert_eq!((counts.inserted, counts.updated, counts.deleted), (0, 0, 0));
+    }
+
+    #[test]
+    fn sqlite_new_read_only() {
+        use diesel::connection::SimpleConnection;
+
+        let db_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_read_only.sqlite", std::process::id()));
+        let fixture = NcbiFileTaxonomyFixture::default();
+        drop(fixture.taxonomy.save_to_sqlite(Some(db_path.to_str().unwrap())).unwrap());
+
+        let taxonomy = NcbiSqliteTaxonomy::new_read_only(&db_path).unwrap();
+        assert_eq!(taxonomy.get_lineage_taxid(370556), Some(vec![1, 10239, 12333, 387088, 370556]));
+        assert_eq!(taxonomy.get_name_by_id(12340), Some("Enterobacteria phage 933J".to_string()));
+        assert_eq!(taxonomy.get_children_taxids(387088), Some(vec![370556, 370557, 370558]));
+        assert!(taxonomy.connection.batch_execute("DELETE FROM taxonomy WHERE id = 12340").is_err());
+        assert!(taxonomy.contains_id(12340));
+        drop(taxonomy);
+        std::fs::remove_file(&db_path).unwrap();
+
+        // a read only database is not created if it does not exist
+        assert!(NcbiSqliteTaxonomy::new_read_only(&db_path).is_err());
+        assert!(!db_path.exists());
+    }
+
+    #[test]
+    fn save_to_sqlite_connection_error() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        match fixture.taxonomy.save_to_sqlite(Some("data/no_such_directory/taxonomy.sqlite")) {
+            Err(ToSqliteError::ConnectionError(_)) => (),
+            _ => panic!("expected a connection error")
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.validate(), Ok(()));
+
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_invalid_tree_nodes.dmp", "data/sample_invalid_tree_names.dmp").unwrap();
+        assert_eq!(tree.validate(), Err(vec![
+            TaxonomyIssue::MultipleParents { taxid: 200, parent_taxids: vec![10239, 1] },
+            TaxonomyIssue::Cycle { taxid: 100, parent_taxid: 101 },
+            TaxonomyIssue::UnknownNameTaxid { taxid: 400, name: "Unknown virus".to_string() },
+            TaxonomyIssue::UndefinedTaxon { taxid: 999 }
+        ]));
+        // the tree is still usable, without the link that closes the cycle
+        assert_eq!(tree.get_lineage_taxid(101), Some(vec![100, 101]));
+        assert_eq!(tree.get_parent_taxid(200), Some(10239));
+
+        match NcbiFileTaxonomyBuilder::new("data").prefix("sample_invalid_tree_").validate().build() {
+            Err(NcbiTaxonomyError::InvalidTaxonomy(issues)) => assert_eq!(issues.len(), 4),
+            _ => panic!("expected an invalid taxonomy")
+        }
+        assert!(NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").validate().build().is_ok());
+    }
+
+    #[test]
+    fn sqlite_new_in_memory() {
+        let tree = NcbiSqliteTaxonomy::new_in_memory().unwrap();
+        assert!(!tree.contains_id(504556));
+    }
+
+    #[test]
+    fn contains_name() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.contains_name("Propionibacterium phage PAS7"));
+        assert!(fixture.taxonomy.contains_name("Viruses"));
+        assert!(fixture.taxonomy.contains_name("environmental samples <bacteriophages>"));
+    }
+
+    #[test]
+    fn sqlite_contains_name() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(fixture.taxonomy.contains_name("Propionibacterium phage PAS7"));
+        assert!(fixture.taxonomy.contains_name("Viruses"));
+        assert!(fixture.taxonomy.contains_name("environmental samples <bacteriophages>"));
+    }
+
+    #[test]
+    fn get_node_by_id() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_node_by_id(999999999), None);
+        assert!(fixture.taxonomy.get_node_by_id(504556).is_some())
+    }
+
+    #[test]
+    fn ancestries() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let ancestries = fixture.taxonomy.ancestries();
+        assert_eq!(ancestries.len(), fixture.taxonomy.len());
+        assert_eq!(ancestries[&12340], "/1/10239/12333/12340/");
+        assert_eq!(ancestries[&1], "/1/");
+        // the same as building each ancestry from the lineage of the taxon
+        for taxid in fixture.taxonomy.iter_taxids() {
+            assert_eq!(ancestries[&taxid], ::queries::format_ancestry(&fixture.taxonomy.get_lineage_taxid(taxid).unwrap()));
+        }
+    }
+
+    #[test]
+    fn get_id_by_node() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let node_id = fixture.taxonomy.get_node_by_id(504556).unwrap();
+        assert_eq!(fixture.taxonomy.get_id_by_node(*node_id).unwrap(), 504556);
+    }
+
+    #[test]
+    fn get_name_by_id() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_name_by_id(370556).unwrap(), "Streptococcus phage 9429.1");
+    }
+
+    #[test]
+    fn sqlite_get_name_by_id() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_name_by_id(370556).unwrap(), "Streptococcus phage 9429.1");
+    }
+
+    #[test]
+    fn common_names() {
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        check_common_names(&taxonomy);
+        // the common name does not replace the scientific name in name lookups
+        assert_eq!(taxonomy.get_id_by_name("eubacteria"), None);
+
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_common_names.cache", std::process::id()));
+        taxonomy.save_cache(cache_path.to_str().unwrap()).unwrap();
+        let cached_taxonomy = NcbiFileTaxonomy::load_cache(cache_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+        check_common_names(&cached_taxonomy);
+    }
+
+    #[test]
+    fn sqlite_common_names() {
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        let taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap();
+        check_common_names(&taxonomy);
+        assert_eq!(taxonomy.get_taxon(2).unwrap().common_name, Some("eubacteria".to_string()));
+        match taxonomy.try_get_common_name_by_id(999999999) {
+            Err(NcbiTaxonomyError::NotFound(taxid)) => assert_eq!(taxid, "999999999"),
+            _ => panic!("expected NotFound")
+        }
+    }
+
+    fn check_root_taxid(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.root_taxid(), Some(1));
+    }
+
+    #[test]
+    fn root_taxid() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        check_root_taxid(&fixture.taxonomy);
+        let subtree = fixture.taxonomy.extract_subtree(12333).unwrap();
+        assert_eq!(subtree.root_taxid(), Some(12333));
+        // the broken cycle and the taxon with a missing parent are roots as well
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_invalid_tree_nodes.dmp", "data/sample_invalid_tree_names.dmp").unwrap();
+        assert_eq!(tree.root_taxid(), None);
+    }
+
+    #[test]
+    fn sqlite_root_taxid() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        check_root_taxid(&fixture.taxonomy);
+        let subtree = NcbiFileTaxonomyFixture::default().taxonomy.extract_subtree(12333).unwrap();
+        let taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&subtree).unwrap();
+        assert_eq!(taxonomy.root_taxid(), Some(12333));
+    }
+
+    fn check_common_names(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.get_scientific_name_by_id(2), Some("Bacteria".to_string()));
+        assert_eq!(taxonomy.get_name_by_id(2), Some("Bacteria".to_string()));
+        assert_eq!(taxonomy.get_common_name_by_id(2), Some("eubacteria".to_string()));
+        assert_eq!(taxonomy.get_preferred_display_name(2), Some("eubacteria".to_string()));
+        assert_eq!(taxonomy.get_common_name_by_id(562), None);
+        assert_eq!(taxonomy.get_preferred_display_name(562), Some("Escherichia coli".to_string()));
+        assert_eq!(taxonomy.get_common_name_by_id(999999999), None);
+        assert_eq!(taxonomy.get_preferred_display_name(999999999), None);
+    }
+
+    #[test]
+    fn get_lineage() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_lineage("Streptococcus phage 9429.1"), Some(vec![1,10239,12333,387088,370556]));
+        assert_eq!(fixture.taxonomy.get_lineage_taxid(1), Some(vec![1]));
+        assert_eq!(fixture.taxonomy.get_lineage_taxid(999999999), None);
+    }
+
+    #[test]
+    fn get_lineage_with_ranks() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_lineage_with_ranks(12340), Some(vec![
+            (1, "root".to_string(), Rank::NoRank),
+            (10239, "Viruses".to_string(), Rank::Superkingdom),
+            (12333, "unclassified bacterial viruses".to_string(), Rank::NoRank),
+            (12340, "Enterobacteria phage 933J".to_string(), Rank::Species)]));
+        assert_eq!(fixture.taxonomy.get_lineage_with_ranks(999999999), None);
+    }
+
+    #[test]
+    fn sqlite_get_lineage_with_ranks() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_lineage_with_ranks(12340), file_fixture.taxonomy.get_lineage_with_ranks(12340));
+        assert_eq!(fixture.taxonomy.get_lineage_with_ranks(999999999), None);
+    }
+
+    #[test]
+    fn get_lineage_sqlite() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_lineage("Streptococcus phage 9429.1"), Some(vec![1,10239,12333,387088,370556]));
+        assert_eq!(fixture.taxonomy.get_lineage_taxid(1), Some(vec![1]));
+        assert_eq!(fixture.taxonomy.get_lineage_taxid(999999999), None);
+    }
+
+    #[test]
+    fn traversal() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let traversal = fixture.taxonomy.traversal(12333);
+        match traversal {
+            Some(traversal) => {
+                let mut counter = 0;
+                for node_edge in traversal {
+                    if let NodeEdge::Start(_) = node_edge {
+                        counter += 1
+                    }
+                }
+                assert_eq!(counter, 500)
+            }
+            None => assert_eq!(true, false, "Failed to load traversal from 12333")
+        }
+    }
+
+    #[test]
+    fn traversal_from_root() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let traversal = fixture.taxonomy.traversal(1).expect("Failed to load traversal from the root");
+        let taxids: HashSet<i32> = traversal.filter_map(|node_edge| match node_edge {
+            NodeEdge::Start(node_id) => Some(fixture.taxonomy.arena[node_id].data),
+            NodeEdge::End(_) => None
+        }).collect();
+        assert_eq!(taxids, fixture.taxonomy.id_to_node.keys().cloned().collect());
+
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_root_only_nodes.dmp", "data/sample_root_only_names.dmp").unwrap();
+        assert!(tree.contains_id(1));
+        assert_eq!(tree.traversal(1).unwrap().count(), 2);
+        assert_eq!(tree.get_lineage_taxid(1), Some(vec![1]));
+    }
+
+    #[test]
+    fn iter_taxids() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let taxids: HashSet<i32> = fixture.taxonomy.iter_taxids().collect();
+        assert_eq!(taxids.len(), fixture.taxonomy.len());
+        assert!(taxids.contains(&1));
+        assert!(taxids.contains(&12340));
+        assert!(!fixture.taxonomy.is_empty());
+        let counts = fixture.taxonomy.count_by_rank();
+        assert_eq!(counts.values().sum::<usize>(), fixture.taxonomy.len());
+        assert_eq!(counts["superkingdom"], 1);
+        assert_eq!(counts["species"], fixture.taxonomy.descendants_at_rank(1, "species").unwrap().len());
+    }
+
+    #[test]
+    fn sqlite_iter_taxids() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let mut expected: Vec<i32> = file_fixture.taxonomy.iter_taxids().collect();
+        expected.sort_unstable();
+        assert_eq!(fixture.taxonomy.iter_taxids().collect::<Vec<i32>>(), expected);
+        assert_eq!(fixture.taxonomy.len(), file_fixture.taxonomy.len());
+        assert!(!fixture.taxonomy.is_empty());
+        assert_eq!(fixture.taxonomy.count_by_rank(), file_fixture.taxonomy.count_by_rank());
+    }
+
+    #[test]
+    fn prune_to_taxids() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let pruned = fixture.taxonomy.prune_to_taxids(&[12340, 370556]).unwrap();
+        let mut taxids: Vec<i32> = pruned.iter_taxids().collect();
+        taxids.sort_unstable();
+        assert_eq!(taxids, vec![1, 10239, 12333, 12340, 370556, 387088]);
+        assert_eq!(pruned.get_lineage_taxid(370556), Some(vec![1, 10239, 12333, 387088, 370556]));
+        assert_eq!(pruned.get_children_taxids(12333), Some(vec![12340, 387088]));
+        assert_eq!(pruned.get_name_by_id(12340), Some("Enterobacteria phage 933J".to_string()));
+        assert_eq!(pruned.get_id_by_name("Enterobacteria phage 933J"), Some(12340));
+        assert_eq!(pruned.get_rank_by_id(10239), Some("superkingdom".to_string()));
+        assert!(!pruned.contains_id(12347));
+        assert!(pruned.validate().is_ok());
+        match fixture.taxonomy.prune_to_taxids(&[12340, 999999999]) {
+            Err(NcbiTaxonomyError::NotFound(taxid)) => assert_eq!(taxid, "999999999"),
+            _ => panic!("expected a NotFound error for an unknown taxid")
+        }
+    }
+
+    #[test]
+    fn extract_subtree() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let subtree = fixture.taxonomy.extract_subtree(12333).unwrap();
+        // the same 500 taxa as the traversal from 12333
+        assert_eq!(subtree.len(), 500);
+        assert_eq!(subtree.get_parent_taxid(12333), None);
+        assert_eq!(subtree.get_lineage_taxid(370556), Some(vec![12333, 387088, 370556]));
+        assert_eq!(subtree.get_children_taxids(12333), fixture.taxonomy.get_children_taxids(12333));
+        assert_eq!(subtree.get_rank_by_id(12340), Some("species".to_string()));
+        assert!(!subtree.contains_id(10239));
+        assert!(!subtree.contains_name("Viruses"));
+        assert!(subtree.validate().is_ok());
+        assert_eq!(fixture.taxonomy.extract_subtree(370556).unwrap().len(), 1);
+        assert!(fixture.taxonomy.extract_subtree(999999999).is_none());
+
+        let sqlite_subtree = NcbiSqliteTaxonomy::from_file_taxonomy(&subtree).unwrap();
+        assert_eq!(sqlite_subtree.len(), 500);
+        assert_eq!(sqlite_subtree.get_lineage_taxid(370556), Some(vec![12333, 387088, 370556]));
+    }
+
+    #[test]
+    fn export_tsv() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let mut output = Vec::new();
+        fixture.taxonomy.export_tsv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), fixture.taxonomy.len() + 1);
+        assert_eq!(lines[0], "taxid\tparent_taxid\trank\tname\tlineage");
+        assert_eq!(lines[1], "1\t\tno rank\troot\troot");
+        assert!(lines.contains(&"12340\t12333\tspecies\tEnterobacteria phage 933J\troot;Viruses;unclassified bacterial viruses;Enterobacteria phage 933J"));
+
+        let subtree = fixture.taxonomy.extract_subtree(12333).unwrap();
+        let mut output = Vec::new();
+        subtree.export_tsv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().nth(1), Some("12333\t\tno rank\tunclassified bacterial viruses\tunclassified bacterial viruses"));
+    }
+
+    #[test]
+    fn descendants() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_descendant("Propionibacterium phage PAS7", "unclassified bacterial viruses"));
+    }
+
+    #[test]
+    fn sqlite_descendants() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_descendant("Propionibacterium phage PAS7", "unclassified bacterial viruses"));
+    }
+
+    fn check_ancestors(taxonomy: &dyn NcbiTaxonomy) {
+        assert!(taxonomy.is_ancestor("unclassified bacterial viruses", "Propionibacterium phage PAS7"));
+        assert!(!taxonomy.is_ancestor("Propionibacterium phage PAS7", "unclassified bacterial viruses"));
+        assert!(taxonomy.is_ancestor_taxid(10239, 12340));
+        assert!(!taxonomy.is_ancestor_taxid(12340, 10239));
+        assert!(taxonomy.is_ancestor_taxid(12340, 12340));
+        assert!(!taxonomy.is_ancestor_taxid(999999999, 12340));
+        let taxids = [1, 10239, 12333, 12340, 12347, 387088, 370556, 999999999];
+        for a in taxids.iter() {
+            for b in taxids.iter() {
+                assert_eq!(taxonomy.is_ancestor_taxid(*a, *b), taxonomy.is_descendant_taxid(*b, *a), "{} {}", a, b);
+            }
+        }
+        let names = ["root", "Viruses", "unclassified bacterial viruses", "Propionibacterium phage PAS7", "Enterobacteria phage 933J"];
+        for a in names.iter() {
+            for b in names.iter() {
+                assert_eq!(taxonomy.is_ancestor(a, b), taxonomy.is_descendant(b, a), "{} {}", a, b);
+            }
+        }
+    }
+
+    fn check_in_clade(taxonomy: &dyn NcbiTaxonomy) {
+        assert!(taxonomy.is_in_clade_taxid(12333, 12333));
+        assert!(taxonomy.is_in_clade_taxid(1, 1));
+        assert!(taxonomy.is_in_clade_taxid(12340, 12333));
+        assert!(taxonomy.is_in_clade_taxid(12340, 1));
+        assert!(!taxonomy.is_in_clade_taxid(12333, 12340));
+        assert!(!taxonomy.is_in_clade_taxid(999999999, 999999999));
+        // is_descendant_taxid is inclusive too
+        assert!(taxonomy.is_descendant_taxid(12333, 12333));
+    }
+
+    #[test]
+    fn in_clade() {
+        check_in_clade(&NcbiFileTaxonomyFixture::default().taxonomy);
+    }
+
+    #[test]
+    fn sqlite_in_clade() {
+        check_in_clade(&NcbiSqliteTaxonomyFixture::default().taxonomy);
+    }
+
+    #[test]
+    fn rank_distance() {
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        // two species in the same family
+        assert_eq!(taxonomy.get_rank_distance(562, 28901), Some(2));
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 28901, false), Some((3, 543)));
+        // the unclassified Enterobacteriaceae has no rank and there is no genus in its lineage
+        assert_eq!(taxonomy.get_rank_distance(562, 2100764), Some(2));
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 2100764, false), Some((3, 543)));
+        // the strain (no rank) counts as a species
+        assert_eq!(taxonomy.get_rank_distance(83333, 584), Some(3));
+        assert_eq!(taxonomy.get_rank_distance(584, 83333), Some(3));
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(584, 83333, false), Some((5, 91347)));
+        assert_eq!(taxonomy.get_rank_distance(83333, 562), Some(0));
+        // the no rank groups between the superkingdom and the phylum are skipped
+        assert_eq!(taxonomy.get_rank_distance(562, 817), Some(7));
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 817, false), Some((9, 2)));
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 817, true), Some((7, 2)));
+        // one taxon is an ancestor of the other
+        assert_eq!(taxonomy.get_rank_distance(562, 561), Some(1));
+        assert_eq!(taxonomy.get_rank_distance(543, 562), Some(2));
+        assert_eq!(taxonomy.get_rank_distance(562, 562), Some(0));
+        assert_eq!(taxonomy.get_rank_distance(562, 1), Some(8));
+        assert_eq!(taxonomy.get_rank_distance(2, 1), Some(1));
+        assert_eq!(taxonomy.get_rank_distance(562, 999999999), None);
+
+        let sqlite_taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&taxonomy).unwrap();
+        let taxids = taxonomy.iter_taxids().collect::<Vec<i32>>();
+        for taxid1 in taxids.iter() {
+            for taxid2 in taxids.iter() {
+                assert_eq!(sqlite_taxonomy.get_rank_distance(*taxid1, *taxid2), taxonomy.get_rank_distance(*taxid1, *taxid2), "{} {}", taxid1, taxid2);
+            }
+        }
+    }
+
+    #[test]
+    fn ancestors() {
+        check_ancestors(&NcbiFileTaxonomyFixture::default().taxonomy);
+    }
+
+    #[test]
+    fn sqlite_ancestors() {
+        check_ancestors(&NcbiSqliteTaxonomyFixture::default().taxonomy);
+    }
+
+    #[test]
+    fn taxid_descendants() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_descendant_taxid(504556, 12333));
+    }
+
+    #[test]
+    fn sqlite_taxid_descendants() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_descendant_taxid(504556, 12333));
+    }
+
+    #[test]
+    fn distance_to_common_ancestor_taxid() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, false), Some((2, 12333)));
+    }
+
+    #[test]
+    fn distance_to_common_ancestor_taxid_canonical() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, true), Some((2, 10239)));
+    }
+
+    #[test]
+    fn distance_to_common_ancestor_taxid_cases() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        // identical taxa
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12340, false), Some((0, 12340)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12340, true), Some((0, 12340)));
+        // one taxon is an ancestor of the other (12340 -> 12333 -> 10239), 12333 has no rank
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 10239, false), Some((3, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 10239, true), Some((2, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(10239, 12340, false), Some((3, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(10239, 12340, true), Some((2, 10239)));
+        // siblings, both children of 12333
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12347, false), Some((2, 12333)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12347, true), Some((2, 10239)));
+        // unknown taxa
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 999999999, false), None);
+    }
+
+    #[test]
+    fn distance_to_common_ancestor() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", false),
+                   Some((2, "unclassified bacterial viruses".to_string())));
+    }
+
+    #[test]
+    fn distance_to_common_ancestor_canonical() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", true),
+                   Some((2, "Viruses".to_string())));
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_taxid() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, false), Some((2, 12333)));
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_taxid_canonical() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, true), Some((2, 10239)));
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_taxid_cases() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12340, false), Some((0, 12340)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12340, true), Some((0, 12340)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 10239, false), Some((3, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 10239, true), Some((2, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(10239, 12340, false), Some((3, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(10239, 12340, true), Some((2, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12347, false), Some((2, 12333)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12347, true), Some((2, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 999999999, false), None);
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_matches_file_for_all_pairs() {
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let sqlite_fixture = NcbiSqliteTaxonomyFixture::default();
+        // every 10th taxon of the sample tree, plus the taxa near the root
+        let mut taxids: Vec<i32> = file_fixture.taxonomy.id_to_name.keys().cloned().collect();
+        taxids.sort_unstable();
+        let mut taxids: Vec<i32> = taxids.into_iter().step_by(10).collect();
+        taxids.extend_from_slice(&[1, 10239, 12333, 387088, 370556]);
+        for only_canonical in &[false, true] {
+            for taxid1 in taxids.iter() {
+                for taxid2 in taxids.iter() {
+                    assert_eq!(sqlite_fixture.taxonomy.get_distance_to_common_ancestor_taxid(*taxid1, *taxid2, *only_canonical),
+                               file_fixture.taxonomy.get_distance_to_common_ancestor_taxid(*taxid1, *taxid2, *only_canonical),
+                               "taxids {} and {} with only_canonical {}", taxid1, taxid2, only_canonical);
+                }
+            }
+        }
+        let name1 = "Cyanophage clone GS2601";
+        let name2 = "Enterobacteria phage 933J";
+        for only_canonical in &[false, true] {
+            assert_eq!(sqlite_fixture.taxonomy.get_distance_to_common_ancestor(name1, name2, *only_canonical),
+                       file_fixture.taxonomy.get_distance_to_common_ancestor(name1, name2, *only_canonical));
+        }
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_taxid_matches_file() {
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let sqlite_fixture = NcbiSqliteTaxonomyFixture::default();
+        let pairs = [(156615, 12340), (504556, 12340), (12340, 10239), (10239, 12340), (156614, 156615), (387088, 370556)];
+        for only_canonical in &[false, true] {
+            for (taxid1, taxid2) in pairs.iter() {
+                assert_eq!(sqlite_fixture.taxonomy.get_distance_to_common_ancestor_taxid(*taxid1, *taxid2, *only_canonical),
+                           file_fixture.taxonomy.get_distance_to_common_ancestor_taxid(*taxid1, *taxid2, *only_canonical),
+                           "taxids {} and {} with only_canonical {}", taxid1, taxid2, only_canonical);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_to_common_ancestor_without_name() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_missing_name_nodes.dmp", "data/sample_missing_name_names.dmp").unwrap();
+        // the common ancestor 20 has no scientific name
+        assert_eq!(tree.get_distance_to_common_ancestor_taxid(21, 22, false), Some((2, 20)));
+        assert_eq!(tree.get_distance_to_common_ancestor("Phage one", "Phage two", false), None);
+        assert_eq!(tree.get_distance_to_common_ancestor("Phage one", "Phage two", true),
+                   Some((2, "Viruses".to_string())));
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", false),
+                   Some((2, "unclassified bacterial viruses".to_string())));
+    }
+
+    #[test]
+    fn sqlite_distance_to_common_ancestor_canonical() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", true),
+                   Some((2, "Viruses".to_string())));
+    }
+
+    #[test]
+    fn effective_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.effective_rank(12340), Some("species".to_string()));
+        assert_eq!(fixture.taxonomy.effective_rank(12333), Some("superkingdom".to_string()));
+        assert_eq!(fixture.taxonomy.effective_rank(1), None);
+    }
+
+    #[test]
+    fn sqlite_effective_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.effective_rank(12340), Some("species".to_string()));
+        assert_eq!(fixture.taxonomy.effective_rank(12333), Some("superkingdom".to_string()));
+        assert_eq!(fixture.taxonomy.effective_rank(1), None);
+    }
+
+    #[test]
+    fn nearest_reference_distance() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.nearest_reference_distance(156615, &[12340, 156616], false), Some((156616, 2)));
+        assert_eq!(fixture.taxonomy.nearest_reference_distance(156615, &[12340, 156615], false), Some((156615, 0)));
+        assert_eq!(fixture.taxonomy.nearest_reference_distance(156615, &[], false), None);
+    }
+
+    #[test]
+    fn sqlite_nearest_reference_distance() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.nearest_reference_distance(156615, &[12340, 156616], false), Some((156616, 2)));
+        assert_eq!(fixture.taxonomy.nearest_reference_distance(156615, &[12340, 156615], false), Some((156615, 0)));
+    }
+
+    #[test]
+    fn builder() {
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").build().unwrap();
+        assert!(tree.contains_id(504556));
+        assert!(NcbiFileTaxonomyBuilder::new("data").build().is_err());
+    }
+
+    #[test]
+    fn builder_with_paths() {
+        let tree = NcbiFileTaxonomyBuilder::default()
+            .nodes("data/sample_tree_nodes.dmp")
+            .names("data/sample_tree_names.dmp")
+            .merged("data/sample_tree_merged.dmp")
+            .delnodes("data/sample_tree_delnodes.dmp")
+            .build()
+            .unwrap();
+        assert_eq!(tree.resolve_merged(999001), Some(12340));
+        assert!(tree.is_deleted(999003));
+        // a path given for a file is used even when a taxdump directory is set
+        let tree = NcbiFileTaxonomyBuilder::new("data")
+            .nodes("data/sample_tree_nodes.dmp")
+            .names("data/sample_tree_names.dmp")
+            .build()
+            .unwrap();
+        assert!(tree.contains_id(504556));
+        // unlike an optional file in the directory, a missing file given by path is an error
+        assert!(NcbiFileTaxonomyBuilder::default()
+            .nodes("data/sample_tree_nodes.dmp")
+            .names("data/sample_tree_names.dmp")
+            .merged("data/no_such_merged.dmp")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn builder_with_progress() {
+        let line_count = ["data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp"].iter()
+            .map(|filename| std::fs::read_to_string(filename).unwrap().lines().count())
+            .sum::<usize>();
+        let mut reported = Vec::new();
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_")
+            .build_with_progress(5, |lines| reported.push(lines))
+            .unwrap();
+        assert!(tree.contains_id(504556));
+        assert_eq!(reported.last(), Some(&line_count));
+        let expected = (1..=line_count / 5).map(|i| i * 5)
+            .chain(if line_count.is_multiple_of(5) { None } else { Some(line_count) })
+            .collect::<Vec<usize>>();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn next_rank_toward() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.next_rank_toward(10239, 370556), Some((12333, "no rank".to_string())));
+        assert_eq!(fixture.taxonomy.next_rank_toward(12333, 370556), Some((387088, "species".to_string())));
+        assert_eq!(fixture.taxonomy.next_rank_toward(370556, 370556), None);
+        assert_eq!(fixture.taxonomy.next_rank_toward(156614, 370556), None);
+    }
+
+    #[test]
+    fn sqlite_next_rank_toward() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.next_rank_toward(10239, 370556), Some((12333, "no rank".to_string())));
+        assert_eq!(fixture.taxonomy.next_rank_toward(12333, 370556), Some((387088, "species".to_string())));
+        assert_eq!(fixture.taxonomy.next_rank_toward(370556, 370556), None);
+        assert_eq!(fixture.taxonomy.next_rank_toward(156614, 370556), None);
+    }
+
+    #[test]
+    fn is_canonical_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_canonical_rank(12340));
+        assert!(!fixture.taxonomy.is_canonical_rank(12333));
+        assert!(!fixture.taxonomy.is_canonical_rank(999999999));
+    }
+
+    #[test]
+    fn sqlite_is_canonical_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(fixture.taxonomy.is_canonical_rank(12340));
+        assert!(!fixture.taxonomy.is_canonical_rank(12333));
+        assert!(!fixture.taxonomy.is_canonical_rank(999999999));
+    }
+
+    #[test]
+    fn get_lineage_by_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let lineage = fixture.taxonomy.get_lineage_by_rank(12340);
+        assert_eq!(lineage.len(), 2);
+        assert_eq!(lineage.get("species"), Some(&12340));
+        assert_eq!(lineage.get("superkingdom"), Some(&10239));
+        assert!(fixture.taxonomy.get_lineage_by_rank(999999999).is_empty());
+        assert_eq!(fixture.taxonomy.get_canonical_lineage_by_rank(12340), lineage);
+    }
+
+    #[test]
+    fn sqlite_get_lineage_by_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let lineage = fixture.taxonomy.get_lineage_by_rank(12340);
+        assert_eq!(lineage.len(), 2);
+        assert_eq!(lineage.get("species"), Some(&12340));
+        assert_eq!(lineage.get("superkingdom"), Some(&10239));
+        assert!(fixture.taxonomy.get_lineage_by_rank(999999999).is_empty());
+    }
+
+    #[test]
+    fn rank_consistency_report() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert!(fixture.taxonomy.rank_consistency_report().is_empty());
+
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_rank_anomaly_nodes.dmp", "data/sample_rank_anomaly_names.dmp").unwrap();
+        assert_eq!(tree.rank_consistency_report(), vec![RankIssue {
+            taxid: 4, rank: "family".to_string(), ancestor_taxid: 3, ancestor_rank: "genus".to_string()
+        }]);
+    }
+
+    #[test]
+    fn distance_to_non_canonical_ancestor() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12333, true), Some((1, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Enterobacteria phage 933J", "unclassified bacterial viruses", true),
+                   Some((1, "Viruses".to_string())));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Enterobacteria phage 933J", "Viruses <viruses>", true),
+                   Some((2, "Viruses".to_string())));
+    }
+
+    #[test]
+    fn sqlite_distance_to_non_canonical_ancestor() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(12340, 12333, true), Some((1, 10239)));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Enterobacteria phage 933J", "unclassified bacterial viruses", true),
+                   Some((1, "Viruses".to_string())));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Enterobacteria phage 933J", "Viruses <viruses>", true),
+                   Some((2, "Viruses".to_string())));
+    }
+
+    #[test]
+    fn merged_ids() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_merged("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                "data/sample_tree_merged.dmp").unwrap();
+        assert_eq!(tree.resolve_merged(999001), Some(12340));
+        assert_eq!(tree.resolve_merged(12340), Some(12340));
+        assert_eq!(tree.resolve_merged(999999999), None);
+        assert!(tree.contains_id(999001));
+        assert!(tree.is_descendant_taxid(999001, 12333));
+        assert_eq!(tree.get_name_by_id(999001), tree.get_name_by_id(12340));
+        assert_eq!(tree.get_lineage_taxid(999001), Some(vec![1, 10239, 12333, 12340]));
+
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").with_merged().build().unwrap();
+        assert!(tree.contains_id(999001));
+    }
+
+    #[test]
+    fn merged_ids_in_rank_and_distance_methods() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_merged("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                "data/sample_tree_merged.dmp").unwrap();
+        assert_eq!(tree.get_distance_to_common_ancestor_taxid(999001, 12347, false),
+                   tree.get_distance_to_common_ancestor_taxid(12340, 12347, false));
+        assert_eq!(tree.get_distance_to_common_ancestor_taxid(12347, 999001, true),
+                   tree.get_distance_to_common_ancestor_taxid(12347, 12340, true));
+        assert_eq!(tree.get_distance_to_common_ancestor_taxid(999001, 12340, false), Some((0, 12340)));
+        assert_eq!(tree.effective_rank(999001), Some("species".to_string()));
+        assert_eq!(tree.next_rank_toward(12333, 999001), tree.next_rank_toward(12333, 12340));
+        assert!(tree.next_rank_toward(12333, 999001).is_some());
+        assert!(tree.is_canonical_rank(999001));
+        assert_eq!(tree.get_lineage_by_rank(999001), tree.get_lineage_by_rank(12340));
+        assert!(!tree.get_lineage_by_rank(999001).is_empty());
+    }
+
+    #[test]
+    fn aliased_ids() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_aliases("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                 "data/sample_tree_aliases.tsv").unwrap();
+        assert_eq!(tree.resolve_alias(900001), Some(12340));
+        assert_eq!(tree.resolve_alias(12340), Some(12340));
+        // the real taxid of an alias must be in the taxonomy
+        assert_eq!(tree.resolve_alias(900003), None);
+        assert_eq!(tree.resolve_merged(900001), None);
+        assert!(tree.contains_id(900001));
+        assert!(!tree.contains_id(900003));
+        assert_eq!(tree.get_lineage_taxid(900001), Some(vec![1, 10239, 12333, 12340]));
+        assert_eq!(tree.get_name_by_id(900002), tree.get_name_by_id(12340));
+        assert!(tree.is_descendant_taxid(900001, 12333));
+        assert_eq!(tree.get_aliases(12340), vec![900001, 900002]);
+        assert!(tree.get_aliases(12347).is_empty());
+
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").with_aliases("data/sample_tree_aliases.tsv")
+            .build().unwrap();
+        assert!(tree.contains_id(900002));
+        let subtree = tree.extract_subtree(12333).unwrap();
+        assert_eq!(subtree.get_aliases(12340), vec![900001, 900002]);
+
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_aliases.cache", std::process::id()));
+        tree.save_cache(cache_path.to_str().unwrap()).unwrap();
+        let cached_tree = NcbiFileTaxonomy::load_cache(cache_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+        assert_eq!(cached_tree.resolve_alias(900001), Some(12340));
+
+        match NcbiFileTaxonomy::from_ncbi_files_with_aliases("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                            "data/sample_tree_merged.dmp") {
+            Err(NcbiTaxonomyError::AliasFileFormatError(line)) => assert_eq!(line, "999001\t|\t12340\t|"),
+            other => panic!("expected an AliasFileFormatError, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn get_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_rank_by_id(12340), Some("species".to_string()));
+        assert_eq!(fixture.taxonomy.get_rank_by_id(12333), None);
+        assert_eq!(fixture.taxonomy.get_rank_by_id(999999999), None);
+        assert_eq!(fixture.taxonomy.get_rank_by_name("Viruses"), Some("superkingdom".to_string()));
+    }
+
+    #[test]
+    fn sqlite_get_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_rank_by_id(12340), Some("species".to_string()));
+        assert_eq!(fixture.taxonomy.get_rank_by_id(12333), None);
+        assert_eq!(fixture.taxonomy.get_rank_by_id(999999999), None);
+        assert_eq!(fixture.taxonomy.get_rank_by_name("Viruses"), Some("superkingdom".to_string()));
+    }
+
+    #[test]
+    fn sqlite_get_taxon() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let taxon = fixture.taxonomy.get_taxon(12340).unwrap();
+        assert_eq!(taxon.id, 12340);
+        assert_eq!(taxon.ancestry, Some("/1/10239/12333/12340/".to_string()));
+        assert_eq!(taxon.name, "Enterobacteria phage 933J");
+        assert_eq!(taxon.rank, "species");
+        assert_eq!(taxon.parent_id, Some(12333));
+        assert_eq!(fixture.taxonomy.get_taxon(1).unwrap().parent_id, None);
+        assert!(fixture.taxonomy.get_taxon(999999999).is_none());
+        match fixture.taxonomy.try_get_taxon(999999999) {
+            Err(NcbiTaxonomyError::NotFound(taxid)) => assert_eq!(taxid, "999999999"),
+            _ => panic!("expected NotFound")
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sqlite_taxon_serde() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let taxon = fixture.taxonomy.get_taxon(12340).unwrap();
+        let json = serde_json::to_value(&taxon).unwrap();
+        assert_eq!(json["name"], "Enterobacteria phage 933J");
+        assert_eq!(json["parent_id"], 12333);
+        assert_eq!(serde_json::from_value::<Taxon>(json).unwrap(), taxon);
+    }
+
+    #[test]
+    fn ancestors_of() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.ancestors_of(370556).unwrap().collect::<Vec<i32>>(), vec![387088, 12333, 10239, 1]);
+        assert_eq!(fixture.taxonomy.ancestors_of(1).unwrap().count(), 0);
+        assert!(fixture.taxonomy.ancestors_of(999999999).is_none());
+    }
+
+    #[test]
+    fn get_rank_enum() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(12340), Some(Rank::Species));
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(12333), Some(Rank::NoRank));
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(999999999), None);
+    }
+
+    #[test]
+    fn sqlite_get_rank_enum() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(12340), Some(Rank::Species));
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(12333), Some(Rank::NoRank));
+        assert_eq!(fixture.taxonomy.get_rank_enum_by_id(999999999), None);
+    }
+
+    #[test]
+    fn common_ancestor() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_common_ancestor_taxid(156615, 12340), Some(12333));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_taxid(156615, 156614), Some(156614));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[156615, 156616, 156617]), Some(156614));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[156615, 156616, 12340]), Some(12333));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[12340]), Some(12340));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[]), None);
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[12340, 999999999]), None);
+    }
+
+    #[test]
+    fn sqlite_common_ancestor() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_common_ancestor_taxid(156615, 12340), Some(12333));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_taxid(156615, 156614), Some(156614));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[156615, 156616, 156617]), Some(156614));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[156615, 156616, 12340]), Some(12333));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[12340]), Some(12340));
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[]), None);
+        assert_eq!(fixture.taxonomy.get_common_ancestor_of(&[12340, 999999999]), None);
+    }
+
+    #[test]
+    fn sqlite_missing_taxid() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert!(!fixture.taxonomy.is_descendant_taxid(999999999, 12333));
+        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(999999999, 12340, false), None);
+        assert_eq!(fixture.taxonomy.effective_rank(999999999), None);
+        match fixture.taxonomy.try_get_lineage_taxid(999999999) {
+            Err(NcbiTaxonomyError::NotFound(taxon)) => assert_eq!(taxon, "999999999"),
+            other => panic!("expected NotFound, got {:?}", other)
+        }
+        assert_eq!(fixture.taxonomy.try_get_name_by_id(12340).unwrap(), "Enterobacteria phage 933J");
+    }
+
+    #[test]
+    fn to_json_tree() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let tree = fixture.taxonomy.to_json_tree(387088).unwrap();
+        assert_eq!(tree.taxid, 387088);
+        assert_eq!(tree.rank, "species");
+        assert_eq!(tree.children.len(), 3);
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["children"][0]["children"], serde_json::json!([]));
+        assert!(fixture.taxonomy.to_json_tree(999999999).is_none());
+    }
+
+    #[test]
+    fn traversal_with_depth() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let count_nodes = |max_depth| fixture.taxonomy.traversal_with_depth(387088, max_depth).unwrap()
+            .filter(|node_edge| matches!(node_edge, NodeEdge::Start(_)))
+            .count();
+        assert_eq!(count_nodes(0), 1);
+        assert_eq!(count_nodes(1), 4);
+        let edges = fixture.taxonomy.traversal_with_depth(10239, 1).unwrap().collect::<Vec<_>>();
+        assert_eq!(edges.iter().filter(|node_edge| matches!(node_edge, NodeEdge::Start(_))).count(),
+                   edges.iter().filter(|node_edge| matches!(node_edge, NodeEdge::End(_))).count());
+        assert!(fixture.taxonomy.traversal_with_depth(999999999, 1).is_none());
+    }
+
+    #[test]
+    fn gzipped_dmp_files() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp.gz", "data/sample_tree_names.dmp.gz").unwrap();
+        assert_eq!(tree.id_to_node.len(), fixture.taxonomy.id_to_node.len());
+        assert_eq!(tree.name_to_node.len(), fixture.taxonomy.name_to_node.len());
+        assert_eq!(tree.get_lineage_taxid(370556), fixture.taxonomy.get_lineage_taxid(370556));
+        assert_eq!(tree.get_rank_by_id(12340), Some("species".to_string()));
+    }
+
+    #[test]
+    fn taxdump_archive() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        // names.dmp comes before nodes.dmp in this archive
+        let tree = NcbiFileTaxonomy::from_taxdump_archive("data/sample_taxdump.tar.gz").unwrap();
+        assert_eq!(tree.id_to_node.len(), fixture.taxonomy.id_to_node.len());
+        assert_eq!(tree.get_name_by_id(12340), Some("Enterobacteria phage 933J".to_string()));
+        match NcbiFileTaxonomy::from_taxdump_archive("data/sample_taxdump_no_names.tar.gz") {
+            Err(NcbiTaxonomyError::MissingArchiveMember(member, _)) => assert_eq!(member, "names.dmp"),
+            other => panic!("expected MissingArchiveMember, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn cache() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}.cache", std::process::id()));
+        let cache_filename = cache_path.to_str().unwrap();
+        fixture.taxonomy.save_cache(cache_filename).unwrap();
+        let tree = NcbiFileTaxonomy::load_cache(cache_filename).unwrap();
+        assert_eq!(tree.id_to_node, fixture.taxonomy.id_to_node);
+        assert_eq!(tree.name_to_node, fixture.taxonomy.name_to_node);
+        assert_eq!(tree.get_lineage_taxid(370556), fixture.taxonomy.get_lineage_taxid(370556));
+        assert_eq!(tree.get_rank_by_id(12340), Some("species".to_string()));
+        std::fs::remove_file(&cache_path).unwrap();
+
+        match NcbiFileTaxonomy::load_cache("data/sample_tree_nodes.dmp") {
+            Err(NcbiTaxonomyError::StaleCache(_)) => (),
+            other => panic!("expected StaleCache, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn inconsistent_cache() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_inconsistent_{}.cache", std::process::id()));
+        let cache_filename = cache_path.to_str().unwrap();
+        fixture.taxonomy.save_cache(cache_filename).unwrap();
+        let saved_contents = std::fs::read(&cache_path).unwrap();
+
+        let corruptions: Vec<fn(&mut TaxonomyCache)> = vec![
+            |cache| cache.children[0] = cache.taxids.len() as u32,
+            |cache| cache.children[1] = cache.children[0],
+            |cache| { let index = cache.child_counts.iter().position(|count| *count > 0).unwrap(); cache.children[0] = index as u32 },
+            |cache| { cache.child_counts.pop(); },
+            |cache| cache.ranks[0] = Some(cache.rank_names.len() as u16),
+            |cache| cache.name_overrides.push(("bad override".to_string(), cache.taxids.len() as u32))
+        ];
+        for corrupt in corruptions {
+            let mut cache: TaxonomyCache = bincode::deserialize(&saved_contents[CACHE_HEADER.len()..]).unwrap();
+            corrupt(&mut cache);
+            let mut contents = CACHE_HEADER.to_vec();
+            contents.extend(bincode::serialize(&cache).unwrap());
+            std::fs::write(&cache_path, contents).unwrap();
+            match NcbiFileTaxonomy::load_cache(cache_filename) {
+                Err(NcbiTaxonomyError::StaleCache(_)) => (),
+                other => panic!("expected StaleCache, got {:?}", other)
+            }
+        }
+        std::fs::remove_file(&cache_path).unwrap();
+    }
+
+    #[test]
+    fn get_children_taxids() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let mut children = fixture.taxonomy.get_children_taxids(387088).unwrap();
+        children.sort_unstable();
+        assert_eq!(children.len(), 3);
+        assert!(children.contains(&370556));
+        assert_eq!(fixture.taxonomy.get_children_taxids(1), Some(vec![10239]));
+        assert_eq!(fixture.taxonomy.get_children_taxids(370556), Some(vec![]));
+        assert_eq!(fixture.taxonomy.get_children_taxids(999999999), None);
+    }
+
+    #[test]
+    fn sqlite_get_children_taxids() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let mut expected = file_fixture.taxonomy.get_children_taxids(387088).unwrap();
+        expected.sort_unstable();
+        assert_eq!(fixture.taxonomy.get_children_taxids(387088), Some(expected));
+        assert_eq!(fixture.taxonomy.get_children_taxids(1), Some(vec![10239]));
+        assert_eq!(fixture.taxonomy.get_children_taxids(370556), Some(vec![]));
+        assert_eq!(fixture.taxonomy.get_children_taxids(999999999), None);
+    }
+
+    #[test]
+    fn get_parent_taxid() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_parent_taxid(370556), Some(387088));
+        assert_eq!(fixture.taxonomy.get_parent_taxid(10239), Some(1));
+        assert_eq!(fixture.taxonomy.get_parent_taxid(1), None);
+        assert_eq!(fixture.taxonomy.get_parent_taxid(999999999), None);
+    }
+
+    #[test]
+    fn sqlite_get_parent_taxid() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_parent_taxid(370556), Some(387088));
+        assert_eq!(fixture.taxonomy.get_parent_taxid(10239), Some(1));
+        assert_eq!(fixture.taxonomy.get_parent_taxid(1), None);
+        assert_eq!(fixture.taxonomy.get_parent_taxid(999999999), None);
+    }
 
-        match self.get_distance_to_common_ancestor_taxid(self.get_id_by_node(*taxon1).unwrap(),
-                                                   self.get_id_by_node(*taxon2).unwrap(), only_canonical) {
-            Some((distance, taxid)) => Some((distance, self.get_name_by_id(taxid).unwrap())),
-            None => None
-        }
+    #[test]
+    fn path_between_taxids() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.path_between_taxids(370556, 12340), Some(vec![370556, 387088, 12333, 12340]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(370556, 10239), Some(vec![370556, 387088, 12333, 10239]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(10239, 370556), Some(vec![10239, 12333, 387088, 370556]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(12340, 12340), Some(vec![12340]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(12340, 999999999), None);
     }
-}
 
-pub struct NcbiSqliteTaxonomy {
-    connection: SqliteConnection
-}
+    #[test]
+    fn sqlite_path_between_taxids() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.path_between_taxids(370556, 12340), Some(vec![370556, 387088, 12333, 12340]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(10239, 370556), Some(vec![10239, 12333, 387088, 370556]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(1, 10239), Some(vec![1, 10239]));
+        assert_eq!(fixture.taxonomy.path_between_taxids(12340, 999999999), None);
+    }
 
-impl NcbiSqliteTaxonomy {
-    pub fn new(db_url: Option<&str>) -> Self {
-        NcbiSqliteTaxonomy {
-            connection: establish_connection(db_url)
-        }
+    #[test]
+    fn get_depth() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_depth(1), Some(0));
+        assert_eq!(fixture.taxonomy.get_depth(10239), Some(1));
+        assert_eq!(fixture.taxonomy.get_depth(12340), Some(3));
+        assert_eq!(fixture.taxonomy.get_depth(999999999), None);
+        // only 10239 (superkingdom) is canonical above 12340
+        assert_eq!(fixture.taxonomy.get_canonical_depth(12340), Some(1));
+        assert_eq!(fixture.taxonomy.get_canonical_depth(1), Some(0));
     }
 
-    pub fn from_connection(connection: SqliteConnection) -> Self {
-        NcbiSqliteTaxonomy {
-            connection
+    #[test]
+    fn sqlite_get_depth() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        for taxid in &[1, 10239, 12340, 370556, 999999999] {
+            assert_eq!(fixture.taxonomy.get_depth(*taxid), file_fixture.taxonomy.get_depth(*taxid));
+            assert_eq!(fixture.taxonomy.get_canonical_depth(*taxid), file_fixture.taxonomy.get_canonical_depth(*taxid));
         }
     }
 
-    fn get_ancestry_for_taxid(&self, taxid: i32) -> Option<String> {
-        use schema::taxonomy::dsl::*;
-
-        let results: Vec<Option<String>> = taxonomy.filter(id.eq(taxid))
-            .select(ancestry)
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
-
-        match results.len() {
-            1 => results[0].clone(),
-            _ => panic!("taxid {} not found in taxonomy", taxid)
-        }
+    #[test]
+    fn get_descendant_taxids() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let descendants = fixture.taxonomy.get_descendant_taxids(12333).unwrap();
+        assert!(descendants.contains(&12340));
+        assert!(descendants.contains(&387088));
+        assert!(descendants.contains(&370556));
+        assert!(!descendants.contains(&12333));
+        assert_eq!(fixture.taxonomy.get_descendant_taxids(1).unwrap().len(), fixture.taxonomy.id_to_node.len() - 1);
+        assert_eq!(fixture.taxonomy.get_descendant_taxids(370556), Some(vec![]));
+        assert_eq!(fixture.taxonomy.get_descendant_taxids(999999999), None);
     }
 
-    fn get_ancestors(&self, taxid: i32) -> Vec<i32> {
-        let ancestry_string = self.get_ancestry_for_taxid(taxid);
-        match ancestry_string {
-            None => vec![], // the root taxon has no ancestry
-            Some(val) => {
-                let mut ancestors: Vec<i32> = val.split('/').map(|id_str| id_str.parse::<i32>().unwrap()).collect();
-                ancestors.reverse();
-                ancestors
-            }
+    #[test]
+    fn sqlite_get_descendant_taxids() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        for taxid in &[1, 10239, 12333] {
+            let mut expected = file_fixture.taxonomy.get_descendant_taxids(*taxid).unwrap();
+            expected.sort_unstable();
+            assert_eq!(fixture.taxonomy.get_descendant_taxids(*taxid), Some(expected));
         }
+        assert_eq!(fixture.taxonomy.get_descendant_taxids(370556), Some(vec![]));
+        assert_eq!(fixture.taxonomy.get_descendant_taxids(999999999), None);
     }
 
-    fn get_rank(&self, taxid: i32) -> Option<String> {
-        use schema::taxonomy::dsl::*;
-
-        let results: Vec<Option<String>> = taxonomy.filter(id.eq(taxid))
-            .select(rank)
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+    #[test]
+    fn sqlite_get_descendant_taxids_shared_digits() {
+        use diesel::connection::SimpleConnection;
 
-        match results.len() {
-            1 => results[0].clone(),
-            _ => panic!("taxid {} not found in taxonomy", taxid)
-        }
+        // taxids that start with (or contain) the digits of another taxid are not its descendants
+        let taxonomy = NcbiSqliteTaxonomy::new_in_memory().unwrap();
+        taxonomy.connection.batch_execute("
+            INSERT INTO taxonomy (id, ancestry, name, rank, parent_id) VALUES
+                (1, '/1/', 'root', 'no rank', NULL),
+                (12, '/1/12/', 'A', 'genus', 1),
+                (123, '/1/123/', 'B', 'genus', 1),
+                (112, '/1/112/', 'C', 'genus', 1),
+                (1234, '/1/12/1234/', 'A a', 'species', 12),
+                (1235, '/1/123/1235/', 'B b', 'species', 123),
+                (1120, '/1/112/1120/', 'C c', 'species', 112);
+        ").unwrap();
+        assert_eq!(taxonomy.get_descendant_taxids(12), Some(vec![1234]));
+        assert_eq!(taxonomy.get_descendant_taxids(123), Some(vec![1235]));
+        assert_eq!(taxonomy.get_descendant_taxids(112), Some(vec![1120]));
+        assert_eq!(taxonomy.get_descendant_taxids(1), Some(vec![12, 112, 123, 1120, 1234, 1235]));
+        assert_eq!(taxonomy.descendants_at_rank(1, "genus"), Some(vec![12, 112, 123]));
+        assert!(!taxonomy.is_descendant_taxid(1235, 12));
+        assert!(!taxonomy.is_descendant_taxid(1120, 12));
     }
-}
 
-impl NcbiTaxonomy for NcbiSqliteTaxonomy {
+    #[test]
+    fn sqlite_is_descendant_taxid_shared_digits() {
+        use diesel::connection::SimpleConnection;
 
-    fn contains_id(&self, taxid: i32) -> bool {
-        use schema::taxonomy::dsl::*;
+        // every taxid in the ancestry is surrounded by '/', so taxids that are digit prefixes (or
+        // suffixes) of each other do not match
+        let taxonomy = NcbiSqliteTaxonomy::new_in_memory().unwrap();
+        taxonomy.connection.batch_execute("
+            INSERT INTO taxonomy (id, ancestry, name, rank, parent_id) VALUES
+                (1, '/1/', 'root', 'no rank', NULL),
+                (5, '/1/5/', 'A', 'genus', 1),
+                (50, '/1/50/', 'B', 'genus', 1),
+                (150, '/1/150/', 'C', 'genus', 1),
+                (15, '/1/5/15/', 'A a', 'species', 5),
+                (25, '/1/50/25/', 'B b', 'species', 50),
+                (51, '/1/150/51/', 'C c', 'species', 150);
+        ").unwrap();
+        assert!(taxonomy.is_descendant_taxid(15, 5));
+        assert!(taxonomy.is_descendant_taxid(5, 5));
+        assert!(!taxonomy.is_descendant_taxid(25, 5));
+        assert!(!taxonomy.is_descendant_taxid(50, 5));
+        assert!(!taxonomy.is_descendant_taxid(51, 5));
+        assert!(!taxonomy.is_descendant_taxid(51, 15));
+        assert!(!taxonomy.is_descendant_taxid(150, 15));
+        assert!(taxonomy.is_descendant_taxid(51, 150));
+        assert!(taxonomy.is_descendant_taxid(51, 1));
+        assert!(!taxonomy.is_descendant_taxid(1, 5));
+        let descendants = taxonomy.are_descendants_taxid(&[1, 5, 15, 25, 50, 51, 150], 5);
+        assert_eq!(descendants.iter().filter(|(_, is_descendant)| **is_descendant).map(|(taxid, _)| *taxid).collect::<HashSet<i32>>(),
+                   [5, 15].iter().cloned().collect::<HashSet<i32>>());
+        assert_eq!(taxonomy.get_descendant_taxids(5), Some(vec![15]));
+        assert_eq!(taxonomy.get_descendant_taxids(50), Some(vec![25]));
+        assert_eq!(taxonomy.get_descendant_taxids(15), Some(vec![]));
+    }
 
-        let results: Vec<i64> = taxonomy.filter(id.eq(taxid))
-            .select(count(id))
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+    #[test]
+    fn count_descendants() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.count_descendants(1), Some(fixture.taxonomy.id_to_node.len() - 1));
+        assert_eq!(fixture.taxonomy.count_descendants(387088), Some(3));
+        assert_eq!(fixture.taxonomy.count_leaves(387088), Some(3));
+        assert_eq!(fixture.taxonomy.count_descendants(370556), Some(0));
+        assert_eq!(fixture.taxonomy.count_leaves(370556), Some(0));
+        assert_eq!(fixture.taxonomy.count_descendants(999999999), None);
+        assert_eq!(fixture.taxonomy.count_leaves(999999999), None);
 
-        results[0] == 1
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        // the leaves below 543 are the strain 83333 and the species 28901 and 2100764
+        assert_eq!(taxonomy.count_descendants(543), Some(7));
+        assert_eq!(taxonomy.count_leaves(543), Some(3));
+        for taxid in taxonomy.iter_taxids() {
+            let descendants = taxonomy.get_descendant_taxids(taxid).unwrap();
+            assert_eq!(taxonomy.count_descendants(taxid), Some(descendants.len()));
+            let leaves = descendants.iter().filter(|descendant| taxonomy.get_children_taxids(**descendant).unwrap().is_empty()).count();
+            assert_eq!(taxonomy.count_leaves(taxid), Some(leaves));
+        }
     }
 
-    fn contains_name(&self, name_str: &str) -> bool {
-        use schema::taxonomy::dsl::*;
-
-        let results: Vec<i64> = taxonomy.filter(name.eq(name_str))
-            .select(count(id))
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+    #[test]
+    fn sqlite_count_descendants() {
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        let taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap();
+        for taxid in file_taxonomy.iter_taxids() {
+            assert_eq!(taxonomy.count_descendants(taxid), file_taxonomy.count_descendants(taxid));
+            assert_eq!(taxonomy.count_leaves(taxid), file_taxonomy.count_leaves(taxid));
+        }
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        for taxid in &[1, 10239, 12333, 387088] {
+            assert_eq!(fixture.taxonomy.count_descendants(*taxid), file_fixture.taxonomy.count_descendants(*taxid));
+            assert_eq!(fixture.taxonomy.count_leaves(*taxid), file_fixture.taxonomy.count_leaves(*taxid));
+        }
+        assert_eq!(fixture.taxonomy.count_descendants(999999999), None);
+        assert_eq!(fixture.taxonomy.count_leaves(999999999), None);
+    }
 
-        results[0] == 1
+    #[test]
+    fn descendants_at_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let species = fixture.taxonomy.descendants_at_rank(12333, "species").unwrap();
+        assert!(species.contains(&12340));
+        assert!(species.contains(&387088));
+        assert!(!species.contains(&370556));
+        assert_eq!(fixture.taxonomy.descendants_at_rank(387088, "species"), Some(vec![]));
+        assert_eq!(fixture.taxonomy.descendants_at_rank(12333, "superkingdom"), Some(vec![]));
+        assert_eq!(fixture.taxonomy.descendants_at_rank(999999999, "species"), None);
     }
 
-    fn is_descendant(&self, name_str: &str, ancestor: &str) -> bool {
-        let taxid = match self.get_id_by_name(name_str) {
-            Some(val) => val,
-            None => return false
-        };
+    #[test]
+    fn sqlite_descendants_at_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let mut expected = file_fixture.taxonomy.descendants_at_rank(12333, "species").unwrap();
+        expected.sort_unstable();
+        assert_eq!(fixture.taxonomy.descendants_at_rank(12333, "species"), Some(expected));
+        assert_eq!(fixture.taxonomy.descendants_at_rank(387088, "species"), Some(vec![]));
+        assert_eq!(fixture.taxonomy.descendants_at_rank(999999999, "species"), None);
+    }
 
-        let ancestor_taxid = match self.get_id_by_name(ancestor) {
-            Some(val) => val,
-            None => return false
-        };
+    #[test]
+    fn get_ancestor_at_rank() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(370556, "species"), Some(387088));
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(370556, "superkingdom"), Some(10239));
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(10239, "superkingdom"), Some(10239));
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(370556, "family"), None);
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(999999999, "species"), None);
+    }
 
-        self.is_descendant_taxid(taxid, ancestor_taxid)
+    #[test]
+    fn sqlite_get_ancestor_at_rank() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(370556, "species"), Some(387088));
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(10239, "superkingdom"), Some(10239));
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(370556, "family"), None);
+        assert_eq!(fixture.taxonomy.get_ancestor_at_rank(999999999, "species"), None);
     }
 
-    fn is_descendant_taxid(&self, taxid: i32, ancestor_taxid: i32) -> bool {
-        use schema::taxonomy::dsl::*;
+    #[test]
+    fn lineage_rank_gaps() {
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        check_lineage_rank_gaps(&taxonomy);
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.lineage_rank_gaps(12340),
+                   vec![Rank::Kingdom, Rank::Phylum, Rank::Class, Rank::Order, Rank::Family, Rank::Genus]);
+        // the genus 3 is above the family 4, but both are in the lineage of 5
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_rank_anomaly_nodes.dmp", "data/sample_rank_anomaly_names.dmp").unwrap();
+        assert_eq!(tree.lineage_rank_gaps(5), vec![Rank::Kingdom, Rank::Phylum, Rank::Class, Rank::Order]);
+    }
 
-        // ancestor pattern is id/id/id so if ancestor_taxid is an ancestor
-        // of taxid, LIKE 'ancestor_taxid/%' OR LIKE '%/ancestor_taxid/%' OR LIKE '%/ancestor_taxid'
-        // will be true
-        let pattern1 = format!("{}/%", ancestor_taxid);
-        let pattern2 = format!("%/{}/%", ancestor_taxid);
-        let pattern3 = format!("%/{}", ancestor_taxid);
-
-        let results: Vec<i64> = taxonomy.filter(
-                id.eq(taxid).and(
-                    ancestry.like(pattern1)
-                        .or(ancestry.like(pattern2))
-                        .or(ancestry.like(pattern3))
-                ))
-            .select(count(id))
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+    #[test]
+    fn sqlite_lineage_rank_gaps() {
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        check_lineage_rank_gaps(&NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap());
+    }
 
-        match results.len() {
-            1 => true,
-            _ => false
-        }
+    fn check_lineage_rank_gaps(taxonomy: &dyn NcbiTaxonomy) {
+        // Bacteria have no kingdom in the NCBI Taxonomy
+        assert_eq!(taxonomy.lineage_rank_gaps(562), vec![Rank::Kingdom]);
+        // a strain is checked down to its species
+        assert_eq!(taxonomy.lineage_rank_gaps(83333), vec![Rank::Kingdom]);
+        // 2100764 is a species whose parent (with no rank) is in a family
+        assert_eq!(taxonomy.lineage_rank_gaps(2100764), vec![Rank::Kingdom, Rank::Genus]);
+        assert_eq!(taxonomy.lineage_rank_gaps(543), vec![Rank::Kingdom]);
+        assert_eq!(taxonomy.lineage_rank_gaps(2), vec![]);
+        // nothing is missing above taxa with no canonical ancestor
+        assert_eq!(taxonomy.lineage_rank_gaps(131567), vec![]);
+        assert_eq!(taxonomy.lineage_rank_gaps(1), vec![]);
+        assert_eq!(taxonomy.lineage_rank_gaps(999999999), vec![]);
     }
 
-    fn get_name_by_id(&self, taxid: i32) -> Option<String> {
-        use schema::taxonomy::dsl::*;
+    #[test]
+    fn custom_canonical_ranks() {
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        assert_eq!(taxonomy.canonical_ranks(), CANONICAL_RANKS.to_vec());
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 584, true), Some((4, 91347)));
+        assert_eq!(taxonomy.get_rank_distance(562, 584), Some(3));
+        let sqlite_taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&taxonomy).unwrap()
+            .with_canonical_ranks(&[Rank::Superkingdom, Rank::Family, Rank::Species]);
+        check_custom_canonical_ranks(&sqlite_taxonomy);
+        check_custom_canonical_ranks(&taxonomy.with_canonical_ranks(&[Rank::Superkingdom, Rank::Family, Rank::Species]));
 
-        let results: Vec<String> = taxonomy.filter(id.eq(taxid))
-            .select(name)
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_ranks_")
+            .canonical_ranks(&[Rank::Superkingdom, Rank::Family, Rank::Species]).build().unwrap();
+        check_custom_canonical_ranks(&tree);
+        assert_eq!(tree.extract_subtree(2).unwrap().canonical_ranks(), tree.canonical_ranks());
 
-        match results.len() {
-            1 => Some(results[0].clone()),
-            _ => None
-        }
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_canonical_ranks_{}.cache", std::process::id()));
+        let cache_filename = cache_path.to_str().unwrap();
+        tree.save_cache(cache_filename).unwrap();
+        let reloaded = NcbiFileTaxonomy::load_cache(cache_filename).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+        check_custom_canonical_ranks(&reloaded);
     }
 
-    fn get_id_by_name(&self, name_str: &str) -> Option<i32> {
-        use schema::taxonomy::dsl::*;
-
-        let results: Vec<i32> = taxonomy.filter(name.eq(name_str))
-            .select(id)
-            .load(&self.connection)
-            .expect("Error loading taxonomy");
+    fn check_custom_canonical_ranks(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.canonical_ranks(), vec![Rank::Superkingdom, Rank::Family, Rank::Species]);
+        assert!(taxonomy.is_canonical_rank(543));
+        assert!(!taxonomy.is_canonical_rank(561));
+        // only the families and the superkingdom are counted above the species
+        assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(562, 584, true), Some((3, 2)));
+        assert_eq!(taxonomy.get_canonical_depth(562), Some(2));
+        assert_eq!(taxonomy.effective_rank(91347), Some("superkingdom".to_string()));
+        assert_eq!(taxonomy.get_rank_distance(562, 584), Some(2));
+        assert_eq!(taxonomy.get_rank_distance(562, 28901), Some(1));
+        assert_eq!(taxonomy.get_canonical_lineage_by_rank(562).len(), 3);
+        assert_eq!(taxonomy.lineage_rank_gaps(562), vec![]);
+        assert_eq!(taxonomy.lineage_rank_gaps(561), vec![]);
+        assert_eq!(taxonomy.lineage_rank_gaps(817), vec![]);
+    }
 
-        match results.len() {
-            1 => Some(results[0]),
-            _ => None
-        }
+    #[test]
+    fn standard_lineage() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let lineage = fixture.taxonomy.get_standard_lineage(12340).unwrap();
+        assert_eq!(lineage.len(), 7);
+        assert_eq!(lineage[0], (Rank::Superkingdom, Some(10239)));
+        assert_eq!(lineage[1], (Rank::Phylum, None));
+        assert_eq!(lineage[6], (Rank::Species, Some(12340)));
+        assert_eq!(fixture.taxonomy.format_standard_lineage(&lineage),
+                   "k__Viruses;p__;c__;o__;f__;g__;s__Enterobacteria phage 933J");
+        assert_eq!(fixture.taxonomy.get_standard_lineage(999999999), None);
     }
 
-    fn get_lineage(&self, name: &str) -> Option<Vec<i32>> {
-        let tax_id = match self.get_id_by_name(name)  {
-            Some(val) => val,
-            None => return None
-        };
-        Some(self.get_ancestors(tax_id))
+    #[test]
+    fn sqlite_standard_lineage() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let lineage = fixture.taxonomy.get_standard_lineage(12340).unwrap();
+        assert_eq!(fixture.taxonomy.format_standard_lineage(&lineage),
+                   "k__Viruses;p__;c__;o__;f__;g__;s__Enterobacteria phage 933J");
+        assert_eq!(fixture.taxonomy.get_standard_lineage(999999999), None);
     }
 
-    fn get_distance_to_common_ancestor_taxid(&self, taxid1: i32, taxid2: i32, only_canonical: bool) -> Option<(i32, i32)> {
-        // canonical ranks (+ superkingdom) as they appear in the NCBI taxonomy database
-        let canonical_ranks = get_canonical_ranks();
+    #[test]
+    fn delnodes() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_delnodes("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                   "data/sample_tree_delnodes.dmp").unwrap();
+        assert!(tree.is_deleted(999003));
+        assert!(!tree.contains_id(999003));
+        assert!(!tree.is_deleted(999999999));
+        assert!(!tree.is_deleted(12340));
 
-        if taxid1 == taxid2 {
-            return Some((0, taxid1))
-        }
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").with_delnodes().build().unwrap();
+        assert!(tree.is_deleted(999004));
+    }
 
-        let mut ancestors_distance1 = HashMap::new();
-        let mut current_distance = 0;
-        // TODO: make rank a NON NULL column
-        let taxid1_rank = self.get_rank(taxid1)?;
-        if !only_canonical || canonical_ranks.contains(&taxid1_rank) {
-            // see comment above for why distance is 0
-            ancestors_distance1.insert(taxid1, 0);
-        }
-        for taxid in self.get_ancestors(taxid1) {
-            let current_rank = self.get_rank(taxid)?;
-            if taxid == taxid2 {
-                return Some((current_distance, taxid2))
-            }
-            if !only_canonical || canonical_ranks.contains(&current_rank) {
-                current_distance += 1;
-                ancestors_distance1.insert(taxid, current_distance);
-            }
-        }
+    #[test]
+    fn get_division() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_division(12340), Some("3".to_string()));
+        assert_eq!(fixture.taxonomy.get_division(999999999), None);
 
-        current_distance = 0;
-        for taxid in self.get_ancestors(taxid2) {
-            let current_rank = self.get_rank(taxid)?;
-            if !only_canonical || canonical_ranks.contains(&current_rank) {
-                current_distance += 1;
-                if ancestors_distance1.contains_key(&taxid) {
-                    return Some((current_distance, taxid))
-                }
-            }
-        }
-        None
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_division("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                   "data/sample_tree_division.dmp").unwrap();
+        assert_eq!(tree.get_division(12340), Some("Phages".to_string()));
+        assert_eq!(tree.get_division(10239), Some("Viruses".to_string()));
+
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").with_division().build().unwrap();
+        assert_eq!(tree.get_division(1), Some("Unassigned".to_string()));
     }
 
-    fn get_distance_to_common_ancestor(&self, name1: &str, name2: &str, only_canonical: bool) -> Option<(i32, String)> {
-        let taxid1 = match self.get_id_by_name(name1) {
-            Some(val) => val,
-            None => return None
-        };
+    #[test]
+    fn get_hosts() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_hosts(12340), None);
 
-        let taxid2 = match self.get_id_by_name(name2) {
-            Some(val) => val,
-            None => return None
-        };
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_host("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                               "data/sample_tree_host.dmp").unwrap();
+        assert_eq!(tree.get_hosts(12340), Some(vec!["bacteria".to_string()]));
+        assert_eq!(tree.get_hosts(12366), Some(vec!["archaea".to_string(), "bacteria".to_string()]));
+        assert_eq!(tree.get_hosts(12333), None);
+        assert_eq!(tree.get_hosts(999999999), None);
+        let subtree = tree.extract_subtree(12333).unwrap();
+        assert_eq!(subtree.get_hosts(370556), Some(vec!["bacteria".to_string()]));
+
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_tree_").with_host().build().unwrap();
+        assert_eq!(tree.get_hosts(12347), Some(vec!["bacteria".to_string()]));
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_hosts.cache", std::process::id()));
+        tree.save_cache(cache_path.to_str().unwrap()).unwrap();
+        let cached_tree = NcbiFileTaxonomy::load_cache(cache_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+        assert_eq!(cached_tree.get_hosts(12366), tree.get_hosts(12366));
 
-        match self.get_distance_to_common_ancestor_taxid(taxid1, taxid2, only_canonical) {
-            Some((distance, taxid)) => Some((distance, self.get_name_by_id(taxid).unwrap())),
-            None => None
+        match NcbiFileTaxonomy::from_ncbi_files_with_host("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                          "data/sample_tree_aliases.tsv") {
+            Err(NcbiTaxonomyError::HostFileFormatError(line)) => assert!(line.starts_with("# alias_taxid")),
+            other => panic!("expected a HostFileFormatError, got {:?}", other.map(|_| ()))
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{NcbiFileTaxonomy, NcbiSqliteTaxonomy, NcbiTaxonomy, NodeEdge};
+    #[test]
+    fn get_type_material() {
+        let tree = NcbiFileTaxonomy::from_ncbi_files_with_type_material("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp",
+                                                                        "data/sample_ranks_typematerial.dmp").unwrap();
+        assert_eq!(tree.get_type_material(562), Some(vec!["ATCC 11775".to_string(), "DSM 30083".to_string(), "NCTC 9001".to_string()]));
+        assert_eq!(tree.get_type_material(28901), Some(vec!["ATCC 43971".to_string()]));
+        assert_eq!(tree.get_type_material(561), None);
+        assert_eq!(tree.get_type_material(999999999), None);
 
-    pub struct NcbiFileTaxonomyFixture {
-        pub taxonomy: NcbiFileTaxonomy,
-    }
+        let tree = NcbiFileTaxonomyBuilder::new("data").prefix("sample_ranks_").with_type_material().build().unwrap();
+        assert_eq!(tree.get_type_material(584), Some(vec!["ATCC 29906".to_string()]));
+        assert_eq!(tree.extract_subtree(543).unwrap().get_type_material(562), tree.get_type_material(562));
+        assert_eq!(tree.extract_subtree(543).unwrap().get_type_material(584), None);
+        let cache_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_type_material.cache", std::process::id()));
+        tree.save_cache(cache_path.to_str().unwrap()).unwrap();
+        let cached_tree = NcbiFileTaxonomy::load_cache(cache_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+        assert_eq!(cached_tree.get_type_material(562), tree.get_type_material(562));
 
-    impl Default for NcbiFileTaxonomyFixture {
-        fn default() -> Self {
-            let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
-            Self { taxonomy: tree }
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_ranks_nodes.dmp", "data/sample_ranks_names.dmp").unwrap();
+        assert_eq!(tree.get_type_material(562), None);
+        match NcbiFileTaxonomy::from_ncbi_files_with_type_material("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp",
+                                                                   "data/sample_tree_host.dmp") {
+            Err(NcbiTaxonomyError::TypeMaterialFileFormatError(line)) => assert_eq!(line, "12340\t|\tbacteria\t|"),
+            other => panic!("expected a TypeMaterialFileFormatError, got {:?}", other.map(|_| ()))
         }
     }
 
-    pub struct NcbiSqliteTaxonomyFixture {
-        pub taxonomy: NcbiSqliteTaxonomy,
-    }
+    #[test]
+    fn get_genetic_code() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.get_genetic_code(12340), Some(11));
+        assert_eq!(fixture.taxonomy.get_mito_genetic_code(12340), Some(0));
+        assert_eq!(fixture.taxonomy.get_genetic_code(999999999), None);
 
-    impl Default for NcbiSqliteTaxonomyFixture {
-        fn default() -> Self {
-            let tree = NcbiSqliteTaxonomy::new(Some("data/ncbi_taxonomy.sqlite"));
-            Self { taxonomy: tree }
-        }
+        // only taxid, parent taxid and rank
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes_truncated.dmp", "data/sample_tree_names.dmp").unwrap();
+        assert_eq!(tree.get_rank_by_id(12340), Some("species".to_string()));
+        assert_eq!(tree.get_genetic_code(12340), None);
+        assert_eq!(tree.get_division(12340), None);
     }
 
     #[test]
-    fn contains_id() {
+    fn search_names() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert!(fixture.taxonomy.contains_id(504556));
+        let results = fixture.taxonomy.search_names("Enterobacteria phage 933", 3);
+        assert_eq!(results.len(), 3);
+        assert_eq!((results[0].0, results[0].1.as_str()), (12340, "Enterobacteria phage 933J"));
+        assert!(results[0].2 >= results[1].2 && results[1].2 >= results[2].2);
+        assert_eq!(fixture.taxonomy.search_names("viruses", 1)[0].1, "Viruses");
     }
 
     #[test]
-    fn sqlite_contains_id() {
+    fn sqlite_search_names() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert!(fixture.taxonomy.contains_id(504556));
+        let results = fixture.taxonomy.search_names("Enterobacteria phage 933", 3);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, 12340);
     }
 
     #[test]
-    fn contains_name() {
+    fn names_with_prefix() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert!(fixture.taxonomy.contains_name("Propionibacterium phage PAS7"));
-        assert!(fixture.taxonomy.contains_name("Viruses"));
-        assert!(fixture.taxonomy.contains_name("environmental samples <bacteriophages>"));
+        let names = fixture.taxonomy.names_with_prefix("enterobacteria phage 9", 10);
+        assert_eq!(names, vec![(12340, "Enterobacteria phage 933J".to_string())]);
+        let names = fixture.taxonomy.names_containing("phage", 3);
+        assert_eq!(names.len(), 3);
+        assert!(names[0].1 <= names[1].1 && names[1].1 <= names[2].1);
+        assert_eq!(fixture.taxonomy.names_containing("no such name", 3), vec![]);
     }
 
     #[test]
-    fn sqlite_contains_name() {
+    fn sqlite_names_with_prefix() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert!(fixture.taxonomy.contains_name("Propionibacterium phage PAS7"));
-        assert!(fixture.taxonomy.contains_name("Viruses"));
-        assert!(fixture.taxonomy.contains_name("environmental samples <bacteriophages>"));
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        assert_eq!(fixture.taxonomy.names_with_prefix("enterobacteria phage 9", 10),
+                   vec![(12340, "Enterobacteria phage 933J".to_string())]);
+        assert_eq!(fixture.taxonomy.names_containing("phage", 3), file_fixture.taxonomy.names_containing("phage", 3));
+        assert_eq!(fixture.taxonomy.names_containing("phage_", 3), vec![]);
     }
 
     #[test]
-    fn get_node_by_id() {
+    fn get_names_by_ids() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_node_by_id(999999999), None);
-        assert!(match fixture.taxonomy.get_node_by_id(504556) { Some(_) => true, None => false })
+        let names = fixture.taxonomy.get_names_by_ids(&[12340, 10239, 999999999]);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[&12340], "Enterobacteria phage 933J");
+        assert_eq!(names[&10239], "Viruses");
     }
 
     #[test]
-    fn get_id_by_node() {
-        let fixture = NcbiFileTaxonomyFixture::default();
-        let node_id = fixture.taxonomy.get_node_by_id(504556).unwrap();
-        assert_eq!(fixture.taxonomy.get_id_by_node(*node_id).unwrap(), 504556);
+    fn sqlite_get_names_by_ids() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        let names = fixture.taxonomy.get_names_by_ids(&[12340, 10239, 999999999]);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[&12340], "Enterobacteria phage 933J");
+        // more taxids than fit in one query
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        let taxids = (0..2000).chain(file_fixture.taxonomy.id_to_name.keys().cloned()).collect::<Vec<i32>>();
+        assert_eq!(fixture.taxonomy.get_names_by_ids(&taxids), file_fixture.taxonomy.get_names_by_ids(&taxids));
     }
 
     #[test]
-    fn get_name_by_id() {
+    fn get_ids_by_names() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_name_by_id(370556).unwrap(), "Streptococcus phage 9429.1");
+        let ids = fixture.taxonomy.get_ids_by_names(&["Enterobacteria phage 933J", "Viruses", "Unknown phage XYZ"]);
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids["Enterobacteria phage 933J"], 12340);
+        assert_eq!(ids["Viruses"], 10239);
     }
 
     #[test]
-    fn sqlite_get_name_by_id() {
+    fn sqlite_get_ids_by_names() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_name_by_id(370556).unwrap(), "Streptococcus phage 9429.1");
+        let file_fixture = NcbiFileTaxonomyFixture::default();
+        // more names than fit in one query
+        let mut names: Vec<String> = (0..2000).map(|i| format!("Unknown phage {}", i)).collect();
+        names.extend(file_fixture.taxonomy.id_to_name.values().cloned());
+        let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        let ids = fixture.taxonomy.get_ids_by_names(&names);
+        assert_eq!(ids["Enterobacteria phage 933J"], 12340);
+        assert_eq!(ids, file_fixture.taxonomy.get_ids_by_names(&names));
     }
 
     #[test]
-    fn get_lineage() {
+    fn get_ids_by_name() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_lineage("Streptococcus phage 9429.1"), Some(vec![370556,387088,12333,10239,1]));
+        assert_eq!(fixture.taxonomy.get_ids_by_name("Viruses"), vec![10239]);
+        assert_eq!(fixture.taxonomy.get_ids_by_name("environmental samples"), vec![156614]);
+        assert!(fixture.taxonomy.get_ids_by_name("Environmental samples").is_empty());
+        assert!(fixture.taxonomy.get_ids_by_name("Unknown phage XYZ").is_empty());
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_homonyms_nodes.dmp", "data/sample_homonyms_names.dmp").unwrap();
+        assert_eq!(taxonomy.get_ids_by_name("Prunella"), vec![10, 20]);
+        assert_eq!(taxonomy.get_id_by_name("Prunella"), None);
+        assert_eq!(taxonomy.get_ids_by_name("Prunella <bird>"), vec![20]);
     }
 
-    #[test]
-    fn get_lineage_sqlite() {
-        let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_lineage("Streptococcus phage 9429.1"), Some(vec![370556,387088,12333,10239,1]));
+    fn check_plain_and_unique_names(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.get_id_by_name("environmental samples <bacteriophages>"), Some(156614));
+        assert_eq!(taxonomy.get_id_by_name("environmental samples"), Some(156614));
+        assert_eq!(taxonomy.get_name_by_id(156614), Some("environmental samples <bacteriophages>".to_string()));
+        assert!(taxonomy.contains_name("environmental samples"));
+        assert_eq!(taxonomy.get_id_by_name("Environmental samples"), None);
+    }
+
+    fn check_disambiguated_names(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.get_id_by_name("Viruses <viruses>"), Some(10239));
+        assert!(taxonomy.contains_name("Viruses <viruses>"));
+        // the qualifier has to match when the taxon has one
+        assert_eq!(taxonomy.get_id_by_name("environmental samples <fungi>"), None);
+        assert_eq!(taxonomy.get_id_by_name("Unknown phage XYZ <phages>"), None);
+    }
+
+    fn check_siblings_taxids(taxonomy: &dyn NcbiTaxonomy) {
+        let mut expected = taxonomy.get_children_taxids(12333).unwrap();
+        expected.sort_unstable();
+        expected.retain(|taxid| *taxid != 12340);
+        assert_eq!(taxonomy.get_siblings_taxids(12340), Some(expected));
+        assert!(taxonomy.get_siblings_taxids(12340).unwrap().contains(&12347));
+        assert_eq!(taxonomy.get_siblings_taxids(1), Some(vec![]));
+        assert_eq!(taxonomy.get_siblings_taxids(999999999), None);
     }
 
     #[test]
-    fn traversal() {
-        let fixture = NcbiFileTaxonomyFixture::default();
-        let traversal = fixture.taxonomy.traversal(12333);
-        match traversal {
-            Some(traversal) => {
-                let mut counter = 0;
-                for node_edge in traversal {
-                    match node_edge {
-                        NodeEdge::Start(_) => counter += 1,
-                        _ => ()
-                    }
-                }
-                assert_eq!(counter, 500)
-            }
-            None => assert_eq!(true, false, "Failed to load traversal from 12333")
-        }
+    fn traversal_order() {
+        // the children are listed out of order in this nodes.dmp
+        let tree = NcbiFileTaxonomy::from_ncbi_files("data/sample_unsorted_tree_nodes.dmp", "data/sample_unsorted_tree_names.dmp").unwrap();
+        let order: Vec<i32> = tree.traversal(1).unwrap()
+            .filter_map(|node_edge| match node_edge {
+                NodeEdge::Start(node_id) => tree.get_id_by_node(node_id),
+                NodeEdge::End(_) => None
+            })
+            .collect();
+        assert_eq!(order, vec![1, 10, 20, 21, 25, 30]);
+        assert_eq!(tree.get_children_taxids(1), Some(vec![10, 20, 30]));
+        assert_eq!(tree.get_siblings_taxids(20), Some(vec![10, 30]));
     }
 
     #[test]
-    fn descendants() {
+    fn get_siblings_taxids() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert!(fixture.taxonomy.is_descendant("Propionibacterium phage PAS7", "unclassified bacterial viruses"));
+        check_siblings_taxids(&fixture.taxonomy);
     }
 
     #[test]
-    fn sqlite_descendants() {
+    fn sqlite_get_siblings_taxids() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert!(fixture.taxonomy.is_descendant("Propionibacterium phage PAS7", "unclassified bacterial viruses"));
+        check_siblings_taxids(&fixture.taxonomy);
+    }
+
+    fn check_is_leaf(taxonomy: &dyn NcbiTaxonomy) {
+        assert_eq!(taxonomy.is_leaf(12340), Some(true));
+        assert_eq!(taxonomy.is_leaf(12333), Some(false));
+        assert_eq!(taxonomy.is_leaf(1), Some(false));
+        assert_eq!(taxonomy.is_leaf(999999999), None);
     }
 
     #[test]
-    fn taxid_descendants() {
+    fn is_leaf() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert!(fixture.taxonomy.is_descendant_taxid(504556, 12333));
+        check_is_leaf(&fixture.taxonomy);
     }
 
     #[test]
-    fn sqlite_taxid_descendants() {
+    fn sqlite_is_leaf() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert!(fixture.taxonomy.is_descendant_taxid(504556, 12333));
+        check_is_leaf(&fixture.taxonomy);
     }
 
     #[test]
-    fn distance_to_common_ancestor_taxid() {
-        let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, false), Some((2, 12333)));
+    fn strip_disambiguation() {
+        assert_eq!(super::strip_disambiguation("Prunella <bird>"), "Prunella");
+        assert_eq!(super::strip_disambiguation("environmental samples <bacteriophages>"), "environmental samples");
+        assert_eq!(super::strip_disambiguation("Viruses"), "Viruses");
+        assert_eq!(super::strip_disambiguation("unfinished <qualifier"), "unfinished <qualifier");
     }
 
     #[test]
-    fn distance_to_common_ancestor_taxid_canonical() {
+    fn disambiguated_names() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, true), Some((2, 10239)));
+        check_disambiguated_names(&fixture.taxonomy);
     }
 
     #[test]
-    fn distance_to_common_ancestor() {
-        let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", false),
-                   Some((2, "unclassified bacterial viruses".to_string())));
+    fn sqlite_disambiguated_names() {
+        let fixture = NcbiSqliteTaxonomyFixture::default();
+        check_disambiguated_names(&fixture.taxonomy);
     }
 
     #[test]
-    fn distance_to_common_ancestor_canonical() {
+    fn plain_and_unique_names() {
         let fixture = NcbiFileTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", true),
-                   Some((2, "Viruses".to_string())));
+        check_plain_and_unique_names(&fixture.taxonomy);
+        let subtree = fixture.taxonomy.extract_subtree(12333).unwrap();
+        assert_eq!(subtree.get_id_by_name("environmental samples"), Some(156614));
+        // a plain name of several taxa finds none of them
+        let taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_homonyms_nodes.dmp", "data/sample_homonyms_names.dmp").unwrap();
+        assert_eq!(taxonomy.get_id_by_name("Prunella <bird>"), Some(20));
+        assert_eq!(taxonomy.get_id_by_name("Prunella"), None);
     }
 
     #[test]
-    fn sqlite_distance_to_common_ancestor_taxid() {
+    fn sqlite_plain_and_unique_names() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, false), Some((2, 12333)));
+        check_plain_and_unique_names(&fixture.taxonomy);
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_homonyms_nodes.dmp", "data/sample_homonyms_names.dmp").unwrap();
+        let taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap();
+        assert_eq!(taxonomy.get_id_by_name("Prunella <bird>"), Some(20));
+        assert_eq!(taxonomy.get_id_by_name("Prunella"), None);
     }
 
     #[test]
-    fn sqlite_distance_to_common_ancestor_taxid_canonical() {
+    fn sqlite_get_ids_by_name() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, true), Some((2, 10239)));
+        assert_eq!(fixture.taxonomy.get_ids_by_name("Viruses"), vec![10239]);
+        assert_eq!(fixture.taxonomy.get_ids_by_name("environmental samples"), vec![156614]);
+        assert!(fixture.taxonomy.get_ids_by_name("Environmental samples").is_empty());
+        assert!(fixture.taxonomy.get_ids_by_name("Unknown phage XYZ").is_empty());
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_homonyms_nodes.dmp", "data/sample_homonyms_names.dmp").unwrap();
+        let taxonomy = NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap();
+        assert_eq!(taxonomy.get_ids_by_name("Prunella"), vec![10, 20]);
+        assert_eq!(taxonomy.get_ids_by_name("Prunella <bird>"), vec![20]);
     }
 
     #[test]
-    fn sqlite_distance_to_common_ancestor() {
-        let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", false),
-                   Some((2, "unclassified bacterial viruses".to_string())));
+    fn are_descendants_taxid() {
+        let fixture = NcbiFileTaxonomyFixture::default();
+        let descendants = fixture.taxonomy.are_descendants_taxid(&[504556, 12340, 10239, 999999999], 12333);
+        assert!(descendants[&504556]);
+        assert!(descendants[&12340]);
+        assert!(!descendants[&10239]);
+        assert!(!descendants[&999999999]);
     }
 
     #[test]
-    fn sqlite_distance_to_common_ancestor_canonical() {
+    fn sqlite_are_descendants_taxid() {
         let fixture = NcbiSqliteTaxonomyFixture::default();
-        assert_eq!(fixture.taxonomy.get_distance_to_common_ancestor("Cyanophage clone GS2601", "Enterobacteria phage 933J", true),
-                   Some((2, "Viruses".to_string())));
+        let descendants = fixture.taxonomy.are_descendants_taxid(&[504556, 12340, 10239, 999999999], 12333);
+        assert!(descendants[&504556]);
+        assert!(descendants[&12340]);
+        assert!(!descendants[&10239]);
+        assert!(!descendants[&999999999]);
+        // 123 is a substring of 12333 but not one of its ancestors
+        assert!(!fixture.taxonomy.is_descendant_taxid(12340, 123));
+        assert!(fixture.taxonomy.is_descendant_taxid(1, 1));
+    }
+
+    #[test]
+    fn sqlite_cache_size() {
+        for cache_size in &[0, 1, DEFAULT_SQLITE_CACHE_SIZE] {
+            let taxonomy = NcbiSqliteTaxonomyFixture::default().taxonomy.with_cache_size(*cache_size);
+            // repeat the lookups so that the second round is answered from the cache (if any)
+            for _ in 0..2 {
+                assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, false), Some((2, 12333)));
+                assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(156615, 12340, true), Some((2, 10239)));
+                assert_eq!(taxonomy.get_rank_by_id(12340), Some("species".to_string()));
+            }
+        }
     }
 }
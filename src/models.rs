@@ -1,11 +1,17 @@
 use super::schema::taxonomy;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-#[derive(Queryable)]
+/// A row of the taxonomy table
+#[derive(Queryable, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Taxon {
     pub id: i32,
     pub ancestry: Option<String>,
     pub name: String,
-    pub rank: Option<String>
+    pub rank: String,
+    pub parent_id: Option<i32>,
+    pub common_name: Option<String>
 }
 
 #[derive(Insertable)]
@@ -14,5 +20,7 @@ pub struct NewTaxon<'a> {
     pub id: &'a i32,
     pub ancestry: Option<&'a str>,
     pub name: &'a str,
-    pub rank: Option<&'a str>
+    pub rank: &'a str,
+    pub parent_id: Option<i32>,
+    pub common_name: Option<&'a str>
 }
\ No newline at end of file
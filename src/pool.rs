@@ -0,0 +1,101 @@
+//! A SQLite taxonomy that can be shared between threads, using a pool of connections
+//! (needs the r2d2 feature)
+
+use std::collections::HashMap;
+
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::sqlite::SqliteConnection;
+
+use {try_database_url, NcbiSqliteTaxonomy, NcbiTaxonomy, Rank, ToSqliteError, CANONICAL_RANKS};
+
+/// A NcbiTaxonomy backed by a pool of SQLite connections
+///
+/// NcbiSqliteTaxonomy owns a single connection and caches taxa in a RefCell, so it can be moved
+/// to another thread (it is Send) but not shared between threads (it is not Sync). NcbiSqlitePool
+/// is both Send and Sync, so it can be shared (e.g. in an Arc) by the threads of a web service.
+/// each NcbiTaxonomy method checks a connection out of the pool for the duration of the call,
+/// blocking if all the connections are in use. unlike NcbiSqliteTaxonomy it does not cache taxa
+/// between calls, and it panics if no connection can be checked out
+pub struct NcbiSqlitePool {
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+    canonical_ranks: Vec<Rank>
+}
+
+impl NcbiSqlitePool {
+    /// new
+    ///
+    /// create a pool (with the default r2d2 settings, i.e. up to 10 connections) for the SQLite
+    /// database at db_url (or DATABASE_URL if db_url is None)
+    pub fn new(db_url: Option<&str>) -> Result<Self, ToSqliteError> {
+        let pool = Pool::new(ConnectionManager::new(try_database_url(db_url)?))?;
+        Ok(NcbiSqlitePool::from_pool(pool))
+    }
+
+    /// from_pool
+    ///
+    /// use an already configured pool, e.g. one built with a different maximum size
+    pub fn from_pool(pool: Pool<ConnectionManager<SqliteConnection>>) -> Self {
+        NcbiSqlitePool { pool, canonical_ranks: CANONICAL_RANKS.to_vec() }
+    }
+
+    /// with_canonical_ranks
+    ///
+    /// use another set of canonical ranks, see NcbiFileTaxonomy::with_canonical_ranks
+    pub fn with_canonical_ranks(mut self, canonical_ranks: &[Rank]) -> Self {
+        self.canonical_ranks = canonical_ranks.to_vec();
+        self
+    }
+
+    /// pool
+    ///
+    /// the underlying connection pool
+    pub fn pool(&self) -> &Pool<ConnectionManager<SqliteConnection>> {
+        &self.pool
+    }
+
+    // a taxonomy holding a connection checked out of the pool, which is returned when it is dropped
+    fn taxonomy(&self) -> NcbiSqliteTaxonomy {
+        NcbiSqliteTaxonomy::from_pooled_connection(self.pool.get().expect("Error getting a connection from the pool"), &self.canonical_ranks)
+    }
+}
+
+impl NcbiTaxonomy for NcbiSqlitePool {
+    delegate_ncbi_taxonomy!(taxonomy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+    use NcbiFileTaxonomy;
+
+    #[test]
+    fn pool_shared_between_threads() {
+        let db_path = env::temp_dir().join(format!("ncbitaxonomy_test_{}_pool.sqlite", std::process::id()));
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
+        drop(file_taxonomy.save_to_sqlite(Some(db_path.to_str().unwrap())).unwrap());
+
+        let taxonomy = Arc::new(NcbiSqlitePool::new(Some(db_path.to_str().unwrap())).unwrap());
+        assert_eq!(taxonomy.len(), file_taxonomy.len());
+        assert_eq!(taxonomy.iter_taxids().count(), file_taxonomy.len());
+        let handles = (0..4).map(|_| {
+            let taxonomy = Arc::clone(&taxonomy);
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    assert_eq!(taxonomy.get_lineage_taxid(12340), Some(vec![1, 10239, 12333, 12340]));
+                    assert_eq!(taxonomy.get_name_by_id(12340), Some("Enterobacteria phage 933J".to_string()));
+                    assert_eq!(taxonomy.get_distance_to_common_ancestor_taxid(12340, 12347, false), Some((2, 12333)));
+                    assert!(!taxonomy.contains_id(999999999));
+                }
+            })
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(taxonomy);
+        fs::remove_file(&db_path).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! Queries on the taxonomy table that do not depend on the database backend
+//!
+//! The ancestry of a taxon is stored as the taxids from the root down to the taxon itself,
+//! separated and surrounded by '/' (e.g. "/1/10239/12333/12340/", and "/1/" for the root). A
+//! taxid in the ancestry is always written as "/taxid/", so the descendants of a taxon are the
+//! rows whose ancestry matches the LIKE pattern "%/taxid/%", which works on SQLite as well as
+//! PostgreSQL (with the postgres feature the queries can be loaded from a PgConnection) and
+//! cannot match a taxid that only shares some digits with the ancestor.
+
+use std::num::ParseIntError;
+
+use diesel::backend::Backend;
+use diesel::prelude::*;
+use diesel::dsl::count_star;
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+
+use models::NewTaxon;
+use schema::taxonomy;
+
+/// format_ancestry
+///
+/// the ancestry of a taxon, from the taxids on the path from the root down to the taxon
+pub fn format_ancestry(lineage: &[i32]) -> String {
+    format!("/{}/", lineage.iter().map(|taxid| taxid.to_string()).collect::<Vec<String>>().join("/"))
+}
+
+/// parse_ancestry
+///
+/// the taxids in an ancestry, from the root down to the taxon itself (empty if the taxon has no
+/// ancestry). an ancestry without the surrounding '/' (as written before they were added) is
+/// read as well
+pub fn parse_ancestry(ancestry: Option<&str>) -> Result<Vec<i32>, ParseIntError> {
+    match ancestry.map(|ancestry| ancestry.trim_matches('/')) {
+        Some(ancestry) if !ancestry.is_empty() => ancestry.split('/').map(|taxid| taxid.parse::<i32>()).collect(),
+        _ => Ok(vec![])
+    }
+}
+
+/// ancestry_pattern
+///
+/// the LIKE pattern that matches the ancestry of a taxon and all its descendants. taxids only
+/// contain digits, so the pattern needs no escaping
+pub fn ancestry_pattern(ancestor_taxid: i32) -> String {
+    format!("%/{}/%", ancestor_taxid)
+}
+
+/// descendant_ids
+///
+/// a query for the taxids of all the descendants (not including the taxon itself) of a taxon,
+/// sorted
+pub fn descendant_ids<'a, DB: Backend>(ancestor_taxid: i32) -> taxonomy::BoxedQuery<'a, DB, Integer> {
+    taxonomy::table.filter(taxonomy::ancestry.like(ancestry_pattern(ancestor_taxid))
+            .and(taxonomy::id.ne(ancestor_taxid)))
+        .select(taxonomy::id)
+        .order(taxonomy::id)
+        .into_boxed()
+}
+
+/// descendant_ids_at_rank
+///
+/// a query for the taxids of the descendants of a taxon that have a given rank, sorted
+pub fn descendant_ids_at_rank<'a, DB: Backend + 'a>(ancestor_taxid: i32, rank: &'a str) -> taxonomy::BoxedQuery<'a, DB, Integer> {
+    descendant_ids(ancestor_taxid).filter(taxonomy::rank.eq(rank))
+}
+
+/// descendant_count
+///
+/// a query for the number of descendants (not including the taxon itself) of a taxon
+pub fn descendant_count<'a, DB: Backend>(ancestor_taxid: i32) -> taxonomy::BoxedQuery<'a, DB, BigInt> {
+    taxonomy::table.filter(taxonomy::ancestry.like(ancestry_pattern(ancestor_taxid))
+            .and(taxonomy::id.ne(ancestor_taxid)))
+        .select(count_star())
+        .into_boxed()
+}
+
+/// leaf_descendant_count
+///
+/// a query for the number of descendants of a taxon that are leaves, i.e. are not the parent of
+/// any taxon
+pub fn leaf_descendant_count<'a, DB: Backend + 'a>(ancestor_taxid: i32) -> taxonomy::BoxedQuery<'a, DB, BigInt> {
+    let parent_ids = taxonomy::table.select(taxonomy::parent_id).filter(taxonomy::parent_id.is_not_null()).into_boxed();
+    taxonomy::table.filter(taxonomy::ancestry.like(ancestry_pattern(ancestor_taxid))
+            .and(taxonomy::id.ne(ancestor_taxid))
+            .and(taxonomy::id.nullable().ne_all(parent_ids)))
+        .select(count_star())
+        .into_boxed()
+}
+
+/// descendant_ids_among
+///
+/// a query for the taxids in taxids that are ancestor_taxid itself or one of its descendants
+pub fn descendant_ids_among<'a, DB: Backend>(taxids: &'a [i32], ancestor_taxid: i32) -> taxonomy::BoxedQuery<'a, DB, Integer> {
+    taxonomy::table.filter(taxonomy::id.eq_any(taxids)
+            .and(taxonomy::ancestry.like(ancestry_pattern(ancestor_taxid))))
+        .select(taxonomy::id)
+        .into_boxed()
+}
+
+/// the number of columns of a row inserted by insert_taxa, i.e. the number of bind parameters
+/// it uses for each taxon
+pub const NEW_TAXON_COLUMNS: usize = 6;
+
+/// InsertTaxa
+///
+/// a query that inserts rows into the taxonomy table with a single multi-row INSERT statement,
+/// see insert_taxa
+pub struct InsertTaxa<'a> {
+    taxa: &'a [NewTaxon<'a>]
+}
+
+/// insert_taxa
+///
+/// a query that inserts taxa into the taxonomy table with a single statement. diesel (1.x) runs
+/// a separate INSERT for each row of a batch on SQLite, which makes saving millions of taxa slow.
+/// the statement has NEW_TAXON_COLUMNS bind parameters for each taxon, so the number of taxa must
+/// stay within the limit of the database (999 parameters for older SQLite versions)
+pub fn insert_taxa<'a>(taxa: &'a [NewTaxon<'a>]) -> InsertTaxa<'a> {
+    InsertTaxa { taxa }
+}
+
+impl<'a> QueryId for InsertTaxa<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, DB: Backend> QueryFragment<DB> for InsertTaxa<'a> {
+    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+        out.push_sql("INSERT INTO ");
+        out.push_identifier("taxonomy")?;
+        out.push_sql(" (");
+        for (i, column) in ["id", "ancestry", "name", "rank", "parent_id", "common_name"].iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_identifier(column)?;
+        }
+        out.push_sql(") VALUES ");
+        for (i, taxon) in self.taxa.iter().enumerate() {
+            out.push_sql(if i > 0 { ", (" } else { "(" });
+            out.push_bind_param::<Integer, _>(taxon.id)?;
+            out.push_sql(", ");
+            out.push_bind_param::<Nullable<Text>, _>(&taxon.ancestry)?;
+            out.push_sql(", ");
+            out.push_bind_param::<Text, _>(&taxon.name)?;
+            out.push_sql(", ");
+            out.push_bind_param::<Text, _>(&taxon.rank)?;
+            out.push_sql(", ");
+            out.push_bind_param::<Nullable<Integer>, _>(&taxon.parent_id)?;
+            out.push_sql(", ");
+            out.push_bind_param::<Nullable<Text>, _>(&taxon.common_name)?;
+            out.push_sql(")");
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for InsertTaxa<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::debug_query;
+    use diesel::sqlite::Sqlite;
+
+    #[test]
+    fn ancestries() {
+        assert_eq!(format_ancestry(&[1, 10239, 12333, 12340]), "/1/10239/12333/12340/");
+        assert_eq!(format_ancestry(&[1]), "/1/");
+        assert_eq!(parse_ancestry(Some("/1/10239/12333/12340/")).unwrap(), vec![1, 10239, 12333, 12340]);
+        assert_eq!(parse_ancestry(Some("/1/")).unwrap(), vec![1]);
+        assert_eq!(parse_ancestry(Some("1/10239")).unwrap(), vec![1, 10239]);
+        assert!(parse_ancestry(None).unwrap().is_empty());
+        assert!(parse_ancestry(Some("/1/x/")).is_err());
+        assert_eq!(ancestry_pattern(12333), "%/12333/%");
+    }
+
+    #[test]
+    fn sqlite_queries() {
+        let query = descendant_ids::<Sqlite>(12333);
+        assert_eq!(debug_query(&query).to_string(),
+                   "SELECT `taxonomy`.`id` FROM `taxonomy` WHERE `taxonomy`.`ancestry` LIKE ? AND `taxonomy`.`id` != ? ORDER BY `taxonomy`.`id` -- binds: [\"%/12333/%\", 12333]");
+        let query = descendant_ids_at_rank::<Sqlite>(1, "species");
+        assert_eq!(debug_query(&query).to_string(),
+                   "SELECT `taxonomy`.`id` FROM `taxonomy` WHERE `taxonomy`.`ancestry` LIKE ? AND `taxonomy`.`id` != ? AND `taxonomy`.`rank` = ? ORDER BY `taxonomy`.`id` -- binds: [\"%/1/%\", 1, \"species\"]");
+        let taxa = [NewTaxon { id: &1, ancestry: Some("/1/"), name: "root", rank: "no rank", parent_id: None, common_name: None },
+                    NewTaxon { id: &2, ancestry: Some("/1/2/"), name: "Bacteria", rank: "superkingdom", parent_id: Some(1), common_name: Some("eubacteria") }];
+        let query = insert_taxa(&taxa);
+        assert_eq!(debug_query::<Sqlite, _>(&query).to_string(),
+                   "INSERT INTO `taxonomy` (`id`, `ancestry`, `name`, `rank`, `parent_id`, `common_name`) VALUES (?, ?, ?, ?, ?, ?), (?, ?, ?, ?, ?, ?) -- binds: [1, Some(\"/1/\"), \"root\", \"no rank\", None, None, 2, Some(\"/1/2/\"), \"Bacteria\", \"superkingdom\", Some(1), Some(\"eubacteria\")]");
+        let query = leaf_descendant_count::<Sqlite>(12333);
+        assert_eq!(debug_query(&query).to_string(),
+                   "SELECT COUNT(*) FROM `taxonomy` WHERE `taxonomy`.`ancestry` LIKE ? AND `taxonomy`.`id` != ? AND `taxonomy`.`id` NOT IN (SELECT `taxonomy`.`parent_id` FROM `taxonomy` WHERE `taxonomy`.`parent_id` IS NOT NULL) -- binds: [\"%/12333/%\", 12333]");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_queries() {
+        use diesel::pg::Pg;
+
+        let query = descendant_ids_at_rank::<Pg>(12333, "species");
+        assert_eq!(debug_query(&query).to_string(),
+                   "SELECT \"taxonomy\".\"id\" FROM \"taxonomy\" WHERE \"taxonomy\".\"ancestry\" LIKE $1 AND \"taxonomy\".\"id\" != $2 AND \"taxonomy\".\"rank\" = $3 ORDER BY \"taxonomy\".\"id\" -- binds: [\"%/12333/%\", 12333, \"species\"]");
+    }
+}
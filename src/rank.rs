@@ -0,0 +1,205 @@
+//! Taxonomic ranks as used in the NCBI Taxonomy database
+
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// A taxonomic rank. The ranks used by NCBI are listed from least to most specific,
+/// any other rank string is kept as NonStandard
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Rank {
+    Superkingdom,
+    Kingdom,
+    Subkingdom,
+    Superphylum,
+    Phylum,
+    Subphylum,
+    Superclass,
+    Class,
+    Subclass,
+    Infraclass,
+    Cohort,
+    Subcohort,
+    Superorder,
+    Order,
+    Suborder,
+    Infraorder,
+    Parvorder,
+    Superfamily,
+    Family,
+    Subfamily,
+    Tribe,
+    Subtribe,
+    Genus,
+    Subgenus,
+    Section,
+    Subsection,
+    Series,
+    SpeciesGroup,
+    SpeciesSubgroup,
+    Species,
+    Subspecies,
+    Varietas,
+    Forma,
+    Strain,
+    Clade,
+    NoRank,
+    NonStandard(String)
+}
+
+// (rank, name in the NCBI Taxonomy database) for all the ranks with a fixed name
+const RANK_NAMES: [(Rank, &str); 36] = [
+    (Rank::Superkingdom, "superkingdom"),
+    (Rank::Kingdom, "kingdom"),
+    (Rank::Subkingdom, "subkingdom"),
+    (Rank::Superphylum, "superphylum"),
+    (Rank::Phylum, "phylum"),
+    (Rank::Subphylum, "subphylum"),
+    (Rank::Superclass, "superclass"),
+    (Rank::Class, "class"),
+    (Rank::Subclass, "subclass"),
+    (Rank::Infraclass, "infraclass"),
+    (Rank::Cohort, "cohort"),
+    (Rank::Subcohort, "subcohort"),
+    (Rank::Superorder, "superorder"),
+    (Rank::Order, "order"),
+    (Rank::Suborder, "suborder"),
+    (Rank::Infraorder, "infraorder"),
+    (Rank::Parvorder, "parvorder"),
+    (Rank::Superfamily, "superfamily"),
+    (Rank::Family, "family"),
+    (Rank::Subfamily, "subfamily"),
+    (Rank::Tribe, "tribe"),
+    (Rank::Subtribe, "subtribe"),
+    (Rank::Genus, "genus"),
+    (Rank::Subgenus, "subgenus"),
+    (Rank::Section, "section"),
+    (Rank::Subsection, "subsection"),
+    (Rank::Series, "series"),
+    (Rank::SpeciesGroup, "species group"),
+    (Rank::SpeciesSubgroup, "species subgroup"),
+    (Rank::Species, "species"),
+    (Rank::Subspecies, "subspecies"),
+    (Rank::Varietas, "varietas"),
+    (Rank::Forma, "forma"),
+    (Rank::Strain, "strain"),
+    (Rank::Clade, "clade"),
+    (Rank::NoRank, "no rank")
+];
+
+/// The canonical ranks (and superkingdom), from least to most specific
+pub const CANONICAL_RANKS: [Rank; 8] = [Rank::Superkingdom, Rank::Kingdom, Rank::Phylum, Rank::Class,
+    Rank::Order, Rank::Family, Rank::Genus, Rank::Species];
+
+/// The ranks of the standard 7 rank lineages used by amplicon pipelines (e.g. QIIME and mothur),
+/// where superkingdom takes the place of kingdom
+pub const STANDARD_RANKS: [Rank; 7] = [Rank::Superkingdom, Rank::Phylum, Rank::Class, Rank::Order,
+    Rank::Family, Rank::Genus, Rank::Species];
+
+impl Rank {
+    /// is_canonical
+    ///
+    /// check whether this is one of the CANONICAL_RANKS (superkingdom, kingdom, phylum, class,
+    /// order, family, genus or species). taxonomies can use another set, see
+    /// NcbiTaxonomy::canonical_ranks
+    pub fn is_canonical(&self) -> bool {
+        CANONICAL_RANKS.contains(self)
+    }
+
+    /// canonical_level
+    ///
+    /// the position of a canonical rank from superkingdom (0) to species (7), None for other ranks
+    pub fn canonical_level(&self) -> Option<usize> {
+        CANONICAL_RANKS.iter().position(|rank| rank == self)
+    }
+
+    /// standard_prefix
+    ///
+    /// the prefix used for this rank in standard lineage strings (e.g. "f" for family, used as
+    /// "f__"), None for ranks that are not one of the STANDARD_RANKS
+    pub fn standard_prefix(&self) -> Option<&'static str> {
+        match *self {
+            Rank::Superkingdom => Some("k"),
+            Rank::Phylum => Some("p"),
+            Rank::Class => Some("c"),
+            Rank::Order => Some("o"),
+            Rank::Family => Some("f"),
+            Rank::Genus => Some("g"),
+            Rank::Species => Some("s"),
+            _ => None
+        }
+    }
+
+    /// level
+    ///
+    /// the position of a rank in the NCBI rank hierarchy, from superkingdom (0) downwards.
+    /// ranks that are not part of the hierarchy (strain, clade, no rank and non-standard
+    /// ranks) have no level. a more specific rank has a higher level
+    pub fn level(&self) -> Option<usize> {
+        match *self {
+            Rank::Strain | Rank::Clade | Rank::NoRank | Rank::NonStandard(_) => None,
+            _ => RANK_NAMES.iter().position(|(rank, _)| rank == self)
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Rank {
+    fn from(name: &'a str) -> Self {
+        match RANK_NAMES.iter().find(|(_, rank_name)| *rank_name == name) {
+            Some((rank, _)) => rank.clone(),
+            None => Rank::NonStandard(name.to_string())
+        }
+    }
+}
+
+impl FromStr for Rank {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(Rank::from(name))
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Rank::NonStandard(ref name) => write!(f, "{}", name),
+            _ => {
+                let (_, name) = RANK_NAMES.iter().find(|(rank, _)| rank == self).unwrap();
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rank;
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!("species".parse::<Rank>().unwrap(), Rank::Species);
+        assert_eq!(Rank::from("species group"), Rank::SpeciesGroup);
+        assert_eq!(Rank::from("no rank"), Rank::NoRank);
+        assert_eq!(Rank::from("biotype"), Rank::NonStandard("biotype".to_string()));
+        assert_eq!(Rank::SpeciesGroup.to_string(), "species group");
+        assert_eq!(Rank::NonStandard("biotype".to_string()).to_string(), "biotype");
+    }
+
+    #[test]
+    fn canonical() {
+        assert!(Rank::Species.is_canonical());
+        assert!(Rank::Superkingdom.is_canonical());
+        assert!(!Rank::Subspecies.is_canonical());
+        assert!(!Rank::NoRank.is_canonical());
+        assert_eq!(Rank::Genus.canonical_level(), Some(6));
+        assert_eq!(Rank::Subgenus.canonical_level(), None);
+    }
+
+    #[test]
+    fn level() {
+        assert!(Rank::Family.level() < Rank::Subfamily.level());
+        assert!(Rank::Genus.level() < Rank::Species.level());
+        assert_eq!(Rank::NoRank.level(), None);
+    }
+}
@@ -3,6 +3,8 @@ table! {
         id -> Integer,
         ancestry -> Nullable<Text>,
         name -> Text,
-        rank -> Nullable<Text>,
+        rank -> Text,
+        parent_id -> Nullable<Integer>,
+        common_name -> Nullable<Text>,
     }
 }
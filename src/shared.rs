@@ -0,0 +1,76 @@
+//! A SQLite taxonomy that can be shared between threads, by locking a single connection
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use {NcbiSqliteTaxonomy, NcbiTaxonomy, Rank};
+
+/// A NcbiSqliteTaxonomy behind a Mutex
+///
+/// NcbiSqliteTaxonomy is Send but not Sync (neither its SqliteConnection nor its cache can be
+/// used from two threads at once), so a single instance cannot be shared between threads.
+/// SharedNcbiSqliteTaxonomy is Sync, so it can be shared (e.g. in an Arc) by threads that filter
+/// several files in parallel. each NcbiTaxonomy method locks the taxonomy for the duration of
+/// the call, so the queries of different threads run one at a time (but share the cache of taxa).
+/// NcbiSqlitePool (with the r2d2 feature) runs queries in parallel on several connections instead
+pub struct SharedNcbiSqliteTaxonomy {
+    taxonomy: Mutex<NcbiSqliteTaxonomy>
+}
+
+impl SharedNcbiSqliteTaxonomy {
+    pub fn new(taxonomy: NcbiSqliteTaxonomy) -> Self {
+        SharedNcbiSqliteTaxonomy { taxonomy: Mutex::new(taxonomy) }
+    }
+
+    /// into_inner
+    ///
+    /// get the NcbiSqliteTaxonomy back, e.g. once the threads sharing it are done
+    pub fn into_inner(self) -> NcbiSqliteTaxonomy {
+        self.taxonomy.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // lock the taxonomy until the returned guard is dropped. a thread that panicked while holding
+    // the lock (e.g. on a database error) leaves the taxonomy usable, so the poisoning is ignored
+    fn taxonomy(&self) -> MutexGuard<'_, NcbiSqliteTaxonomy> {
+        self.taxonomy.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl NcbiTaxonomy for SharedNcbiSqliteTaxonomy {
+    delegate_ncbi_taxonomy!(taxonomy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use NcbiFileTaxonomy;
+
+    #[test]
+    fn shared_between_threads() {
+        let file_taxonomy = NcbiFileTaxonomy::from_ncbi_files("data/sample_tree_nodes.dmp", "data/sample_tree_names.dmp").unwrap();
+        let taxonomy = Arc::new(SharedNcbiSqliteTaxonomy::new(NcbiSqliteTaxonomy::from_file_taxonomy(&file_taxonomy).unwrap()));
+        assert_eq!(taxonomy.len(), file_taxonomy.len());
+        let handles = (0..4).map(|_| {
+            let taxonomy = Arc::clone(&taxonomy);
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    assert_eq!(taxonomy.get_lineage_taxid(12340), Some(vec![1, 10239, 12333, 12340]));
+                    assert_eq!(taxonomy.get_name_by_id(12340), Some("Enterobacteria phage 933J".to_string()));
+                    assert!(taxonomy.is_descendant_taxid(12340, 10239));
+                    assert!(!taxonomy.contains_id(999999999));
+                }
+            })
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // usable as a trait object like the other taxonomies
+        let shared: &dyn NcbiTaxonomy = &*taxonomy;
+        assert_eq!(shared.get_parent_taxid(12340), Some(12333));
+        let taxonomy = Arc::try_unwrap(taxonomy).ok().unwrap().into_inner();
+        assert_eq!(taxonomy.get_name_by_id(10239), Some("Viruses".to_string()));
+    }
+}
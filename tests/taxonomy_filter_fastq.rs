@@ -0,0 +1,39 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn output_dir() {
+    let output_dir = env::temp_dir().join(format!("ncbitaxonomy_filter_fastq_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&output_dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_taxonomy_filter_fastq"))
+        .args(["--db", "data/ncbi_taxonomy.sqlite", "--ancestor_taxid", "10239", "--kraken2",
+                "--tax_report_filename", "data/sample_kraken2.txt", "data/sample_reads.fastq"])
+        .arg("--output_dir").arg(&output_dir)
+        .status()
+        .expect("failed to run taxonomy_filter_fastq");
+    assert!(status.success());
+    let filtered = fs::read_to_string(output_dir.join("sample_reads.filtered.fastq")).unwrap();
+    fs::remove_dir_all(&output_dir).unwrap();
+    // read5 is unclassified
+    assert_eq!(filtered.lines().filter(|line| line.starts_with('@')).count(), 4);
+    assert!(!filtered.contains("@read5"));
+}
+
+#[test]
+fn rejected() {
+    let output_dir = env::temp_dir().join(format!("ncbitaxonomy_filter_fastq_rejected_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&output_dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_taxonomy_filter_fastq"))
+        .args(["--db", "data/ncbi_taxonomy.sqlite", "--ancestor_taxid", "10239", "--kraken2", "--rejected",
+               "--tax_report_filename", "data/sample_kraken2.txt", "data/sample_reads.fastq"])
+        .arg("--output_dir").arg(&output_dir)
+        .status()
+        .expect("failed to run taxonomy_filter_fastq");
+    assert!(status.success());
+    let filtered = fs::read_to_string(output_dir.join("sample_reads.filtered.fastq")).unwrap();
+    let rejected = fs::read_to_string(output_dir.join("sample_reads.rejected.fastq")).unwrap();
+    fs::remove_dir_all(&output_dir).unwrap();
+    assert!(!filtered.contains("@read5"));
+    assert_eq!(rejected, "@read5\nACGTACGT\n+\nIIIIIIII\n");
+}
@@ -0,0 +1,19 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn ancestor_taxid_with_output() {
+    let output = env::temp_dir().join(format!("ncbitaxonomy_filter_refseq_{}.fasta", std::process::id()));
+    let _ = fs::remove_file(&output);
+    let status = Command::new(env!("CARGO_BIN_EXE_taxonomy_filter_refseq"))
+        .args(["--db", "data/ncbi_taxonomy.sqlite", "-A", "10239", "data/sample_refseq.fasta"])
+        .arg("-o").arg(&output)
+        .status()
+        .expect("failed to run taxonomy_filter_refseq");
+    assert!(status.success());
+    let filtered = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(filtered.lines().filter(|line| line.starts_with('>')).count(), 6);
+    assert!(filtered.contains(">YP_044791.1 RepB"));
+}